@@ -0,0 +1,269 @@
+use std::ops::{Index, IndexMut};
+
+use super::{Map, JSON};
+
+// What json["missing"] and json[99] give back
+static NUL: JSON = JSON::Nul;
+
+// json["results"][0]["id"] reads like in other JSON crates: a missing key, an
+// index out of range or a value of the wrong type all give JSON::Nul, so a
+// chain never panics.
+impl Index<&str> for JSON {
+    type Output = JSON;
+
+    fn index(&self, key: &str) -> &JSON {
+        match self {
+            JSON::Obj(obj) => obj.get(key).unwrap_or(&NUL),
+            _ => &NUL,
+        }
+    }
+}
+
+impl Index<usize> for JSON {
+    type Output = JSON;
+
+    fn index(&self, i: usize) -> &JSON {
+        match self {
+            JSON::Lst(lst) => lst.get(i).unwrap_or(&NUL),
+            _ => &NUL,
+        }
+    }
+}
+
+// Writing through json["a"]["b"] = ... makes what is missing: a null becomes
+// an empty object and a missing key is inserted as null. Panics when the
+// value is not an object or null.
+impl IndexMut<&str> for JSON {
+    fn index_mut(&mut self, key: &str) -> &mut JSON {
+        if let JSON::Nul = self {
+            *self = JSON::Obj(Map::new());
+        }
+        match self {
+            JSON::Obj(obj) => {
+                if !obj.contains_key(key) {
+                    obj.insert(key.to_string(), JSON::Nul);
+                }
+                obj.get_mut(key).expect("The key was just inserted")
+            }
+            _ => panic!("Cannot index {} with the key {key:?}", kind(self)),
+        }
+    }
+}
+
+// Same for lists: a null becomes an empty list and the index right after the
+// end appends a null. Panics when the value is not a list or null, or when
+// the index is further out, since padding could ask for any amount of memory.
+impl IndexMut<usize> for JSON {
+    fn index_mut(&mut self, i: usize) -> &mut JSON {
+        if let JSON::Nul = self {
+            *self = JSON::Lst(Vec::new());
+        }
+        match self {
+            JSON::Lst(lst) => {
+                if i == lst.len() {
+                    lst.push(JSON::Nul);
+                }
+                let len = lst.len();
+                match lst.get_mut(i) {
+                    Some(json) => json,
+                    None => panic!("Cannot index a list of length {len} with the index {i}"),
+                }
+            }
+            _ => panic!("Cannot index {} with the index {i}", kind(self)),
+        }
+    }
+}
+
+fn kind(json: &JSON) -> &'static str {
+    match json {
+        JSON::Int(_) | JSON::Flt(_) | JSON::Num(_) => "a number",
+        JSON::Str(_) => "a string",
+        JSON::Lst(_) => "a list",
+        JSON::Obj(_) => "an object",
+        JSON::Bol(_) => "a boolean",
+        JSON::Nul => "null",
+    }
+}
+
+// Typed access without a match. None when the value is of another type.
+impl JSON {
+    // Any number with an integer value in the i64 range, so 1E2 and 100.0
+    // give 100 alike. The other floats are not truncated.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            JSON::Int(i) => Some(*i),
+            // -2^63 <= f < 2^63, the range where the cast is exact
+            JSON::Flt(f)
+                if f.fract() == 0.0 && *f >= i64::MIN as f64 && *f < -(i64::MIN as f64) =>
+            {
+                Some(*f as i64)
+            }
+            JSON::Num(n) => n.as_i64(),
+            _ => None,
+        }
+    }
+
+    // Any number, rounded to the nearest f64 if need be
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JSON::Int(i) => Some(*i as f64),
+            JSON::Flt(f) => Some(*f),
+            JSON::Num(n) => n.as_f64(),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JSON::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JSON::Bol(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&Vec<JSON>> {
+        match self {
+            JSON::Lst(lst) => Some(lst),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&Map<String, JSON>> {
+        match self {
+            JSON::Obj(obj) => Some(obj),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, JSON::Nul)
+    }
+
+    // The _mut ones only give the value as it is stored: as_i64_mut() is for
+    // an Int and as_f64_mut() for a Flt
+    pub fn as_i64_mut(&mut self) -> Option<&mut i64> {
+        match self {
+            JSON::Int(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64_mut(&mut self) -> Option<&mut f64> {
+        match self {
+            JSON::Flt(f) => Some(f),
+            _ => None,
+        }
+    }
+
+    pub fn as_str_mut(&mut self) -> Option<&mut String> {
+        match self {
+            JSON::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool_mut(&mut self) -> Option<&mut bool> {
+        match self {
+            JSON::Bol(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_list_mut(&mut self) -> Option<&mut Vec<JSON>> {
+        match self {
+            JSON::Lst(lst) => Some(lst),
+            _ => None,
+        }
+    }
+
+    pub fn as_object_mut(&mut self) -> Option<&mut Map<String, JSON>> {
+        match self {
+            JSON::Obj(obj) => Some(obj),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse;
+    use crate::JSON;
+
+    #[test]
+    fn test_index() {
+        let mut json = parse(
+            r#"{"results": [{"id": 7, "name": "a", "ok": true, "score": 0.5},
+                {"id": 18446744073709551616}], "none": null}"#,
+        )
+        .unwrap();
+        assert_eq!(json["results"][0]["id"].as_i64(), Some(7));
+        assert_eq!(json["results"][0]["name"].as_str(), Some("a"));
+        assert_eq!(json["results"][0]["ok"].as_bool(), Some(true));
+        assert_eq!(json["results"][0]["score"].as_f64(), Some(0.5));
+        assert_eq!(json["results"][0]["score"].as_i64(), None);
+        assert_eq!(json["results"][1]["id"].as_i64(), None);
+        // The same value gives the same answer, however it was written
+        for (src, int) in [("1E2", Some(100)), ("1.0e0", Some(1)), ("100.0", Some(100))] {
+            assert_eq!(parse(src).unwrap().as_i64(), int, "{src}");
+        }
+        for (src, int) in [
+            ("-0.0", Some(0)),
+            ("9007199254740993.0", Some(9007199254740993)),
+        ] {
+            assert_eq!(parse(src).unwrap().as_i64(), int, "{src}");
+        }
+        for src in ["1.5", "1e19", "-1e19", "1e400"] {
+            assert_eq!(parse(src).unwrap().as_i64(), None, "{src}");
+        }
+        assert_eq!(
+            json["results"][1]["id"].as_f64(),
+            Some(18446744073709551616.0)
+        );
+        assert_eq!(json["results"].as_list().map(Vec::len), Some(2));
+        assert_eq!(json.as_object().map(|obj| obj.len()), Some(2));
+        assert!(json["none"].is_null() && json["nope"].is_null());
+        assert!(json["results"][5]["id"].is_null() && json["results"]["id"].is_null());
+        assert!(json["results"][0]["id"][0].is_null());
+
+        if let Some(name) = json["results"][0]["name"].as_str_mut() {
+            name.push('!');
+        }
+        *json["results"][0]["id"].as_i64_mut().unwrap() += 1;
+        *json["results"][0]["ok"].as_bool_mut().unwrap() = false;
+        json["results"].as_list_mut().unwrap().pop();
+        json.as_object_mut().unwrap().remove("none");
+        assert_eq!(json["results"][0]["name"].as_str(), Some("a!"));
+        assert_eq!(json["results"][0]["id"].as_i64(), Some(8));
+        assert_eq!(json["results"][0]["ok"].as_bool(), Some(false));
+        assert!(json["results"][1].is_null() && json["none"].is_null());
+
+        // Auto-vivification
+        json["new"]["deep"][0] = JSON::Nul;
+        json["new"]["deep"][1] = JSON::Str("x".into());
+        json["new"]["deep"][0] = JSON::Int(1);
+        json["results"][0]["id"] = JSON::Nul;
+        assert!(matches!(json["new"]["deep"].as_list().map(|l| &l[..]),
+            Some([JSON::Int(1), JSON::Str(x)]) if x == "x"));
+        assert!(json["results"][0]["id"].is_null());
+        let mut nul = JSON::Nul;
+        nul[0]["a"] = JSON::Int(1);
+        assert_eq!(nul[0]["a"].as_i64(), Some(1));
+
+        let mut int = JSON::Int(1);
+        let caught = std::panic::catch_unwind(move || int["a"] = JSON::Nul);
+        assert!(caught.is_err());
+        // Only the next index appends, anything further out panics
+        let mut lst = JSON::Lst(vec![JSON::Nul]);
+        let caught = std::panic::catch_unwind(move || lst[2] = JSON::Nul);
+        assert!(caught.is_err());
+        let mut lst = JSON::Lst(Vec::new());
+        let caught = std::panic::catch_unwind(move || lst[usize::MAX] = JSON::Nul);
+        assert!(caught.is_err());
+    }
+}
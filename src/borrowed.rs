@@ -0,0 +1,168 @@
+use std::borrow::Cow;
+
+use super::{
+    pack_list, pack_object, pack_val, take_root, Fail, Map, Node, Number, ParseErr, ParseOptions,
+    Pda, Preb, SeenKeys, Sink, Text, JSON,
+};
+
+// Same as JSON but the strings point into the parsed input when they have no
+// escape, only the escaped ones are owned
+#[derive(Debug)]
+pub enum JsonRef<'a> {
+    Int(i64),
+    Flt(f64),
+    Num(Number),
+    Str(Cow<'a, str>),
+    Lst(Vec<JsonRef<'a>>),
+    Obj(Map<Cow<'a, str>, JsonRef<'a>>),
+    Bol(bool),
+    Nul,
+}
+
+impl JsonRef<'_> {
+    // Copy everything which is still borrowed
+    pub fn into_owned(self) -> JSON {
+        match self {
+            JsonRef::Int(i) => JSON::Int(i),
+            JsonRef::Flt(f) => JSON::Flt(f),
+            JsonRef::Num(n) => JSON::Num(n),
+            JsonRef::Str(s) => JSON::Str(s.into_owned()),
+            JsonRef::Lst(lst) => JSON::Lst(lst.into_iter().map(JsonRef::into_owned).collect()),
+            JsonRef::Obj(obj) => JSON::Obj(
+                obj.into_iter()
+                    .map(|(k, v)| (k.into_owned(), v.into_owned()))
+                    .collect(),
+            ),
+            JsonRef::Bol(b) => JSON::Bol(b),
+            JsonRef::Nul => JSON::Nul,
+        }
+    }
+}
+
+impl<'a> Node for JsonRef<'a> {
+    type Key = Cow<'a, str>;
+    fn obj(obj: Map<Cow<'a, str>, JsonRef<'a>>) -> JsonRef<'a> {
+        JsonRef::Obj(obj)
+    }
+    fn lst(lst: Vec<JsonRef<'a>>) -> JsonRef<'a> {
+        JsonRef::Lst(lst)
+    }
+}
+
+// Same as the TreeBuilder of parse() but the strings are taken from the input
+struct RefBuilder<'a> {
+    src: &'a str,
+    mem: Vec<Preb<JsonRef<'a>>>,
+    keys: SeenKeys<Cow<'a, str>>,
+}
+
+impl Sink for RefBuilder<'_> {
+    fn bgn_obj(&mut self) -> Result<(), Fail> {
+        self.keys.open();
+        self.mem.push(Preb::BgnObj);
+        Ok(())
+    }
+    fn end_obj(&mut self) -> Result<(), Fail> {
+        self.keys.close();
+        pack_object(&mut self.mem, self.keys.dup)?;
+        pack_val(&mut self.mem)
+    }
+    fn bgn_lst(&mut self) -> Result<(), Fail> {
+        self.mem.push(Preb::BgnLst);
+        Ok(())
+    }
+    fn end_lst(&mut self) -> Result<(), Fail> {
+        pack_list(&mut self.mem)?;
+        pack_val(&mut self.mem)
+    }
+    fn key_text(&mut self, key: Text) -> Result<(), Fail> {
+        let key = key.into_cow(self.src);
+        self.keys.check(&key)?;
+        self.mem.push(Preb::Key(key));
+        Ok(())
+    }
+    fn str_text(&mut self, val: Text) -> Result<(), Fail> {
+        self.mem
+            .push(Preb::Val(JsonRef::Str(val.into_cow(self.src))));
+        pack_val(&mut self.mem)
+    }
+    fn prim(&mut self, val: JSON) -> Result<(), Fail> {
+        let val = match val {
+            JSON::Int(i) => JsonRef::Int(i),
+            JSON::Flt(f) => JsonRef::Flt(f),
+            JSON::Num(n) => JsonRef::Num(n),
+            JSON::Bol(b) => JsonRef::Bol(b),
+            _ => JsonRef::Nul,
+        };
+        self.mem.push(Preb::Val(val));
+        pack_val(&mut self.mem)
+    }
+}
+
+// Parse without copying the strings which have no escape
+pub fn parse_borrowed(json_str: &str) -> Result<JsonRef<'_>, ParseErr> {
+    ParseOptions::default().parse_borrowed(json_str)
+}
+
+impl ParseOptions {
+    // See parse_borrowed()
+    pub fn parse_borrowed<'a>(&self, json_str: &'a str) -> Result<JsonRef<'a>, ParseErr> {
+        let mut pda = Pda::new(self);
+        pda.lazy = true;
+        let mut tree = RefBuilder {
+            src: json_str,
+            mem: Vec::new(),
+            keys: SeenKeys::new(self.duplicate_keys),
+        };
+        for ch in json_str.chars() {
+            pda.step(ch, &mut tree)?;
+        }
+        pda.end(&mut tree)?;
+        take_root(&mut tree.mem).map_err(|fail| pda.locate(fail))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_borrowed() {
+        let src = r#"{"plain": "héllo", "esc\naped": ["a\"b", "xé"], "n": [1, 2.5, true, null]}"#;
+        let json = parse_borrowed(src).unwrap();
+        let JsonRef::Obj(obj) = &json else {
+            panic!("Expected an object");
+        };
+        for (k, _) in obj.iter() {
+            match k.as_ref() {
+                "plain" | "n" => assert!(matches!(k, Cow::Borrowed(_))),
+                _ => assert!(matches!(k, Cow::Owned(_))),
+            }
+        }
+        assert!(matches!(
+            obj.get("plain"),
+            Some(JsonRef::Str(Cow::Borrowed("héllo")))
+        ));
+        match obj.get("esc\naped") {
+            Some(JsonRef::Lst(lst)) => {
+                assert!(matches!(&lst[0], JsonRef::Str(Cow::Owned(s)) if s == "a\"b"));
+                assert!(matches!(&lst[1], JsonRef::Str(Cow::Borrowed("xé"))));
+            }
+            _ => panic!("Expected a list"),
+        }
+
+        match json.into_owned() {
+            JSON::Obj(obj) => {
+                assert!(matches!(obj.get("plain"), Some(JSON::Str(s)) if s == "héllo"));
+                assert!(matches!(obj.get("n"), Some(JSON::Lst(l)) if l.len() == 4));
+            }
+            _ => panic!("Expected an object"),
+        }
+
+        assert!(matches!(
+            parse_borrowed("\"\""),
+            Ok(JsonRef::Str(Cow::Borrowed("")))
+        ));
+        assert!(parse_borrowed("[\"a\\qb\"]").is_err());
+    }
+}
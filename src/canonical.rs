@@ -0,0 +1,166 @@
+use super::number::format_es;
+use super::ser::{write_escaped, MAX_DEPTH};
+use super::sha256::sha256;
+use super::JSON;
+
+// The JSON Canonicalization Scheme of RFC 8785 (JCS): no whitespace, the keys
+// sorted by their UTF-16 code units, numbers written like ECMAScript does and
+// strings with the fewest escapes. The same tree always gives the same bytes,
+// to sign or hash them.
+//
+// JCS numbers are IEEE 754 doubles, so an Int or a Num which an f64 can't hold
+// exactly is rounded like JavaScript would do. Infinity and NaN are errors.
+pub fn to_canonical_string(json: &JSON) -> Result<String, &'static str> {
+    let mut out = String::new();
+    canonical(&mut out, json, 0)?;
+    Ok(out)
+}
+
+// SHA-256 of the UTF-8 bytes of to_canonical_string()
+pub fn canonical_hash(json: &JSON) -> Result<[u8; 32], &'static str> {
+    to_canonical_string(json).map(|canon| sha256(canon.as_bytes()))
+}
+
+fn canonical(out: &mut String, json: &JSON, lvl: usize) -> Result<(), &'static str> {
+    if lvl >= MAX_DEPTH {
+        return Err("The JSON is nested too deep to be written");
+    }
+    match json {
+        JSON::Int(i) => out.push_str(&format_es(*i as f64)),
+        JSON::Flt(f) if f.is_finite() => out.push_str(&format_es(*f)),
+        JSON::Flt(_) => return Err("Infinity and NaN can't be written as JSON numbers"),
+        JSON::Num(n) => {
+            let f = n
+                .as_f64()
+                .ok_or("The number is out of the range of JCS numbers")?;
+            out.push_str(&format_es(f));
+        }
+        // Writing to a String can't fail
+        JSON::Str(s) => {
+            let _ = write_escaped(out, s, false);
+        }
+        JSON::Lst(lst) => {
+            out.push('[');
+            for (i, item) in lst.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                canonical(out, item, lvl + 1)?;
+            }
+            out.push(']');
+        }
+        JSON::Obj(obj) => {
+            let mut entries: Vec<_> = obj.iter().collect();
+            entries.sort_by(|a, b| a.0.encode_utf16().cmp(b.0.encode_utf16()));
+            out.push('{');
+            for (i, (k, v)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                let _ = write_escaped(out, k, false);
+                out.push(':');
+                canonical(out, v, lvl + 1)?;
+            }
+            out.push('}');
+        }
+        JSON::Bol(b) => out.push_str(if *b { "true" } else { "false" }),
+        JSON::Nul => out.push_str("null"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn test_canonical() {
+        // The examples of RFC 8785, section 3.2.2 and 3.2.3
+        let src = r#"{
+  "numbers": [333333333.33333329, 1E30, 4.50,
+              2e-3, 0.000000000000000000000000001],
+  "string": "\u20ac$\u000F\u000aA'\u0042\u0022\u005c\\\"\/",
+  "literals": [null, true, false]
+}"#;
+        let canon = to_canonical_string(&parse(src).unwrap()).unwrap();
+        assert_eq!(
+            canon,
+            r#"{"literals":[null,true,false],"numbers":[333333333.3333333,1e+30,4.5,0.002,1e-27],"string":"€$\u000f\nA'B\"\\\\\"/"}"#
+        );
+
+        let src = r#"{
+  "€": "Euro Sign",
+  "\r": "Carriage Return",
+  "דּ": "Hebrew Letter Dalet With Dagesh",
+  "1": "One",
+  "😀": "Emoji: Grinning Face",
+  "\u0080": "Control",
+  "ö": "Latin Small Letter O With Diaeresis"
+}"#;
+        let canon = to_canonical_string(&parse(src).unwrap()).unwrap();
+        let values: Vec<_> = canon
+            .split(":\"")
+            .skip(1)
+            .map(|s| &s[..s.find('"').unwrap()])
+            .collect();
+        assert_eq!(
+            values,
+            [
+                "Carriage Return",
+                "One",
+                "Control",
+                "Latin Small Letter O With Diaeresis",
+                "Euro Sign",
+                "Emoji: Grinning Face",
+                "Hebrew Letter Dalet With Dagesh"
+            ]
+        );
+
+        // The numbers of RFC 8785 Appendix B
+        let numbers = [
+            (0x0000000000000000, "0"),
+            (0x8000000000000000, "0"),
+            (0x0000000000000001, "5e-324"),
+            (0x8000000000000001, "-5e-324"),
+            (0x7fefffffffffffff, "1.7976931348623157e+308"),
+            (0xffefffffffffffff, "-1.7976931348623157e+308"),
+            (0x4340000000000000, "9007199254740992"),
+            (0xc340000000000000, "-9007199254740992"),
+            (0x4430000000000000, "295147905179352830000"),
+            (0x44b52d02c7e14af5, "9.999999999999997e+22"),
+            (0x44b52d02c7e14af6, "1e+23"),
+            (0x44b52d02c7e14af7, "1.0000000000000001e+23"),
+            (0x444b1ae4d6e2ef4e, "999999999999999700000"),
+            (0x444b1ae4d6e2ef4f, "999999999999999900000"),
+            (0x444b1ae4d6e2ef50, "1e+21"),
+            (0x3eb0c6f7a0b5ed8c, "9.999999999999997e-7"),
+            (0x3eb0c6f7a0b5ed8d, "0.000001"),
+            (0x41b3de4355555553, "333333333.3333332"),
+            (0x41b3de4355555554, "333333333.33333325"),
+            (0x41b3de4355555555, "333333333.3333333"),
+            (0x41b3de4355555556, "333333333.3333334"),
+            (0x41b3de4355555557, "333333333.33333343"),
+            (0xbecbf647612f3696, "-0.0000033333333333333333"),
+            (0x43143ff3c1cb0959, "1424953923781206.2"),
+        ];
+        for (bits, text) in numbers {
+            let flt = JSON::Flt(f64::from_bits(bits));
+            assert_eq!(to_canonical_string(&flt).unwrap(), text, "{bits:#x}");
+        }
+        for bits in [0x7fffffffffffffffu64, 0x7ff0000000000000] {
+            assert!(to_canonical_string(&JSON::Flt(f64::from_bits(bits))).is_err());
+        }
+        assert_eq!(to_canonical_string(&JSON::Int(-42)).unwrap(), "-42");
+
+        // The same tree whatever the order or the layout of the source
+        let a = canonical_hash(&parse("{\"b\": [1, 2.0], \"a\": \"x\"}").unwrap()).unwrap();
+        let b = canonical_hash(&parse("{\"a\":\"x\",\"b\":[1.0,2]}").unwrap()).unwrap();
+        assert_eq!(a, b);
+        let hex: String = a.iter().map(|b| format!("{b:02x}")).collect();
+        assert_eq!(
+            hex,
+            "721ef82f2d6c0997bffb7a8ab3f40f8fb45b0b52ce2af3afa6b0f05efbdc317f"
+        );
+    }
+}
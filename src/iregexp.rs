@@ -0,0 +1,454 @@
+// I-Regexp (RFC 9485), the regular expressions of the match() and search()
+// functions of JSONPath. Compiled to a little program for a Pike VM, so the
+// time is linear in the text whatever the pattern.
+//
+// Limits: the n and m of {n,m} go up to 1000 and the whole program up to
+// 10000 instructions once the counted repetitions are unrolled. Past them a
+// valid I-Regexp like a{1001} or (a{100}){200} is refused like an invalid
+// one, and match() and search() give false for it.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+use super::categories::category;
+
+// Counted repetitions are unrolled, these keep the programs small
+const MAX_INSTS: usize = 10_000;
+const MAX_REPEAT: u32 = 1000; // The n and m of {n,m}
+
+#[derive(Debug)]
+pub(crate) struct Regex {
+    prog: Vec<Inst>,
+}
+
+#[derive(Debug)]
+enum Inst {
+    Char(Class),
+    Split(usize, usize), // Try both
+    Jmp(usize),
+    Match,
+}
+
+#[derive(Debug, Clone)]
+enum Class {
+    Lit(char),
+    Dot, // Anything but '\n' and '\r'
+    Cat(bool, Cat),
+    Set(bool, Vec<Item>), // [...], true for [^...]
+}
+
+#[derive(Debug, Clone)]
+enum Item {
+    Range(char, char),
+    Cat(bool, Cat),
+}
+
+// A Unicode general category, or a group of them like L
+#[derive(Debug, Clone, Copy)]
+struct Cat {
+    major: char,
+    minor: Option<char>,
+}
+
+enum Node {
+    Class(Class),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat(Box<Node>, u32, Option<u32>),
+}
+
+impl Regex {
+    // None when the pattern is not an I-Regexp
+    pub(crate) fn new(pattern: &str) -> Option<Regex> {
+        let mut p = Parser {
+            chars: pattern.chars().peekable(),
+            depth: 0,
+        };
+        let node = p.alt()?;
+        if p.chars.next().is_some() {
+            return None; // An unmatched ')'
+        }
+        let mut prog = Vec::new();
+        compile(&node, &mut prog)?;
+        prog.push(Inst::Match);
+        Some(Regex { prog })
+    }
+
+    // The whole text matches, for match()
+    pub(crate) fn is_match(&self, text: &str) -> bool {
+        self.run(text, true)
+    }
+
+    // Some substring matches, for search()
+    pub(crate) fn is_found(&self, text: &str) -> bool {
+        self.run(text, false)
+    }
+
+    fn run(&self, text: &str, whole: bool) -> bool {
+        let mut clist = Vec::new();
+        let mut nlist = Vec::new();
+        let mut seen = vec![usize::MAX; self.prog.len()];
+        let mut step = 0;
+        self.add(&mut clist, &mut seen, step, 0);
+        for ch in text.chars() {
+            if !whole && clist.iter().any(|pc| matches!(self.prog[*pc], Inst::Match)) {
+                return true;
+            }
+            step += 1;
+            for pc in clist.drain(..) {
+                if let Inst::Char(class) = &self.prog[pc] {
+                    if class.matches(ch) {
+                        self.add(&mut nlist, &mut seen, step, pc + 1);
+                    }
+                }
+            }
+            if !whole {
+                // A new match can begin at each character
+                self.add(&mut nlist, &mut seen, step, 0);
+            }
+            std::mem::swap(&mut clist, &mut nlist);
+        }
+        clist.iter().any(|pc| matches!(self.prog[*pc], Inst::Match))
+    }
+
+    // Follow the jumps and splits from pc, seen keeps each one once a step
+    fn add(&self, list: &mut Vec<usize>, seen: &mut [usize], step: usize, pc: usize) {
+        let mut todo = vec![pc];
+        while let Some(pc) = todo.pop() {
+            if seen[pc] == step {
+                continue;
+            }
+            seen[pc] = step;
+            match self.prog[pc] {
+                Inst::Jmp(to) => todo.push(to),
+                Inst::Split(a, b) => {
+                    todo.push(b);
+                    todo.push(a);
+                }
+                _ => list.push(pc),
+            }
+        }
+    }
+}
+
+fn compile(node: &Node, prog: &mut Vec<Inst>) -> Option<()> {
+    if prog.len() > MAX_INSTS {
+        return None;
+    }
+    match node {
+        Node::Class(class) => prog.push(Inst::Char(class.clone())),
+        Node::Concat(nodes) => {
+            for node in nodes {
+                compile(node, prog)?;
+            }
+        }
+        Node::Alt(nodes) => {
+            // Split(a, Split(b, c)) with a jump to the end after each branch
+            let mut jumps = Vec::new();
+            for (i, node) in nodes.iter().enumerate() {
+                if i + 1 < nodes.len() {
+                    let split = prog.len();
+                    prog.push(Inst::Split(split + 1, 0));
+                    compile(node, prog)?;
+                    jumps.push(prog.len());
+                    prog.push(Inst::Jmp(0));
+                    prog[split] = Inst::Split(split + 1, prog.len());
+                } else {
+                    compile(node, prog)?;
+                }
+            }
+            let end = prog.len();
+            for jump in jumps {
+                prog[jump] = Inst::Jmp(end);
+            }
+        }
+        Node::Repeat(node, min, max) => {
+            // Something empty like () is as empty repeated, and each copy of
+            // the others adds to prog, so MAX_INSTS bounds the loops
+            if is_empty(node) {
+                return Some(());
+            }
+            for _ in 0..*min {
+                compile(node, prog)?;
+            }
+            match max {
+                None => {
+                    let split = prog.len();
+                    prog.push(Inst::Split(split + 1, 0));
+                    compile(node, prog)?;
+                    prog.push(Inst::Jmp(split));
+                    prog[split] = Inst::Split(split + 1, prog.len());
+                }
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(prog.len());
+                        prog.push(Inst::Split(prog.len() + 1, 0));
+                        compile(node, prog)?;
+                    }
+                    let end = prog.len();
+                    for split in splits {
+                        prog[split] = Inst::Split(split + 1, end);
+                    }
+                }
+            }
+        }
+    }
+    (prog.len() <= MAX_INSTS).then_some(())
+}
+
+// True when the node compiles to no instruction at all
+fn is_empty(node: &Node) -> bool {
+    match node {
+        Node::Class(_) => false,
+        Node::Concat(nodes) | Node::Alt(nodes) => nodes.iter().all(is_empty),
+        Node::Repeat(_, _, Some(0)) => true,
+        Node::Repeat(node, _, _) => is_empty(node),
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn alt(&mut self) -> Option<Node> {
+        let mut branches = vec![self.branch()?];
+        while self.chars.next_if_eq(&'|').is_some() {
+            branches.push(self.branch()?);
+        }
+        Some(match branches.len() {
+            1 => branches.pop()?,
+            _ => Node::Alt(branches),
+        })
+    }
+
+    fn branch(&mut self) -> Option<Node> {
+        let mut pieces = Vec::new();
+        while let Some(&ch) = self.chars.peek() {
+            if ch == '|' || ch == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            pieces.push(self.quantifier(atom)?);
+        }
+        Some(Node::Concat(pieces))
+    }
+
+    fn atom(&mut self) -> Option<Node> {
+        let class = match self.chars.next()? {
+            '(' => {
+                self.depth += 1;
+                if self.depth > 100 {
+                    return None;
+                }
+                let node = self.alt()?;
+                self.chars.next_if_eq(&')')?;
+                self.depth -= 1;
+                return Some(node);
+            }
+            '.' => Class::Dot,
+            '[' => self.set()?,
+            '\\' => match self.escape()? {
+                Item::Range(ch, _) => Class::Lit(ch),
+                Item::Cat(neg, cat) => Class::Cat(neg, cat),
+            },
+            ')' | '*' | '+' | '?' | ']' | '{' | '|' | '}' => return None,
+            ch => Class::Lit(ch),
+        };
+        Some(Node::Class(class))
+    }
+
+    fn quantifier(&mut self, atom: Node) -> Option<Node> {
+        let (min, max) = match self.chars.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                self.chars.next();
+                let min = self.number()?;
+                let max = match self.chars.next()? {
+                    '}' => Some(min),
+                    ',' if self.chars.next_if_eq(&'}').is_some() => None,
+                    ',' => {
+                        let max = self.number()?;
+                        self.chars.next_if_eq(&'}')?;
+                        Some(max)
+                    }
+                    _ => return None,
+                };
+                if min > MAX_REPEAT || max.is_some_and(|max| max < min || max > MAX_REPEAT) {
+                    return None;
+                }
+                return Some(Node::Repeat(Box::new(atom), min, max));
+            }
+            _ => return Some(atom),
+        };
+        self.chars.next();
+        Some(Node::Repeat(Box::new(atom), min, max))
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let mut num = String::new();
+        while let Some(digit) = self.chars.next_if(char::is_ascii_digit) {
+            num.push(digit);
+        }
+        num.parse().ok()
+    }
+
+    // After '\': a single character escape or \p{..} and \P{..}
+    fn escape(&mut self) -> Option<Item> {
+        let ch = match self.chars.next()? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            ch @ ('(' | ')' | '*' | '+' | '-' | '.' | '?' | '[' | '\\' | ']' | '^' | '{' | '|'
+            | '}') => ch,
+            p @ ('p' | 'P') => {
+                self.chars.next_if_eq(&'{')?;
+                let major = self.chars.next_if(|c| "LMNPZSC".contains(*c))?;
+                let minors = match major {
+                    'L' => "lmotu",
+                    'M' => "cen",
+                    'N' => "dlo",
+                    'P' => "cdefios",
+                    'Z' => "lps",
+                    'S' => "ckmo",
+                    _ => "cfno",
+                };
+                let minor = self.chars.next_if(|c| minors.contains(*c));
+                self.chars.next_if_eq(&'}')?;
+                return Some(Item::Cat(p == 'P', Cat { major, minor }));
+            }
+            _ => return None,
+        };
+        Some(Item::Range(ch, ch))
+    }
+
+    // After '[': the items up to ']'. A '-' is itself at the ends only.
+    fn set(&mut self) -> Option<Class> {
+        let neg = self.chars.next_if_eq(&'^').is_some();
+        let mut items = Vec::new();
+        if self.chars.next_if_eq(&'-').is_some() {
+            items.push(Item::Range('-', '-'));
+        }
+        loop {
+            let item = match self.chars.next()? {
+                ']' if !items.is_empty() => break,
+                '-' if self.chars.next_if_eq(&']').is_some() => {
+                    items.push(Item::Range('-', '-'));
+                    break;
+                }
+                '\\' => self.escape()?,
+                '[' | ']' | '-' => return None,
+                ch => Item::Range(ch, ch),
+            };
+            let Item::Range(from, _) = item else {
+                items.push(item);
+                continue;
+            };
+            // A range like a-z, unless the '-' is the last one before ']'
+            let mut ahead = self.chars.clone();
+            if ahead.next() == Some('-') && ahead.peek().is_some_and(|c| *c != ']') {
+                self.chars.next();
+                let to = match self.chars.next()? {
+                    '\\' => match self.escape()? {
+                        Item::Range(to, _) => to,
+                        Item::Cat(..) => return None,
+                    },
+                    '[' | ']' | '-' => return None,
+                    to => to,
+                };
+                if to < from {
+                    return None;
+                }
+                items.push(Item::Range(from, to));
+            } else {
+                items.push(item);
+            }
+        }
+        Some(Class::Set(neg, items))
+    }
+}
+
+impl Class {
+    fn matches(&self, ch: char) -> bool {
+        match self {
+            Class::Lit(lit) => ch == *lit,
+            Class::Dot => ch != '\n' && ch != '\r',
+            Class::Cat(neg, cat) => cat.has(ch) != *neg,
+            Class::Set(neg, items) => {
+                let found = items.iter().any(|item| match item {
+                    Item::Range(from, to) => (*from..=*to).contains(&ch),
+                    Item::Cat(neg, cat) => cat.has(ch) != *neg,
+                });
+                found != *neg
+            }
+        }
+    }
+}
+
+impl Cat {
+    fn has(&self, ch: char) -> bool {
+        let (major, minor) = category(ch);
+        major == self.major && self.minor.is_none_or(|m| m == minor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iregexp() {
+        let is_match = |re: &str, text: &str| Regex::new(re).unwrap().is_match(text);
+        let is_found = |re: &str, text: &str| Regex::new(re).unwrap().is_found(text);
+        assert!(is_match("a.c", "abc") && !is_match("a.c", "a\nc"));
+        assert!(is_match("(ab|cd)+e?", "abcdab") && !is_match("(ab|cd)+", "abc"));
+        assert!(is_match("[a-c-]{2,3}", "b-a") && !is_match("[a-c]{2,3}", "abca"));
+        assert!(is_match("[^0-9]*", "abc") && !is_match("[^0-9]*", "a1"));
+        assert!(is_match("x{2}y{1,}z{0,1}", "xxyyy") && !is_match("x{2}", "x"));
+        assert!(is_match("\\p{Lu}\\p{Ll}+\\P{L}", "Abc1") && is_match("\\.\\[\\]", ".[]"));
+        // The categories come from the Unicode tables, not only for ASCII
+        assert!(is_match("\\p{Nd}+", "1\u{663}\u{FF19}") && !is_match("\\p{Nd}", "\u{2163}"));
+        assert!(is_match("\\p{Nl}\\p{No}", "\u{2163}\u{BD}"));
+        assert!(is_match(
+            "\\p{Pi}\\p{Pf}\\p{Po}\\p{Pd}",
+            "\u{AB}\u{BB}\u{3001}\u{2014}"
+        ));
+        assert!(is_match(
+            "\\p{Sc}\\p{Sm}\\p{So}\\p{Sk}",
+            "\u{20AC}\u{2211}\u{2603}\u{B4}"
+        ));
+        assert!(is_match("[\\p{P}\\p{S}]+", "\u{AB}\u{20AC}") && !is_match("\\p{P}", "\u{20AC}"));
+        assert!(is_match(
+            "\\p{Lt}\\p{Lm}\\p{Lo}\\p{Mn}",
+            "\u{1C5}\u{2B0}\u{5D0}\u{301}"
+        ));
+        assert!(is_match(
+            "\\p{Cn}\\p{Co}\\p{Cf}\\p{Zs}",
+            "\u{378}\u{E000}\u{AD}\u{3000}"
+        ));
+        assert!(is_match("\\P{Cn}", "a") && !is_match("\\p{C}", "a"));
+        // ^ and $ are plain characters in I-Regexp
+        assert!(is_match("^a$", "^a$"));
+        assert!(is_found("b+", "abbc") && !is_found("bb", "abc"));
+        assert!(is_found("", "x") && is_match("", ""));
+        // Linear time, no backtracking blow-up
+        assert!(!is_match("(a*)*b", &"a".repeat(10_000)));
+        // The unrolled repetitions stay small, even of nothing
+        assert!(is_match("((){1000}){1000}", "") && is_match("(()*){2,}a", "a"));
+        assert!(is_match("a{1000}", &"a".repeat(1000)) && Regex::new("a{1001}").is_none());
+        assert!(Regex::new("a{2,1001}").is_none() && Regex::new("(a{100}){200}").is_none());
+        assert!(Regex::new("((){60000}){60000}").is_none());
+        let nested = format!("{}a{}", "(".repeat(100), ")?".repeat(100));
+        assert!(is_match(&nested, "a") && is_match(&nested, ""));
+
+        for bad in [
+            "(a", "a)", "*a", "a{2,1}", "[b-a]", "\\d", "[a", "a{,2}", "\\p{X}", "a**",
+        ] {
+            assert!(Regex::new(bad).is_none(), "{bad}");
+        }
+    }
+}
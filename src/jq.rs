@@ -0,0 +1,2265 @@
+use core::cmp::Ordering;
+use core::fmt;
+use std::cell::Cell;
+use std::str::FromStr;
+
+use super::ser::MAX_DEPTH;
+use super::{parse, Map, JSON};
+
+// The expressions of a program can't nest deeper than this, both the
+// parser and the interpreter recurse on them
+const MAX_NESTING: usize = 64;
+
+// Bytes of a string made by "s" * n, a program can't ask for more memory
+const MAX_REPEAT_LEN: usize = 1 << 26;
+
+// The functions of the programs, with their number of arguments
+const BUILTINS: &[(&str, usize)] = &[
+    ("empty", 0),
+    ("error", 0),
+    ("error", 1),
+    ("not", 0),
+    ("length", 0),
+    ("keys", 0),
+    ("keys_unsorted", 0),
+    ("has", 1),
+    ("contains", 1),
+    ("add", 0),
+    ("any", 0),
+    ("any", 1),
+    ("all", 0),
+    ("all", 1),
+    ("map", 1),
+    ("map_values", 1),
+    ("select", 1),
+    ("recurse", 0),
+    ("recurse", 1),
+    ("to_entries", 0),
+    ("from_entries", 0),
+    ("with_entries", 1),
+    ("type", 0),
+    ("tostring", 0),
+    ("tonumber", 0),
+    ("tojson", 0),
+    ("fromjson", 0),
+    ("range", 1),
+    ("range", 2),
+    ("limit", 2),
+    ("first", 0),
+    ("first", 1),
+    ("last", 0),
+    ("last", 1),
+    ("reverse", 0),
+    ("sort", 0),
+    ("sort_by", 1),
+    ("group_by", 1),
+    ("unique", 0),
+    ("unique_by", 1),
+    ("min", 0),
+    ("min_by", 1),
+    ("max", 0),
+    ("max_by", 1),
+    ("flatten", 0),
+    ("flatten", 1),
+    ("floor", 0),
+    ("ceil", 0),
+    ("round", 0),
+    ("sqrt", 0),
+    ("startswith", 1),
+    ("endswith", 1),
+    ("ltrimstr", 1),
+    ("rtrimstr", 1),
+    ("split", 1),
+    ("join", 1),
+    ("ascii_downcase", 0),
+    ("ascii_upcase", 0),
+];
+
+// A compiled jq program, to reshape documents without running jq itself:
+//
+//   let jq = Jq::compile(r#".items | map(select(.price < 10) | {name, cost: .price * 1.2})"#)?;
+//   let out: Vec<JSON> = jq.run(&doc)?;
+//
+// Like in jq, a program gives a stream of values, so run() gives a Vec. It
+// understands paths (.a, .[0], .[], .[1:3], .., ?), literals, string
+// interpolation "\(.a)", arrays, objects {a, b: .c, (.k): 1}, arithmetic,
+// comparisons, and/or, //, if/elif/else, try/catch, `as $x`, reduce, foreach
+// and the functions of BUILTINS. def, paths updates (|=, del...), formats
+// (@base64...) and regular expressions are not there.
+#[derive(Debug)]
+pub struct Jq {
+    expr: Expr,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Identity,
+    Recurse, // ..
+    Lit(JSON),
+    Str(Vec<Part>), // A string with interpolations
+    Var(String),
+    Array(Option<Box<Expr>>),
+    Object(Vec<(Expr, Expr)>),
+    Index(Box<Expr>, Box<Expr>),
+    Slice(Box<Expr>, Option<Box<Expr>>, Option<Box<Expr>>),
+    Iter(Box<Expr>),
+    Try(Box<Expr>, Option<Box<Expr>>),
+    Neg(Box<Expr>),
+    Pipe(Box<Expr>, Box<Expr>),
+    Comma(Box<Expr>, Box<Expr>),
+    Bin(Box<Expr>, Op, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Alt(Box<Expr>, Box<Expr>),
+    If(Box<Expr>, Box<Expr>, Option<Box<Expr>>), // elif nests in the else
+    As(Box<Expr>, String, Box<Expr>),
+    Reduce(Box<Expr>, String, Box<Expr>, Box<Expr>),
+    Foreach(Box<Expr>, String, Box<Expr>, Box<Expr>, Option<Box<Expr>>),
+    Call(&'static str, Vec<Expr>),
+}
+
+impl Expr {
+    fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Identity | Expr::Recurse | Expr::Lit(_) | Expr::Var(_) => Vec::new(),
+            Expr::Str(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    Part::Expr(expr) => Some(expr),
+                    Part::Lit(_) => None,
+                })
+                .collect(),
+            Expr::Array(items) => items.as_deref().into_iter().collect(),
+            Expr::Object(entries) => entries.iter().flat_map(|(k, v)| [k, v]).collect(),
+            Expr::Iter(a) | Expr::Neg(a) => vec![a],
+            Expr::Index(a, b)
+            | Expr::Pipe(a, b)
+            | Expr::Comma(a, b)
+            | Expr::Bin(a, _, b)
+            | Expr::And(a, b)
+            | Expr::Or(a, b)
+            | Expr::Alt(a, b)
+            | Expr::As(a, _, b) => vec![a, b],
+            Expr::Reduce(a, _, b, c) => vec![a, b, c],
+            Expr::Try(a, b) => {
+                let mut children: Vec<&Expr> = vec![a];
+                children.extend(b.as_deref());
+                children
+            }
+            Expr::If(a, b, c) => {
+                let mut children: Vec<&Expr> = vec![a, b];
+                children.extend(c.as_deref());
+                children
+            }
+            Expr::Foreach(a, _, b, c, d) => {
+                let mut children: Vec<&Expr> = vec![a, b, c];
+                children.extend(d.as_deref());
+                children
+            }
+            Expr::Slice(a, b, c) => {
+                let mut children: Vec<&Expr> = vec![a];
+                children.extend(b.as_deref());
+                children.extend(c.as_deref());
+                children
+            }
+            Expr::Call(_, args) => args.iter().collect(),
+        }
+    }
+
+    // How many levels the expression has, without recursion
+    fn height(&self) -> usize {
+        let mut height = 0;
+        let mut stack = vec![(self, 1)];
+        while let Some((expr, lvl)) = stack.pop() {
+            height = height.max(lvl);
+            stack.extend(expr.children().into_iter().map(|child| (child, lvl + 1)));
+        }
+        height
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Lit(String),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+// The program doesn't compile, or it failed on the input
+#[derive(Debug)]
+pub enum JqErr {
+    Syntax { column: usize, msg: &'static str },
+    Runtime(String),
+}
+
+impl fmt::Display for JqErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JqErr::Syntax { column, msg } => write!(f, "Char[{column}]: {msg}"),
+            JqErr::Runtime(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for JqErr {}
+
+impl Jq {
+    pub fn compile(program: &str) -> Result<Jq, JqErr> {
+        let mut p = Parser {
+            chars: program.chars().collect(),
+            pos: 0,
+            depth: 0,
+            scope: Vec::new(),
+            comma: true,
+        };
+        p.space();
+        if p.pos == p.chars.len() {
+            return Ok(Jq {
+                expr: Expr::Identity,
+            });
+        }
+        let expr = p.pipe()?;
+        p.space();
+        if p.pos < p.chars.len() {
+            return p.err("Unexpected character in the program");
+        }
+        Ok(Jq { expr })
+    }
+
+    pub fn run(&self, input: &JSON) -> Result<Vec<JSON>, JqErr> {
+        let mut out = Vec::new();
+        let interp = Interp {
+            labels: Cell::new(0),
+        };
+        match interp.eval(&self.expr, input, None, &mut |v| {
+            out.push(v);
+            Ok(())
+        }) {
+            Err(Stop::Err(err)) => Err(err),
+            _ => Ok(out),
+        }
+    }
+}
+
+impl FromStr for Jq {
+    type Err = JqErr;
+
+    fn from_str(program: &str) -> Result<Jq, JqErr> {
+        Jq::compile(program)
+    }
+}
+
+impl JSON {
+    // Run a jq program on this document, see Jq
+    pub fn jq(&self, program: &str) -> Result<Vec<JSON>, JqErr> {
+        Jq::compile(program)?.run(self)
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    depth: usize,
+    scope: Vec<String>, // The variables which can be used here
+    comma: bool,        // False in the values of objects, where ',' ends the value
+}
+
+fn is_ident_start(ch: char) -> bool {
+    ch.is_ascii_alphabetic() || ch == '_'
+}
+
+fn is_ident_part(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '_'
+}
+
+impl Parser {
+    fn err<T>(&self, msg: &'static str) -> Result<T, JqErr> {
+        Err(JqErr::Syntax {
+            column: self.pos + 1,
+            msg,
+        })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, n: usize) -> Option<char> {
+        self.chars.get(self.pos + n).copied()
+    }
+
+    fn eat(&mut self, ch: char) -> bool {
+        self.space();
+        let found = self.peek() == Some(ch);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, ch: char, msg: &'static str) -> Result<(), JqErr> {
+        if !self.eat(ch) {
+            return self.err(msg);
+        }
+        Ok(())
+    }
+
+    // Whitespace and # comments
+    fn space(&mut self) {
+        while let Some(ch) = self.peek() {
+            match ch {
+                ' ' | '\t' | '\n' | '\r' => self.pos += 1,
+                '#' => {
+                    while self.peek().is_some_and(|ch| ch != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn word(&self) -> String {
+        self.chars[self.pos..]
+            .iter()
+            .take_while(|ch| is_ident_part(**ch))
+            .collect()
+    }
+
+    fn ident(&mut self) -> String {
+        let word = self.word();
+        self.pos += word.len();
+        word
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        self.space();
+        let found = self.word() == word;
+        if found {
+            self.pos += word.len();
+        }
+        found
+    }
+
+    fn expect_word(&mut self, word: &str, msg: &'static str) -> Result<(), JqErr> {
+        if !self.eat_word(word) {
+            return self.err(msg);
+        }
+        Ok(())
+    }
+
+    fn var(&mut self) -> Result<String, JqErr> {
+        self.space();
+        if self.peek() != Some('$') || !self.peek_at(1).is_some_and(is_ident_start) {
+            return self.err("Expected a variable like $x");
+        }
+        self.pos += 1;
+        Ok(self.ident())
+    }
+
+    // A whole pipe in (), [] or the arguments of a call, where ',' is back
+    fn inner(&mut self) -> Result<Expr, JqErr> {
+        let comma = std::mem::replace(&mut self.comma, true);
+        let expr = self.pipe();
+        self.comma = comma;
+        expr
+    }
+
+    // One more level of nesting, the caller takes it back from self.depth
+    fn nest(&mut self) -> Result<(), JqErr> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            return self.err("The program is nested too deep");
+        }
+        Ok(())
+    }
+
+    fn pipe(&mut self) -> Result<Expr, JqErr> {
+        self.nest()?;
+        let lhs = match self.comma {
+            true => self.comma_expr()?,
+            false => self.alt()?,
+        };
+        self.space();
+        let expr = if self.peek() == Some('|') && self.peek_at(1) != Some('=') {
+            self.pos += 1;
+            Expr::Pipe(Box::new(lhs), Box::new(self.pipe()?))
+        } else {
+            lhs
+        };
+        self.depth -= 1;
+        // The loops below count their own nodes, not the first operand's
+        if self.depth + expr.height() > MAX_NESTING {
+            return self.err("The program is nested too deep");
+        }
+        Ok(expr)
+    }
+
+    fn comma_expr(&mut self) -> Result<Expr, JqErr> {
+        let depth = self.depth;
+        let mut expr = self.alt()?;
+        while self.eat(',') {
+            self.nest()?;
+            expr = Expr::Comma(Box::new(expr), Box::new(self.alt()?));
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn alt(&mut self) -> Result<Expr, JqErr> {
+        let lhs = self.or()?;
+        self.space();
+        if self.peek() == Some('/') && self.peek_at(1) == Some('/') && self.peek_at(2) != Some('=')
+        {
+            self.pos += 2;
+            self.nest()?;
+            let rhs = self.alt()?;
+            self.depth -= 1;
+            return Ok(Expr::Alt(Box::new(lhs), Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    fn or(&mut self) -> Result<Expr, JqErr> {
+        let depth = self.depth;
+        let mut expr = self.and()?;
+        while self.eat_word("or") {
+            self.nest()?;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, JqErr> {
+        let depth = self.depth;
+        let mut expr = self.cmp()?;
+        while self.eat_word("and") {
+            self.nest()?;
+            expr = Expr::And(Box::new(expr), Box::new(self.cmp()?));
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    // Comparisons don't chain, 1 < 2 < 3 is an error
+    fn cmp(&mut self) -> Result<Expr, JqErr> {
+        let lhs = self.sum()?;
+        self.space();
+        let ops = [
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ];
+        for (s, op) in ops {
+            if s.chars()
+                .enumerate()
+                .all(|(i, ch)| self.peek_at(i) == Some(ch))
+            {
+                self.pos += s.len();
+                return Ok(Expr::Bin(Box::new(lhs), op, Box::new(self.sum()?)));
+            }
+        }
+        Ok(lhs)
+    }
+
+    fn sum(&mut self) -> Result<Expr, JqErr> {
+        let depth = self.depth;
+        let mut expr = self.product()?;
+        loop {
+            self.space();
+            let op = match (self.peek(), self.peek_at(1)) {
+                (Some('+'), next) if next != Some('=') => Op::Add,
+                (Some('-'), next) if next != Some('=') => Op::Sub,
+                _ => break,
+            };
+            self.pos += 1;
+            self.nest()?;
+            expr = Expr::Bin(Box::new(expr), op, Box::new(self.product()?));
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> Result<Expr, JqErr> {
+        let depth = self.depth;
+        let mut expr = self.unary()?;
+        loop {
+            self.space();
+            let op = match (self.peek(), self.peek_at(1)) {
+                (_, Some('=')) => break,
+                (Some('*'), _) => Op::Mul,
+                (Some('/'), next) if next != Some('/') => Op::Div,
+                (Some('%'), _) => Op::Rem,
+                _ => break,
+            };
+            self.pos += 1;
+            self.nest()?;
+            expr = Expr::Bin(Box::new(expr), op, Box::new(self.unary()?));
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, JqErr> {
+        if !self.eat('-') {
+            return self.term();
+        }
+        self.nest()?;
+        let expr = self.unary()?;
+        self.depth -= 1;
+        Ok(match expr {
+            Expr::Lit(JSON::Int(i)) if i != i64::MIN => Expr::Lit(JSON::Int(-i)),
+            Expr::Lit(JSON::Flt(f)) => Expr::Lit(JSON::Flt(-f)),
+            expr => Expr::Neg(Box::new(expr)),
+        })
+    }
+
+    // A term, or `term as $x | body` which takes the rest of the pipe
+    fn term(&mut self) -> Result<Expr, JqErr> {
+        let term = self.postfix()?;
+        if !self.eat_word("as") {
+            return Ok(term);
+        }
+        let name = self.var()?;
+        self.expect('|', "Expected '|' after the variable")?;
+        self.scope.push(name.clone());
+        let body = self.pipe();
+        self.scope.pop();
+        Ok(Expr::As(Box::new(term), name, Box::new(body?)))
+    }
+
+    fn postfix(&mut self) -> Result<Expr, JqErr> {
+        let depth = self.depth;
+        let mut expr = self.primary()?;
+        loop {
+            self.space();
+            match (self.peek(), self.peek_at(1)) {
+                (Some('.'), Some(ch)) if is_ident_start(ch) => {
+                    self.pos += 1;
+                    self.nest()?;
+                    let key = Expr::Lit(JSON::Str(self.ident()));
+                    expr = Expr::Index(Box::new(expr), Box::new(key));
+                }
+                (Some('.'), Some('"')) => {
+                    self.pos += 2;
+                    self.nest()?;
+                    expr = Expr::Index(Box::new(expr), Box::new(self.string()?));
+                }
+                (Some('.'), Some('[')) => {
+                    self.pos += 2;
+                    self.nest()?;
+                    expr = self.bracket(expr)?;
+                }
+                (Some('['), _) => {
+                    self.pos += 1;
+                    self.nest()?;
+                    expr = self.bracket(expr)?;
+                }
+                (Some('?'), _) => {
+                    self.pos += 1;
+                    self.nest()?;
+                    expr = Expr::Try(Box::new(expr), None);
+                }
+                _ => break,
+            }
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    // After '[': [], [key], [from:], [:to] or [from:to]
+    fn bracket(&mut self, target: Expr) -> Result<Expr, JqErr> {
+        let target = Box::new(target);
+        if self.eat(']') {
+            return Ok(Expr::Iter(target));
+        }
+        let from = match self.eat(':') {
+            true => None,
+            false => {
+                let key = self.inner()?;
+                if !self.eat(':') {
+                    self.expect(']', "Expected ']'")?;
+                    return Ok(Expr::Index(target, Box::new(key)));
+                }
+                Some(Box::new(key))
+            }
+        };
+        let to = match self.eat(']') {
+            true if from.is_some() => return Ok(Expr::Slice(target, from, None)),
+            true => return self.err("Expected the end of the slice"),
+            false => Some(Box::new(self.inner()?)),
+        };
+        self.expect(']', "Expected ']'")?;
+        Ok(Expr::Slice(target, from, to))
+    }
+
+    fn primary(&mut self) -> Result<Expr, JqErr> {
+        self.space();
+        match (self.peek(), self.peek_at(1)) {
+            (Some('.'), Some('.')) => {
+                self.pos += 2;
+                Ok(Expr::Recurse)
+            }
+            (Some('.'), Some(ch)) if is_ident_start(ch) => {
+                self.pos += 1;
+                let key = Expr::Lit(JSON::Str(self.ident()));
+                Ok(Expr::Index(Box::new(Expr::Identity), Box::new(key)))
+            }
+            (Some('.'), Some('"')) => {
+                self.pos += 1;
+                let key = self.string()?;
+                Ok(Expr::Index(Box::new(Expr::Identity), Box::new(key)))
+            }
+            (Some('.'), _) => {
+                self.pos += 1;
+                Ok(Expr::Identity)
+            }
+            (Some('$'), _) => {
+                let at = self.pos;
+                let name = self.var()?;
+                if !self.scope.contains(&name) {
+                    self.pos = at;
+                    return self.err("The variable is not defined");
+                }
+                Ok(Expr::Var(name))
+            }
+            (Some('0'..='9'), _) => self.number(),
+            (Some('"'), _) => self.string(),
+            (Some('['), _) => {
+                self.pos += 1;
+                if self.eat(']') {
+                    return Ok(Expr::Array(None));
+                }
+                let items = self.inner()?;
+                self.expect(']', "Expected ']' at the end of the array")?;
+                Ok(Expr::Array(Some(Box::new(items))))
+            }
+            (Some('{'), _) => self.object(),
+            (Some('('), _) => {
+                self.pos += 1;
+                let expr = self.inner()?;
+                self.expect(')', "Expected ')'")?;
+                Ok(expr)
+            }
+            (Some(ch), _) if is_ident_start(ch) => self.word_term(),
+            _ => self.err("Expected an expression"),
+        }
+    }
+
+    fn number(&mut self) -> Result<Expr, JqErr> {
+        let from = self.pos;
+        let digits = |p: &mut Parser| {
+            while p.peek().is_some_and(|ch| ch.is_ascii_digit()) {
+                p.pos += 1;
+            }
+        };
+        digits(self);
+        if self.peek() == Some('.') && self.peek_at(1).is_some_and(|ch| ch.is_ascii_digit()) {
+            self.pos += 1;
+            digits(self);
+        }
+        if matches!(self.peek(), Some('e' | 'E')) {
+            self.pos += 1;
+            if matches!(self.peek(), Some('+' | '-')) {
+                self.pos += 1;
+            }
+            if !self.peek().is_some_and(|ch| ch.is_ascii_digit()) {
+                return self.err("Expected the digits of the exponent");
+            }
+            digits(self);
+        }
+        let text: String = self.chars[from..self.pos].iter().collect();
+        match (text.parse::<i64>(), text.parse::<f64>()) {
+            (Ok(int), _) => Ok(Expr::Lit(JSON::Int(int))),
+            (_, Ok(flt)) if flt.is_finite() => Ok(Expr::Lit(JSON::Flt(flt))),
+            _ => {
+                self.pos = from;
+                self.err("The number is too large")
+            }
+        }
+    }
+
+    // A string literal, with "\(expr)" interpolations
+    fn string(&mut self) -> Result<Expr, JqErr> {
+        self.pos += 1; // '"'
+        let mut parts = Vec::new();
+        let mut lit = String::new();
+        loop {
+            let Some(ch) = self.peek() else {
+                return self.err("The string is not closed");
+            };
+            self.pos += 1;
+            match ch {
+                '"' => break,
+                '\\' => {
+                    let Some(esc) = self.peek() else {
+                        return self.err("The string is not closed");
+                    };
+                    self.pos += 1;
+                    match esc {
+                        '(' => {
+                            parts.push(Part::Lit(std::mem::take(&mut lit)));
+                            parts.push(Part::Expr(self.inner()?));
+                            self.expect(')', "Expected ')' at the end of the interpolation")?;
+                        }
+                        'b' => lit.push('\u{0008}'),
+                        'f' => lit.push('\u{000C}'),
+                        'n' => lit.push('\n'),
+                        'r' => lit.push('\r'),
+                        't' => lit.push('\t'),
+                        '"' | '\\' | '/' => lit.push(esc),
+                        'u' => lit.push(self.unicode()?),
+                        _ => {
+                            self.pos -= 1;
+                            return self.err("Invalid escape in the string");
+                        }
+                    }
+                }
+                _ => lit.push(ch),
+            }
+        }
+        if parts.is_empty() {
+            return Ok(Expr::Lit(JSON::Str(lit)));
+        }
+        parts.push(Part::Lit(lit));
+        Ok(Expr::Str(parts))
+    }
+
+    // After "\u", with the surrogate pairs
+    fn unicode(&mut self) -> Result<char, JqErr> {
+        let high = self.hex4()?;
+        let code = match high {
+            0xD800..=0xDBFF => {
+                if self.peek() != Some('\\') || self.peek_at(1) != Some('u') {
+                    return self.err("Expected a low surrogate \\uDC00-\\uDFFF");
+                }
+                self.pos += 2;
+                let low = self.hex4()?;
+                if !(0xDC00..=0xDFFF).contains(&low) {
+                    return self.err("Expected a low surrogate \\uDC00-\\uDFFF");
+                }
+                0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+            }
+            _ => high,
+        };
+        match char::from_u32(code) {
+            Some(ch) => Ok(ch),
+            None => self.err("Lone low surrogate"),
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, JqErr> {
+        let hex: String = self.chars.iter().skip(self.pos).take(4).collect();
+        if hex.len() != 4 || !hex.chars().all(|ch| ch.is_ascii_hexdigit()) {
+            return self.err("Expected 4 hex digits after \\u");
+        }
+        self.pos += 4;
+        u32::from_str_radix(&hex, 16).or_else(|_| self.err("Expected 4 hex digits after \\u"))
+    }
+
+    // {a, "b": 1, $c, (.k): .v}, a value takes pipes but no ','
+    fn object(&mut self) -> Result<Expr, JqErr> {
+        self.pos += 1; // '{'
+        let mut entries = Vec::new();
+        if self.eat('}') {
+            return Ok(Expr::Object(entries));
+        }
+        loop {
+            self.space();
+            let (key, short) = match self.peek() {
+                Some('$') => {
+                    let name = self.var()?;
+                    if !self.scope.contains(&name) {
+                        return self.err("The variable is not defined");
+                    }
+                    (Expr::Lit(JSON::Str(name.clone())), Some(Expr::Var(name)))
+                }
+                Some('"') => {
+                    let key = self.string()?;
+                    let short = Expr::Index(Box::new(Expr::Identity), Box::new(key.clone()));
+                    (key, Some(short))
+                }
+                Some('(') => {
+                    self.pos += 1;
+                    let key = self.inner()?;
+                    self.expect(')', "Expected ')' after the key")?;
+                    (key, None)
+                }
+                Some(ch) if is_ident_start(ch) => {
+                    let key = Expr::Lit(JSON::Str(self.ident()));
+                    let short = Expr::Index(Box::new(Expr::Identity), Box::new(key.clone()));
+                    (key, Some(short))
+                }
+                _ => return self.err("Expected an object key"),
+            };
+            let val = match (self.eat(':'), short) {
+                (true, _) => {
+                    let comma = std::mem::replace(&mut self.comma, false);
+                    let val = self.pipe();
+                    self.comma = comma;
+                    val?
+                }
+                (false, Some(short)) => short,
+                (false, None) => return self.err("Expected ':' after the key"),
+            };
+            entries.push((key, val));
+            if self.eat('}') {
+                return Ok(Expr::Object(entries));
+            }
+            self.expect(',', "Expected ',' or '}' in the object")?;
+        }
+    }
+
+    // Literals, keywords and calls
+    fn word_term(&mut self) -> Result<Expr, JqErr> {
+        let at = self.pos;
+        let name = self.ident();
+        match name.as_str() {
+            "true" => Ok(Expr::Lit(JSON::Bol(true))),
+            "false" => Ok(Expr::Lit(JSON::Bol(false))),
+            "null" => Ok(Expr::Lit(JSON::Nul)),
+            "if" => self.if_rest(),
+            "try" => {
+                self.nest()?;
+                let body = self.postfix()?;
+                let catch = match self.eat_word("catch") {
+                    true => Some(Box::new(self.postfix()?)),
+                    false => None,
+                };
+                self.depth -= 1;
+                Ok(Expr::Try(Box::new(body), catch))
+            }
+            "reduce" | "foreach" => {
+                self.nest()?;
+                let source = self.postfix()?;
+                self.depth -= 1;
+                self.expect_word("as", "Expected 'as' after the source")?;
+                let var = self.var()?;
+                self.expect('(', "Expected '('")?;
+                let init = self.inner()?;
+                self.expect(';', "Expected ';' after the initial value")?;
+                self.scope.push(var.clone());
+                let update = self.inner();
+                let extract = match (name.as_str(), self.eat(';')) {
+                    ("foreach", true) => Some(self.inner().map(Box::new)),
+                    _ => None,
+                };
+                self.scope.pop();
+                let (update, extract) = (Box::new(update?), extract.transpose()?);
+                self.expect(')', "Expected ')'")?;
+                Ok(match name.as_str() {
+                    "reduce" => Expr::Reduce(Box::new(source), var, Box::new(init), update),
+                    _ => Expr::Foreach(Box::new(source), var, Box::new(init), update, extract),
+                })
+            }
+            "def" | "label" | "import" | "include" => {
+                self.pos = at;
+                self.err("def, label and modules are not supported")
+            }
+            "then" | "elif" | "else" | "end" | "as" | "catch" | "and" | "or" | "__loc__" => {
+                self.pos = at;
+                self.err("Unexpected keyword")
+            }
+            _ => {
+                let mut args = Vec::new();
+                if self.peek() == Some('(') {
+                    self.pos += 1;
+                    loop {
+                        args.push(self.inner()?);
+                        if !self.eat(';') {
+                            break;
+                        }
+                    }
+                    self.expect(')', "Expected ')' after the arguments")?;
+                }
+                let Some((name, _)) = BUILTINS
+                    .iter()
+                    .find(|(func, arity)| *func == name && *arity == args.len())
+                else {
+                    self.pos = at;
+                    return self.err("Unknown function, or wrong number of arguments");
+                };
+                Ok(Expr::Call(name, args))
+            }
+        }
+    }
+
+    // After "if" or "elif", the else is optional like in jq 1.7
+    fn if_rest(&mut self) -> Result<Expr, JqErr> {
+        let cond = self.inner()?;
+        self.expect_word("then", "Expected 'then'")?;
+        let then = self.inner()?;
+        let other = if self.eat_word("elif") {
+            self.nest()?;
+            let other = self.if_rest()?;
+            self.depth -= 1;
+            Some(other)
+        } else if self.eat_word("else") {
+            let other = self.inner()?;
+            self.expect_word("end", "Expected 'end'")?;
+            Some(other)
+        } else {
+            self.expect_word("end", "Expected 'end'")?;
+            None
+        };
+        Ok(Expr::If(
+            Box::new(cond),
+            Box::new(then),
+            other.map(Box::new),
+        ))
+    }
+}
+
+// Why the evaluation stopped: an error, or limit() has all it wants
+enum Stop {
+    Err(JqErr),
+    Break(usize),
+}
+
+fn fail<T>(msg: String) -> Result<T, Stop> {
+    Err(Stop::Err(JqErr::Runtime(msg)))
+}
+
+// The variables, innermost first
+struct Env<'a> {
+    name: &'a str,
+    val: &'a JSON,
+    up: Option<&'a Env<'a>>,
+}
+
+type Emit<'e> = dyn FnMut(JSON) -> Result<(), Stop> + 'e;
+
+// Each expression gives its values one by one to emit, so limit() and
+// first() can stop a generator early
+struct Interp {
+    labels: Cell<usize>,
+}
+
+impl Interp {
+    fn label(&self) -> usize {
+        self.labels.set(self.labels.get() + 1);
+        self.labels.get()
+    }
+
+    fn collect(&self, expr: &Expr, input: &JSON, env: Option<&Env>) -> Result<Vec<JSON>, Stop> {
+        let mut out = Vec::new();
+        self.eval(expr, input, env, &mut |v| {
+            out.push(v);
+            Ok(())
+        })?;
+        Ok(out)
+    }
+
+    fn eval(
+        &self,
+        expr: &Expr,
+        input: &JSON,
+        env: Option<&Env>,
+        emit: &mut Emit,
+    ) -> Result<(), Stop> {
+        match expr {
+            Expr::Identity => emit(input.clone()),
+            Expr::Recurse => {
+                let mut stack = vec![input];
+                while let Some(node) = stack.pop() {
+                    emit(node.clone())?;
+                    let children: Vec<_> = match node {
+                        JSON::Lst(lst) => lst.iter().collect(),
+                        JSON::Obj(obj) => obj.values().collect(),
+                        _ => Vec::new(),
+                    };
+                    stack.extend(children.into_iter().rev());
+                }
+                Ok(())
+            }
+            Expr::Lit(lit) => emit(lit.clone()),
+            Expr::Str(parts) => self.interpolate(parts, input, env, String::new(), emit),
+            Expr::Var(name) => {
+                let mut scope = env;
+                while let Some(var) = scope {
+                    if var.name == name {
+                        return emit(var.val.clone());
+                    }
+                    scope = var.up;
+                }
+                emit(JSON::Nul)
+            }
+            Expr::Array(None) => emit(JSON::Lst(Vec::new())),
+            Expr::Array(Some(items)) => emit(JSON::Lst(self.collect(items, input, env)?)),
+            Expr::Object(entries) => {
+                // One object for each combination of the keys and the values
+                let mut objs = vec![Map::new()];
+                for (key, val) in entries {
+                    let keys = self.collect(key, input, env)?;
+                    let vals = self.collect(val, input, env)?;
+                    let mut next = Vec::new();
+                    for obj in &objs {
+                        for key in &keys {
+                            let JSON::Str(key) = key else {
+                                return fail(format!(
+                                    "Object keys must be strings, not {}",
+                                    describe(key)
+                                ));
+                            };
+                            for val in &vals {
+                                let mut obj = obj.clone();
+                                obj.insert(key.clone(), val.clone());
+                                next.push(obj);
+                            }
+                        }
+                    }
+                    objs = next;
+                }
+                objs.into_iter().try_for_each(|obj| emit(JSON::Obj(obj)))
+            }
+            Expr::Index(target, key) => self.eval(key, input, env, &mut |key| {
+                self.eval(target, input, env, &mut |v| emit(index(&v, &key)?))
+            }),
+            Expr::Slice(target, from, to) => self.bound(from, input, env, &mut |from| {
+                self.bound(to, input, env, &mut |to| {
+                    self.eval(target, input, env, &mut |v| emit(slice(&v, &from, &to)?))
+                })
+            }),
+            Expr::Iter(target) => self.eval(target, input, env, &mut |v| match v {
+                JSON::Lst(lst) => lst.into_iter().try_for_each(&mut *emit),
+                JSON::Obj(obj) => obj.into_iter().try_for_each(|(_, item)| emit(item)),
+                _ => fail(format!("Cannot iterate over {}", describe(&v))),
+            }),
+            Expr::Try(body, catch) => {
+                // Only the errors of the body are caught, not the ones after it
+                let label = self.label();
+                let mut after = None;
+                let res = self.eval(body, input, env, &mut |v| {
+                    emit(v).map_err(|stop| {
+                        after = Some(stop);
+                        Stop::Break(label)
+                    })
+                });
+                match (res, after, catch) {
+                    (_, Some(stop), _) => Err(stop),
+                    (Err(Stop::Err(err)), None, Some(catch)) => {
+                        let msg = match err {
+                            JqErr::Runtime(msg) => msg,
+                            err => err.to_string(),
+                        };
+                        self.eval(catch, &JSON::Str(msg), env, emit)
+                    }
+                    (Err(Stop::Err(_)), None, None) => Ok(()),
+                    (res, None, _) => res,
+                }
+            }
+            Expr::Neg(expr) => self.eval(expr, input, env, &mut |v| emit(neg(&v)?)),
+            Expr::Pipe(lhs, rhs) => {
+                self.eval(lhs, input, env, &mut |v| self.eval(rhs, &v, env, emit))
+            }
+            Expr::Comma(lhs, rhs) => {
+                self.eval(lhs, input, env, emit)?;
+                self.eval(rhs, input, env, emit)
+            }
+            // Like jq, the right side is the outer loop: (1,2) + (10,20) is 11, 12, 21, 22
+            Expr::Bin(lhs, op, rhs) => self.eval(rhs, input, env, &mut |r| {
+                self.eval(lhs, input, env, &mut |l| emit(binop(&l, *op, &r)?))
+            }),
+            Expr::And(lhs, rhs) => self.eval(lhs, input, env, &mut |l| match truthy(&l) {
+                false => emit(JSON::Bol(false)),
+                true => self.eval(rhs, input, env, &mut |r| emit(JSON::Bol(truthy(&r)))),
+            }),
+            Expr::Or(lhs, rhs) => self.eval(lhs, input, env, &mut |l| match truthy(&l) {
+                true => emit(JSON::Bol(true)),
+                false => self.eval(rhs, input, env, &mut |r| emit(JSON::Bol(truthy(&r)))),
+            }),
+            Expr::Alt(lhs, rhs) => {
+                // The errors of the left side count as no value
+                let mut found = Vec::new();
+                let _ = self.eval(lhs, input, env, &mut |v| {
+                    if truthy(&v) {
+                        found.push(v);
+                    }
+                    Ok(())
+                });
+                match found.is_empty() {
+                    true => self.eval(rhs, input, env, emit),
+                    false => found.into_iter().try_for_each(&mut *emit),
+                }
+            }
+            Expr::If(cond, then, other) => {
+                self.eval(cond, input, env, &mut |c| match (truthy(&c), other) {
+                    (true, _) => self.eval(then, input, env, emit),
+                    (false, Some(other)) => self.eval(other, input, env, emit),
+                    (false, None) => emit(input.clone()),
+                })
+            }
+            Expr::As(source, name, body) => self.eval(source, input, env, &mut |v| {
+                let scope = Env {
+                    name,
+                    val: &v,
+                    up: env,
+                };
+                self.eval(body, input, Some(&scope), emit)
+            }),
+            Expr::Reduce(source, name, init, update) => self.eval(init, input, env, &mut |init| {
+                let mut acc = Some(init);
+                self.eval(source, input, env, &mut |x| {
+                    let scope = Env {
+                        name,
+                        val: &x,
+                        up: env,
+                    };
+                    let state = acc.take().unwrap_or(JSON::Nul);
+                    acc = self.collect(update, &state, Some(&scope))?.pop();
+                    Ok(())
+                })?;
+                emit(acc.unwrap_or(JSON::Nul))
+            }),
+            Expr::Foreach(source, name, init, update, extract) => {
+                self.eval(init, input, env, &mut |init| {
+                    let mut state = init;
+                    self.eval(source, input, env, &mut |x| {
+                        let scope = Env {
+                            name,
+                            val: &x,
+                            up: env,
+                        };
+                        let outs = self.collect(update, &state, Some(&scope))?;
+                        for out in &outs {
+                            match extract {
+                                Some(extract) => self.eval(extract, out, Some(&scope), emit)?,
+                                None => emit(out.clone())?,
+                            }
+                        }
+                        if let Some(last) = outs.into_iter().last() {
+                            state = last;
+                        }
+                        Ok(())
+                    })
+                })
+            }
+            Expr::Call(name, args) => self.call(name, args, input, env, emit),
+        }
+    }
+
+    // A missing bound of a slice is null
+    fn bound(
+        &self,
+        expr: &Option<Box<Expr>>,
+        input: &JSON,
+        env: Option<&Env>,
+        emit: &mut Emit,
+    ) -> Result<(), Stop> {
+        match expr {
+            Some(expr) => self.eval(expr, input, env, emit),
+            None => emit(JSON::Nul),
+        }
+    }
+
+    fn interpolate(
+        &self,
+        parts: &[Part],
+        input: &JSON,
+        env: Option<&Env>,
+        done: String,
+        emit: &mut Emit,
+    ) -> Result<(), Stop> {
+        let Some((first, rest)) = parts.split_first() else {
+            return emit(JSON::Str(done));
+        };
+        match first {
+            Part::Lit(lit) => self.interpolate(rest, input, env, done + lit, emit),
+            Part::Expr(expr) => self.eval(expr, input, env, &mut |v| {
+                self.interpolate(rest, input, env, format!("{done}{}", text(&v)), emit)
+            }),
+        }
+    }
+
+    fn limit(
+        &self,
+        n: &JSON,
+        expr: &Expr,
+        input: &JSON,
+        env: Option<&Env>,
+        emit: &mut Emit,
+    ) -> Result<(), Stop> {
+        let Some(n) = num(n).map(N::f64) else {
+            return fail(format!("Invalid limit {}", describe(n)));
+        };
+        if n < 1.0 {
+            return Ok(());
+        }
+        let label = self.label();
+        let mut count = 0.0;
+        let res = self.eval(expr, input, env, &mut |v| {
+            emit(v)?;
+            count += 1.0;
+            match count >= n {
+                true => Err(Stop::Break(label)),
+                false => Ok(()),
+            }
+        });
+        match res {
+            Err(Stop::Break(stop)) if stop == label => Ok(()),
+            res => res,
+        }
+    }
+
+    // Depth first like jq, with a stack of its own instead of the call
+    // stack: the outputs of expr on a value wait there until their turn
+    fn recurse(
+        &self,
+        expr: &Expr,
+        input: &JSON,
+        env: Option<&Env>,
+        emit: &mut Emit,
+    ) -> Result<(), Stop> {
+        let mut stack = vec![(input.clone(), 0)];
+        while let Some((node, lvl)) = stack.pop() {
+            if lvl >= MAX_DEPTH {
+                return fail("recurse() went too deep".into());
+            }
+            emit(node.clone())?;
+            let mut next = Vec::new();
+            self.eval(expr, &node, env, &mut |v| {
+                next.push(v);
+                Ok(())
+            })?;
+            stack.extend(next.into_iter().rev().map(|v| (v, lvl + 1)));
+        }
+        Ok(())
+    }
+
+    // The filters given to map(), select()... run on the parts of the input,
+    // the other arguments are values
+    fn call(
+        &self,
+        name: &str,
+        args: &[Expr],
+        input: &JSON,
+        env: Option<&Env>,
+        emit: &mut Emit,
+    ) -> Result<(), Stop> {
+        match (name, args) {
+            ("empty", []) => Ok(()),
+            ("error", []) => fail(text(input)),
+            ("error", [msg]) => self.eval(msg, input, env, &mut |msg| fail(text(&msg))),
+            ("select", [cond]) => self.eval(cond, input, env, &mut |c| match truthy(&c) {
+                true => emit(input.clone()),
+                false => Ok(()),
+            }),
+            ("recurse", []) => self.eval(&Expr::Recurse, input, env, emit),
+            ("recurse", [expr]) => self.recurse(expr, input, env, emit),
+            ("range", [to]) => self.eval(to, input, env, &mut |to| range(&JSON::Int(0), &to, emit)),
+            ("range", [from, to]) => self.eval(from, input, env, &mut |from| {
+                self.eval(to, input, env, &mut |to| range(&from, &to, emit))
+            }),
+            ("limit", [n, expr]) => self.eval(n, input, env, &mut |n| {
+                self.limit(&n, expr, input, env, emit)
+            }),
+            ("first", [expr]) => self.limit(&JSON::Int(1), expr, input, env, emit),
+            ("last", [expr]) => match self.collect(expr, input, env)?.pop() {
+                Some(last) => emit(last),
+                None => Ok(()),
+            },
+            ("map", [expr]) => {
+                let mut out = Vec::new();
+                for item in items(input)? {
+                    out.extend(self.collect(expr, item, env)?);
+                }
+                emit(JSON::Lst(out))
+            }
+            ("map_values", [expr]) => {
+                // The first value of expr, or the item goes away
+                let first = |v: &JSON| -> Result<Option<JSON>, Stop> {
+                    Ok(self.collect(expr, v, env)?.into_iter().next())
+                };
+                match input {
+                    JSON::Obj(obj) => {
+                        let mut out = Map::new();
+                        for (k, v) in obj {
+                            if let Some(v) = first(v)? {
+                                out.insert(k.clone(), v);
+                            }
+                        }
+                        emit(JSON::Obj(out))
+                    }
+                    _ => {
+                        let mut out = Vec::new();
+                        for item in items(input)? {
+                            out.extend(first(item)?);
+                        }
+                        emit(JSON::Lst(out))
+                    }
+                }
+            }
+            ("with_entries", [expr]) => {
+                let mut out = Vec::new();
+                for entry in entries(input)? {
+                    out.extend(self.collect(expr, &entry, env)?);
+                }
+                emit(from_entries(&JSON::Lst(out))?)
+            }
+            ("any" | "all", [expr]) => {
+                let mut found = Vec::new();
+                for item in items(input)? {
+                    for v in self.collect(expr, item, env)? {
+                        found.push(truthy(&v));
+                    }
+                }
+                emit(JSON::Bol(match name {
+                    "any" => found.contains(&true),
+                    _ => !found.contains(&false),
+                }))
+            }
+            ("sort_by" | "group_by" | "unique_by" | "min_by" | "max_by", [expr]) => {
+                let JSON::Lst(lst) = input else {
+                    return fail(format!(
+                        "Cannot sort {}, as it is not an array",
+                        describe(input)
+                    ));
+                };
+                let mut keyed = Vec::new();
+                for item in lst {
+                    keyed.push((JSON::Lst(self.collect(expr, item, env)?), item));
+                }
+                keyed.sort_by(|a, b| compare(&a.0, &b.0));
+                emit(sorted_by(name, keyed))
+            }
+            ("flatten", [depth]) => self.eval(depth, input, env, &mut |depth| match num(&depth) {
+                Some(depth) if depth.f64() >= 0.0 => emit(flatten(input, depth.f64())?),
+                _ => fail("flatten depth must not be negative".into()),
+            }),
+            (_, [arg]) => self.eval(arg, input, env, &mut |arg| {
+                emit(builtin1(name, input, &arg)?)
+            }),
+            (_, _) => emit(builtin0(name, input)?),
+        }
+    }
+}
+
+fn builtin0(name: &str, input: &JSON) -> Result<JSON, Stop> {
+    let out = match (name, input) {
+        ("not", _) => JSON::Bol(!truthy(input)),
+        ("length", JSON::Nul) => JSON::Int(0),
+        ("length", JSON::Str(s)) => JSON::Int(s.chars().count() as i64),
+        ("length", JSON::Lst(lst)) => JSON::Int(lst.len() as i64),
+        ("length", JSON::Obj(obj)) => JSON::Int(obj.len() as i64),
+        ("length", _) if num(input).is_some() => match input {
+            JSON::Int(i) if *i != i64::MIN => JSON::Int(i.abs()),
+            _ => number(input_f64(input).abs()),
+        },
+        ("keys" | "keys_unsorted", JSON::Obj(obj)) => {
+            let mut keys: Vec<_> = obj.keys().cloned().collect();
+            if name == "keys" {
+                keys.sort();
+            }
+            JSON::Lst(keys.into_iter().map(JSON::Str).collect())
+        }
+        ("keys" | "keys_unsorted", JSON::Lst(lst)) => {
+            JSON::Lst((0..lst.len() as i64).map(JSON::Int).collect())
+        }
+        ("add", _) => {
+            let mut sum = JSON::Nul;
+            for item in items(input)? {
+                sum = add(&sum, item)?;
+            }
+            sum
+        }
+        ("any", _) => JSON::Bol(items(input)?.into_iter().any(truthy)),
+        ("all", _) => JSON::Bol(items(input)?.into_iter().all(truthy)),
+        ("to_entries", _) => JSON::Lst(entries(input)?),
+        ("from_entries", _) => from_entries(input)?,
+        ("type", _) => JSON::Str(type_name(input).into()),
+        ("tostring", _) => JSON::Str(text(input)),
+        ("tojson", _) => JSON::Str(input.to_string()),
+        ("tonumber", JSON::Str(s)) => match parse(s.trim()) {
+            Ok(n) if num(&n).is_some() => n,
+            _ => return fail(format!("Cannot parse {} as a number", describe(input))),
+        },
+        ("tonumber", _) if num(input).is_some() => input.clone(),
+        ("fromjson", JSON::Str(s)) => match parse(s) {
+            Ok(json) => json,
+            Err(err) => return fail(format!("{err} (while parsing '{s}')")),
+        },
+        ("first", _) => index(input, &JSON::Int(0))?,
+        ("last", _) => index(input, &JSON::Int(-1))?,
+        ("reverse", JSON::Nul) => JSON::Lst(Vec::new()),
+        ("reverse", JSON::Str(s)) => JSON::Str(s.chars().rev().collect()),
+        ("reverse", JSON::Lst(lst)) => JSON::Lst(lst.iter().rev().cloned().collect()),
+        ("sort" | "unique" | "min" | "max", JSON::Lst(lst)) => {
+            // Each item is its own key
+            let mut keyed: Vec<_> = lst.iter().map(|item| (item.clone(), item)).collect();
+            keyed.sort_by(|a, b| compare(&a.0, &b.0));
+            let by = match name {
+                "sort" => "sort_by",
+                "unique" => "unique_by",
+                "min" => "min_by",
+                _ => "max_by",
+            };
+            sorted_by(by, keyed)
+        }
+        ("flatten", _) => flatten(input, f64::INFINITY)?,
+        ("floor" | "ceil" | "round" | "sqrt", _) if num(input).is_some() => {
+            let f = input_f64(input);
+            number(match name {
+                "floor" => f.floor(),
+                "ceil" => f.ceil(),
+                "round" => f.round(),
+                _ => f.sqrt(),
+            })
+        }
+        ("ascii_downcase", JSON::Str(s)) => JSON::Str(s.to_ascii_lowercase()),
+        ("ascii_upcase", JSON::Str(s)) => JSON::Str(s.to_ascii_uppercase()),
+        _ => return fail(format!("{} can't be used with {name}", describe(input))),
+    };
+    Ok(out)
+}
+
+fn builtin1(name: &str, input: &JSON, arg: &JSON) -> Result<JSON, Stop> {
+    let out = match (name, input, arg) {
+        ("has", JSON::Obj(obj), JSON::Str(key)) => JSON::Bol(obj.contains_key(key.as_str())),
+        ("has", JSON::Lst(lst), _) if num(arg).is_some() => {
+            let i = input_f64(arg);
+            JSON::Bol(i >= 0.0 && i < lst.len() as f64)
+        }
+        ("contains", _, _) => match contains(input, arg) {
+            Some(found) => JSON::Bol(found),
+            None => {
+                return fail(format!(
+                    "{} and {} cannot have their containment checked",
+                    describe(input),
+                    describe(arg)
+                ))
+            }
+        },
+        ("startswith", JSON::Str(s), JSON::Str(prefix)) => {
+            JSON::Bol(s.starts_with(prefix.as_str()))
+        }
+        ("endswith", JSON::Str(s), JSON::Str(suffix)) => JSON::Bol(s.ends_with(suffix.as_str())),
+        ("ltrimstr", JSON::Str(s), JSON::Str(prefix)) => {
+            JSON::Str(s.strip_prefix(prefix.as_str()).unwrap_or(s).to_string())
+        }
+        ("rtrimstr", JSON::Str(s), JSON::Str(suffix)) => {
+            JSON::Str(s.strip_suffix(suffix.as_str()).unwrap_or(s).to_string())
+        }
+        ("ltrimstr" | "rtrimstr", _, _) => input.clone(),
+        ("split", JSON::Str(s), JSON::Str(sep)) => split(s, sep),
+        ("join", JSON::Lst(lst), JSON::Str(sep)) => {
+            let mut out = String::new();
+            for (i, item) in lst.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(sep);
+                }
+                match item {
+                    JSON::Nul => {}
+                    JSON::Str(s) => out.push_str(s),
+                    JSON::Lst(_) | JSON::Obj(_) => {
+                        return fail(format!("Cannot join with {}", describe(item)));
+                    }
+                    _ => out.push_str(&item.to_string()),
+                }
+            }
+            JSON::Str(out)
+        }
+        _ => {
+            return fail(format!(
+                "{} can't be used with {name}({})",
+                describe(input),
+                describe(arg)
+            ))
+        }
+    };
+    Ok(out)
+}
+
+// A number is an i64 as long as it fits, like the Int of JSON
+#[derive(Clone, Copy)]
+enum N {
+    I(i64),
+    F(f64),
+}
+
+impl N {
+    fn f64(self) -> f64 {
+        match self {
+            N::I(i) => i as f64,
+            N::F(f) => f,
+        }
+    }
+}
+
+fn num(json: &JSON) -> Option<N> {
+    match json {
+        JSON::Int(i) => Some(N::I(*i)),
+        JSON::Flt(f) => Some(N::F(*f)),
+        JSON::Num(n) => Some(
+            n.as_i64()
+                .map_or_else(|| N::F(n.as_str().parse().unwrap_or(f64::NAN)), N::I),
+        ),
+        _ => None,
+    }
+}
+
+fn input_f64(json: &JSON) -> f64 {
+    num(json).map_or(f64::NAN, N::f64)
+}
+
+// Integral results of float arithmetic come back as Int, 1.5 * 2 is 3
+fn number(f: f64) -> JSON {
+    if f.fract() == 0.0 && f.abs() < 9007199254740992.0 {
+        JSON::Int(f as i64)
+    } else {
+        JSON::Flt(f)
+    }
+}
+
+fn type_name(json: &JSON) -> &'static str {
+    match json {
+        JSON::Int(_) | JSON::Flt(_) | JSON::Num(_) => "number",
+        JSON::Str(_) => "string",
+        JSON::Lst(_) => "array",
+        JSON::Obj(_) => "object",
+        JSON::Bol(_) => "boolean",
+        JSON::Nul => "null",
+    }
+}
+
+// Like jq in its errors: number (1.5), string ("a long str...)
+fn describe(json: &JSON) -> String {
+    let mut text = json.to_string();
+    if text.chars().count() > 11 {
+        text = text.chars().take(10).collect::<String>() + "...";
+    }
+    format!("{} ({text})", type_name(json))
+}
+
+// Strings as they are, the rest as compact JSON
+fn text(json: &JSON) -> String {
+    match json {
+        JSON::Str(s) => s.clone(),
+        _ => json.to_string(),
+    }
+}
+
+fn truthy(json: &JSON) -> bool {
+    !matches!(json, JSON::Nul | JSON::Bol(false))
+}
+
+fn items(json: &JSON) -> Result<Vec<&JSON>, Stop> {
+    match json {
+        JSON::Lst(lst) => Ok(lst.iter().collect()),
+        JSON::Obj(obj) => Ok(obj.values().collect()),
+        _ => fail(format!("Cannot iterate over {}", describe(json))),
+    }
+}
+
+// The order of jq: null < false < true < numbers < strings < arrays < objects
+fn compare(a: &JSON, b: &JSON) -> Ordering {
+    let rank = |json: &JSON| match json {
+        JSON::Nul => 0,
+        JSON::Bol(false) => 1,
+        JSON::Bol(true) => 2,
+        JSON::Int(_) | JSON::Flt(_) | JSON::Num(_) => 3,
+        JSON::Str(_) => 4,
+        JSON::Lst(_) => 5,
+        JSON::Obj(_) => 6,
+    };
+    match (a, b) {
+        (JSON::Str(a), JSON::Str(b)) => a.cmp(b),
+        (JSON::Lst(a), JSON::Lst(b)) => a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| compare(a, b))
+            .find(|ord| ord.is_ne())
+            .unwrap_or(a.len().cmp(&b.len())),
+        (JSON::Obj(a), JSON::Obj(b)) => {
+            // The sorted keys first, then the values in the order of the keys
+            let mut keys_a: Vec<_> = a.keys().collect();
+            let mut keys_b: Vec<_> = b.keys().collect();
+            keys_a.sort();
+            keys_b.sort();
+            keys_a.cmp(&keys_b).then_with(|| {
+                keys_a
+                    .iter()
+                    .filter_map(|k| Some(compare(a.get(k.as_str())?, b.get(k.as_str())?)))
+                    .find(|ord| ord.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            })
+        }
+        _ => match (num(a), num(b)) {
+            (Some(N::I(a)), Some(N::I(b))) => a.cmp(&b),
+            (Some(a), Some(b)) => a.f64().partial_cmp(&b.f64()).unwrap_or(Ordering::Equal),
+            _ => rank(a).cmp(&rank(b)),
+        },
+    }
+}
+
+// The result of an i64 operation, or of the f64 one when it overflows
+fn arith(
+    a: &JSON,
+    b: &JSON,
+    int: fn(i64, i64) -> Option<i64>,
+    flt: fn(f64, f64) -> f64,
+) -> Option<JSON> {
+    match (num(a)?, num(b)?) {
+        (N::I(x), N::I(y)) => {
+            Some(int(x, y).map_or_else(|| number(flt(x as f64, y as f64)), JSON::Int))
+        }
+        (x, y) => Some(number(flt(x.f64(), y.f64()))),
+    }
+}
+
+fn cannot<T>(a: &JSON, b: &JSON, what: &str) -> Result<T, Stop> {
+    fail(format!(
+        "{} and {} cannot be {what}",
+        describe(a),
+        describe(b)
+    ))
+}
+
+fn binop(a: &JSON, op: Op, b: &JSON) -> Result<JSON, Stop> {
+    let out = match op {
+        Op::Add => return add(a, b),
+        Op::Sub => match (a, b) {
+            (JSON::Lst(a), JSON::Lst(b)) => JSON::Lst(
+                a.iter()
+                    .filter(|x| !b.iter().any(|y| compare(x, y).is_eq()))
+                    .cloned()
+                    .collect(),
+            ),
+            _ => match arith(a, b, i64::checked_sub, |x, y| x - y) {
+                Some(diff) => diff,
+                None => return cannot(a, b, "subtracted"),
+            },
+        },
+        Op::Mul => match (a, b) {
+            (JSON::Str(s), n) | (n, JSON::Str(s)) if num(n).is_some() => {
+                // jq gives null for zero times or less
+                let times = input_f64(n);
+                if times.is_nan() || times < 1.0 {
+                    return Ok(JSON::Nul);
+                }
+                match s.len().checked_mul(times as usize) {
+                    Some(len) if len <= MAX_REPEAT_LEN => JSON::Str(s.repeat(times as usize)),
+                    _ => return fail("The repeated string is too long".into()),
+                }
+            }
+            (JSON::Obj(_), JSON::Obj(_)) => merge(a, b),
+            _ => match arith(a, b, i64::checked_mul, |x, y| x * y) {
+                Some(product) => product,
+                None => return cannot(a, b, "multiplied"),
+            },
+        },
+        Op::Div => match (a, b) {
+            (JSON::Str(s), JSON::Str(sep)) => split(s, sep),
+            _ if num(a).is_none() || num(b).is_none() => return cannot(a, b, "divided"),
+            _ if input_f64(b) == 0.0 => return cannot(a, b, "divided because the divisor is zero"),
+            _ => match (num(a), num(b)) {
+                (Some(N::I(x)), Some(N::I(y))) if x.checked_rem(y) == Some(0) => x
+                    .checked_div(y)
+                    .map_or_else(|| number(x as f64 / y as f64), JSON::Int),
+                _ => number(input_f64(a) / input_f64(b)),
+            },
+        },
+        // Like jq, on the integer parts
+        Op::Rem => match (num(a), num(b)) {
+            (Some(x), Some(y)) => {
+                let (x, y) = (x.f64() as i64, y.f64() as i64);
+                if y == 0 {
+                    return cannot(a, b, "divided because the divisor is zero");
+                }
+                JSON::Int(x.checked_rem(y).unwrap_or(0))
+            }
+            _ => return cannot(a, b, "divided"),
+        },
+        Op::Eq => JSON::Bol(compare(a, b).is_eq()),
+        Op::Ne => JSON::Bol(compare(a, b).is_ne()),
+        Op::Lt => JSON::Bol(compare(a, b).is_lt()),
+        Op::Le => JSON::Bol(compare(a, b).is_le()),
+        Op::Gt => JSON::Bol(compare(a, b).is_gt()),
+        Op::Ge => JSON::Bol(compare(a, b).is_ge()),
+    };
+    Ok(out)
+}
+
+fn add(a: &JSON, b: &JSON) -> Result<JSON, Stop> {
+    let sum = match (a, b) {
+        (JSON::Nul, other) | (other, JSON::Nul) => other.clone(),
+        (JSON::Str(a), JSON::Str(b)) => JSON::Str(format!("{a}{b}")),
+        (JSON::Lst(a), JSON::Lst(b)) => JSON::Lst(a.iter().chain(b).cloned().collect()),
+        (JSON::Obj(a), JSON::Obj(b)) => {
+            let mut obj = a.clone();
+            for (k, v) in b {
+                obj.insert(k.clone(), v.clone());
+            }
+            JSON::Obj(obj)
+        }
+        _ => match arith(a, b, i64::checked_add, |x, y| x + y) {
+            Some(sum) => sum,
+            None => return cannot(a, b, "added"),
+        },
+    };
+    Ok(sum)
+}
+
+// Objects merged recursively, for {...} * {...}
+fn merge(a: &JSON, b: &JSON) -> JSON {
+    match (a, b) {
+        (JSON::Obj(a), JSON::Obj(b)) => {
+            let mut obj = a.clone();
+            for (k, v) in b {
+                let merged = match obj.get(k.as_str()) {
+                    Some(old) => merge(old, v),
+                    None => v.clone(),
+                };
+                obj.insert(k.clone(), merged);
+            }
+            JSON::Obj(obj)
+        }
+        _ => b.clone(),
+    }
+}
+
+fn neg(json: &JSON) -> Result<JSON, Stop> {
+    match num(json) {
+        Some(N::I(i)) => Ok(i
+            .checked_neg()
+            .map_or_else(|| number(-(i as f64)), JSON::Int)),
+        Some(N::F(f)) => Ok(JSON::Flt(-f)),
+        None => fail(format!("{} cannot be negated", describe(json))),
+    }
+}
+
+fn split(s: &str, sep: &str) -> JSON {
+    // Like jq, an empty string has no parts at all
+    let parts: Vec<_> = match sep.is_empty() {
+        _ if s.is_empty() => Vec::new(),
+        true => s.chars().map(String::from).collect(),
+        false => s.split(sep).map(String::from).collect(),
+    };
+    JSON::Lst(parts.into_iter().map(JSON::Str).collect())
+}
+
+fn index(json: &JSON, key: &JSON) -> Result<JSON, Stop> {
+    match (json, key) {
+        (JSON::Obj(obj), JSON::Str(key)) => Ok(obj.get(key.as_str()).cloned().unwrap_or(JSON::Nul)),
+        (JSON::Lst(lst), _) if num(key).is_some() => {
+            let i = input_f64(key).floor();
+            let i = if i < 0.0 { i + lst.len() as f64 } else { i };
+            match i >= 0.0 {
+                true => Ok(lst.get(i as usize).cloned().unwrap_or(JSON::Nul)),
+                false => Ok(JSON::Nul),
+            }
+        }
+        (JSON::Nul, JSON::Str(_)) => Ok(JSON::Nul),
+        (JSON::Nul, _) if num(key).is_some() => Ok(JSON::Nul),
+        (_, JSON::Str(key)) => fail(format!("Cannot index {} with \"{key}\"", type_name(json))),
+        _ => fail(format!(
+            "Cannot index {} with {}",
+            type_name(json),
+            type_name(key)
+        )),
+    }
+}
+
+// .[from:to] of a list or a string, null bounds are the ends
+fn slice(json: &JSON, from: &JSON, to: &JSON) -> Result<JSON, Stop> {
+    let len = match json {
+        JSON::Nul => return Ok(JSON::Nul),
+        JSON::Lst(lst) => lst.len(),
+        JSON::Str(s) => s.chars().count(),
+        _ => return fail(format!("Cannot index {} with object", type_name(json))),
+    };
+    let bound = |b: &JSON, default: usize, round: fn(f64) -> f64| -> Result<usize, Stop> {
+        match b {
+            JSON::Nul => Ok(default),
+            _ if num(b).is_some() => {
+                let i = round(input_f64(b));
+                let i = if i < 0.0 { i + len as f64 } else { i };
+                Ok(i.clamp(0.0, len as f64) as usize)
+            }
+            _ => fail("Start and end indices of a slice must be numbers".into()),
+        }
+    };
+    let from = bound(from, 0, f64::floor)?;
+    let to = bound(to, len, f64::ceil)?.max(from);
+    Ok(match json {
+        JSON::Lst(lst) => JSON::Lst(lst[from..to].to_vec()),
+        JSON::Str(s) => JSON::Str(s.chars().skip(from).take(to - from).collect()),
+        _ => JSON::Nul,
+    })
+}
+
+fn range(from: &JSON, to: &JSON, emit: &mut Emit) -> Result<(), Stop> {
+    match (num(from), num(to)) {
+        (Some(N::I(from)), Some(N::I(to))) => (from..to).try_for_each(|i| emit(JSON::Int(i))),
+        (Some(from), Some(to)) => {
+            let mut i = from.f64();
+            while i < to.f64() {
+                emit(number(i))?;
+                i += 1.0;
+            }
+            Ok(())
+        }
+        _ => fail("Range bounds must be numeric".into()),
+    }
+}
+
+fn entries(json: &JSON) -> Result<Vec<JSON>, Stop> {
+    let JSON::Obj(obj) = json else {
+        return fail(format!("{} has no keys", describe(json)));
+    };
+    Ok(obj
+        .iter()
+        .map(|(k, v)| {
+            let mut entry = Map::new();
+            entry.insert("key".to_string(), JSON::Str(k.clone()));
+            entry.insert("value".to_string(), v.clone());
+            JSON::Obj(entry)
+        })
+        .collect())
+}
+
+// The names jq takes for the keys and the values of the entries
+fn from_entries(json: &JSON) -> Result<JSON, Stop> {
+    let mut obj = Map::new();
+    for entry in items(json)? {
+        let JSON::Obj(entry) = entry else {
+            return fail(format!("Cannot index {} with \"key\"", type_name(entry)));
+        };
+        let found = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| entry.get(*name).filter(|v| !matches!(v, JSON::Nul)))
+        };
+        let key = match found(&["key", "k", "name", "Name", "K", "Key"]) {
+            Some(JSON::Str(key)) => key.clone(),
+            Some(key @ (JSON::Int(_) | JSON::Flt(_) | JSON::Num(_) | JSON::Bol(_))) => {
+                key.to_string()
+            }
+            Some(key) => return fail(format!("Cannot use {} as object key", describe(key))),
+            None => return fail("Cannot use null (null) as object key".into()),
+        };
+        let val = ["value", "v", "Value", "V"]
+            .iter()
+            .find_map(|name| entry.get(*name))
+            .cloned()
+            .unwrap_or(JSON::Nul);
+        obj.insert(key, val);
+    }
+    Ok(JSON::Obj(obj))
+}
+
+fn flatten(json: &JSON, depth: f64) -> Result<JSON, Stop> {
+    let JSON::Lst(lst) = json else {
+        return fail(format!("Cannot flatten {}", describe(json)));
+    };
+    let mut out = Vec::new();
+    for item in lst {
+        match item {
+            JSON::Lst(_) if depth > 0.0 => match flatten(item, depth - 1.0)? {
+                JSON::Lst(inner) => out.extend(inner),
+                other => out.push(other),
+            },
+            _ => out.push(item.clone()),
+        }
+    }
+    Ok(JSON::Lst(out))
+}
+
+// sort_by() and its friends, on a list sorted by the keys already
+fn sorted_by(name: &str, keyed: Vec<(JSON, &JSON)>) -> JSON {
+    let mut groups: Vec<(JSON, Vec<JSON>)> = Vec::new();
+    for (key, item) in keyed {
+        match groups.last_mut() {
+            Some((last, group)) if compare(last, &key).is_eq() => group.push(item.clone()),
+            _ => groups.push((key, vec![item.clone()])),
+        }
+    }
+    let mut firsts = groups.iter().map(|(_, group)| group[0].clone());
+    match name {
+        "group_by" => JSON::Lst(groups.into_iter().map(|(_, g)| JSON::Lst(g)).collect()),
+        "unique_by" => JSON::Lst(firsts.collect()),
+        "min_by" => firsts.next().unwrap_or(JSON::Nul),
+        "max_by" => groups
+            .last()
+            .and_then(|(_, group)| group.last().cloned())
+            .unwrap_or(JSON::Nul),
+        _ => JSON::Lst(groups.into_iter().flat_map(|(_, g)| g).collect()),
+    }
+}
+
+// None when the types differ
+fn contains(a: &JSON, b: &JSON) -> Option<bool> {
+    match (a, b) {
+        (JSON::Obj(a), JSON::Obj(b)) => Some(b.iter().all(|(k, vb)| {
+            a.get(k.as_str())
+                .is_some_and(|va| contains(va, vb) == Some(true))
+        })),
+        (JSON::Lst(a), JSON::Lst(b)) => Some(
+            b.iter()
+                .all(|vb| a.iter().any(|va| contains(va, vb) == Some(true))),
+        ),
+        (JSON::Str(a), JSON::Str(b)) => Some(a.contains(b.as_str())),
+        _ if type_name(a) == type_name(b) => Some(compare(a, b).is_eq()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::to_canonical_string;
+
+    fn doc() -> JSON {
+        parse(
+            r#"{"items": [
+                {"name": "pen", "price": 1.5, "tags": ["office"]},
+                {"name": "book", "price": 12, "tags": ["office", "read"]},
+                {"name": "cup", "price": 4, "tags": []}
+            ], "owner": {"first": "Ada", "last": "Lovelace"}}"#,
+        )
+        .unwrap()
+    }
+
+    // The outputs in canonical form, joined by spaces
+    fn run_on(input: &JSON, program: &str) -> String {
+        let out = input
+            .jq(program)
+            .unwrap_or_else(|err| panic!("{program}: {err}"));
+        let out: Vec<_> = out
+            .iter()
+            .map(|v| to_canonical_string(v).unwrap())
+            .collect();
+        out.join(" ")
+    }
+
+    fn run(program: &str) -> String {
+        run_on(&doc(), program)
+    }
+
+    fn run_err(program: &str) -> String {
+        match doc().jq(program) {
+            Ok(out) => panic!("{program}: expected an error, got {out:?}"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_jq_paths() {
+        assert_eq!(run(".owner.first"), r#""Ada""#);
+        assert_eq!(run(".items[].name"), r#""pen" "book" "cup""#);
+        assert_eq!(run(".items | map(.price * 2)"), "[3,24,8]");
+        assert_eq!(
+            run(".items | map(select(.price < 10) | {name, cost: .price})"),
+            r#"[{"cost":1.5,"name":"pen"},{"cost":4,"name":"cup"}]"#
+        );
+        assert_eq!(
+            run(r#""\(.owner.first) \(.owner.last | ascii_upcase)!""#),
+            r#""Ada LOVELACE!""#
+        );
+        assert_eq!(
+            run("[range(5)] | .[2:], .[:-3], .[-1], (. | contains([1, 4]))"),
+            "[2,3,4] [0,1] 4 true"
+        );
+        assert_eq!(run(r#"[.. | select(type == "number")] | length"#), "3");
+        assert_eq!(
+            run(r#"{(.owner.first): 1, "k\(1 + 1)": [.items[].price] | max}"#),
+            r#"{"Ada":1,"k2":12}"#
+        );
+        assert_eq!(run("1 as $x | $x, {$x}"), r#"1 {"x":1}"#);
+        assert_eq!(
+            Jq::compile("").unwrap().run(&JSON::Int(1)).unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_jq_arithmetic() {
+        assert_eq!(
+            run("1 + 2 * 3 - 8 / 4 % 3, -(1 + 1), 7 / 2, 10 / 5"),
+            "5 -2 3.5 2"
+        );
+        assert_eq!(run("(1, 2) + (10, 20)"), "11 12 21 22");
+        assert_eq!(
+            run(r#"[1, 2, 3, 2] - [2], {"a": {"b": 1}} * {"a": {"c": 2}}"#),
+            r#"[1,3] {"a":{"b":1,"c":2}}"#
+        );
+        // % works on the integer parts and keeps the sign of the dividend
+        assert_eq!(
+            run("5 % -2, -5 % 2, -5 % -2, 5.5 % 2, -9223372036854775808 % -1"),
+            "1 -1 -1 1 0"
+        );
+        let zero = "cannot be divided because the divisor is zero";
+        assert!(run_err("5 % 0").ends_with(zero));
+        assert!(run_err("5 % 0.5").ends_with(zero));
+        assert!(run_err("1 / 0").ends_with(zero));
+        assert!(run_err("0 / 0").ends_with(zero));
+    }
+
+    #[test]
+    fn test_jq_string_ops() {
+        // Repeating, null for zero times or less
+        assert_eq!(
+            run(r#""ab" * 3, 2 * "ab", "ab" * 1.5, "ab" * 0, "ab" * -1"#),
+            r#""ababab" "abab" "ab" null null"#
+        );
+        assert_eq!(run_err(r#""x" * 1e15"#), "The repeated string is too long");
+        assert_eq!(run_err(r#""x" * 1e300"#), "The repeated string is too long");
+        // Dividing strings splits them
+        assert_eq!(
+            run(r#""a,b" / ",", "" / ",", "abc" / "", ",a," / ",""#),
+            r#"["a","b"] [] ["a","b","c"] ["","a",""]"#
+        );
+        assert_eq!(
+            run_err("[1] * 2"),
+            "array ([1]) and number (2) cannot be multiplied"
+        );
+        assert_eq!(
+            run_err("[1] * [2]"),
+            "array ([1]) and array ([2]) cannot be multiplied"
+        );
+        assert_eq!(
+            run_err(r#""a" / 1"#),
+            r#"string ("a") and number (1) cannot be divided"#
+        );
+        assert_eq!(
+            run_err("[1] / [1]"),
+            "array ([1]) and array ([1]) cannot be divided"
+        );
+    }
+
+    #[test]
+    fn test_jq_control() {
+        assert_eq!(
+            run("if .items | length > 2 then \"many\" elif .items then \"few\" else \"none\" end"),
+            r#""many""#
+        );
+        assert_eq!(
+            run(r#"(.nope // "default"), (null // false // 3), ([.owner[]] | sort)"#),
+            r#""default" 3 ["Ada","Lovelace"]"#
+        );
+        // The errors on the left of // are swallowed like false and null
+        assert_eq!(
+            run(r#"error("x") // 1, ((1, error("x")) // 2), (.owner.first.x // 3)"#),
+            "1 1 3"
+        );
+        assert_eq!(
+            run(
+                r#"try error("boom") catch ., (.owner.first.x)?, [limit(3; range(10))], first(range(5; 9))"#
+            ),
+            r#""boom" [0,1,2] 5"#
+        );
+        // ? on an iterator keeps what came before the error and stops there
+        let input = parse(r#"["1", "x", "2"]"#).unwrap();
+        assert_eq!(run_on(&input, "[.[] | tonumber?]"), "[1,2]");
+        assert_eq!(run_on(&input, "[(.[] | tonumber)?]"), "[1]");
+        assert_eq!(
+            run_on(&input, r#"[.[] | try tonumber catch "bad"]"#),
+            r#"[1,"bad",2]"#
+        );
+        assert_eq!(run_on(&input, "[.[][]?], [1 | .[]?]"), "[] []");
+
+        assert_eq!(run("reduce .items[] as $i (0; . + $i.price)"), "17.5");
+        assert_eq!(
+            run("[foreach .items[] as $i (0; . + 1; [$i.name, .])]"),
+            r#"[["pen",1],["book",2],["cup",3]]"#
+        );
+        // Several outputs: each init starts a reduce of its own, the last
+        // update is the state, and foreach emits every update and extract
+        assert_eq!(run("reduce (1, 2) as $x (0, 10; . + $x)"), "3 13");
+        assert_eq!(run("reduce range(3) as $x (0; . + 1, . + 10)"), "30");
+        assert_eq!(
+            run("reduce (1, 2) as $x (0; empty), reduce empty as $x (0; 1)"),
+            "null 0"
+        );
+        assert_eq!(
+            run("[foreach (1, 2) as $x (0; . + $x, . - $x)]"),
+            "[1,-1,1,-3]"
+        );
+        assert_eq!(
+            run("[foreach (1, 2) as $x (0; . + $x; ., -.)]"),
+            "[1,-1,3,-3]"
+        );
+        // label and break are not part of the supported language
+        let err = Jq::compile("label $out | 1, break $out").unwrap_err();
+        assert!(matches!(err, JqErr::Syntax { column: 1, .. }), "{err}");
+    }
+
+    #[test]
+    fn test_jq_builtins() {
+        assert_eq!(
+            run(".owner | to_entries | map(.key) | sort"),
+            r#"["first","last"]"#
+        );
+        assert_eq!(
+            run(".owner | with_entries({key: .value, value: .key}) | keys"),
+            r#"["Ada","Lovelace"]"#
+        );
+        assert_eq!(
+            run(r#".owner | with_entries(select(.key == "last"))"#),
+            r#"{"last":"Lovelace"}"#
+        );
+        assert_eq!(run("[.items[] | .tags | length] | add"), "3");
+        assert_eq!(
+            run("[.items[].tags[]] | unique | join(\", \")"),
+            r#""office, read""#
+        );
+        assert_eq!(
+            run(".items | sort_by(.price) | map(.name) | .[1:]"),
+            r#"["cup","book"]"#
+        );
+        assert_eq!(
+            run(".items | group_by(.tags | length) | map(length)"),
+            "[1,1,1]"
+        );
+        assert_eq!(run(".items | max_by(.price).name"), r#""book""#);
+        assert_eq!(
+            run(r#""a,b,c" | split(",") | map(ltrimstr("a")) | tojson"#),
+            r#""[\"\",\"b\",\"c\"]""#
+        );
+        assert_eq!(
+            run("[1, [2, [3]]] | flatten, flatten(1)"),
+            "[1,2,3] [1,2,[3]]"
+        );
+        assert_eq!(
+            run("\"3.5\" | tonumber | floor, (\"[1]\" | fromjson)"),
+            "3 [1]"
+        );
+        assert_eq!(
+            run("[1, null, \"a\", [], {}, false] | sort | map(type)"),
+            r#"["null","boolean","number","string","array","object"]"#
+        );
+    }
+
+    #[test]
+    fn test_jq_errors() {
+        let err = Jq::compile(".a | map(").unwrap_err();
+        assert!(matches!(err, JqErr::Syntax { column: 10, .. }), "{err}");
+        assert!(Jq::compile("$x").is_err() && Jq::compile("nope(1)").is_err());
+        assert_eq!(
+            run_err(".owner.first + 1"),
+            r#"string ("Ada") and number (1) cannot be added"#
+        );
+        assert!(doc().jq(".items[] | .name[0]").is_err());
+
+        let cases = [
+            ("1 | keys", "number (1) can't be used with keys"),
+            ("1 | to_entries", "number (1) has no keys"),
+            ("1 | with_entries(.)", "number (1) has no keys"),
+            ("[1] | from_entries", "Cannot index number with \"key\""),
+            (
+                "{} | has(0)",
+                "object ({}) can't be used with has(number (0))",
+            ),
+            (
+                "[] | has(\"a\")",
+                "array ([]) can't be used with has(string (\"a\"))",
+            ),
+            ("true | length", "boolean (true) can't be used with length"),
+            (
+                "1 | ascii_downcase",
+                "number (1) can't be used with ascii_downcase",
+            ),
+            (
+                "1 | split(\",\")",
+                "number (1) can't be used with split(string (\",\"))",
+            ),
+            (
+                "1 | startswith(\"a\")",
+                "number (1) can't be used with startswith(string (\"a\"))",
+            ),
+            ("\"a\" | floor", "string (\"a\") can't be used with floor"),
+            ("{} | sort", "object ({}) can't be used with sort"),
+            ("1 | min", "number (1) can't be used with min"),
+            ("1 | unique", "number (1) can't be used with unique"),
+            (
+                "1 | group_by(.)",
+                "Cannot sort number (1), as it is not an array",
+            ),
+            ("1 | flatten", "Cannot flatten number (1)"),
+            ("[1] | flatten(-1)", "flatten depth must not be negative"),
+            (
+                "\"x\" | fromjson",
+                "Line[1], Char[1]: Expected a JSON value. (while parsing 'x')",
+            ),
+            ("[1, [2]] | join(\",\")", "Cannot join with array ([2])"),
+            (
+                "\"x\" | tonumber",
+                "Cannot parse string (\"x\") as a number",
+            ),
+            (
+                "1 | contains(\"a\")",
+                "number (1) and string (\"a\") cannot have their containment checked",
+            ),
+            ("1 | add", "Cannot iterate over number (1)"),
+            ("1 | map(.)", "Cannot iterate over number (1)"),
+            ("1 | .[0]", "Cannot index number with number"),
+            ("{} | .[0]", "Cannot index object with number"),
+            ("[] | .a", "Cannot index array with \"a\""),
+            ("range(\"a\")", "Range bounds must be numeric"),
+            ("error(\"boom\")", "boom"),
+            ("{} | error", "{}"),
+        ];
+        for (program, msg) in cases {
+            assert_eq!(run_err(program), msg, "{program}");
+        }
+    }
+
+    #[test]
+    fn test_jq_recurse_depth() {
+        assert_eq!(run("[limit(4; 0 | recurse(. + 1))]"), "[0,1,2,3]");
+        assert_eq!(
+            run("[1 | recurse(if . < 300 then . + 1 else empty end)] | length"),
+            "300"
+        );
+        assert_eq!(run_err("0 | recurse(. + 1)"), "recurse() went too deep");
+        // .. walks without recursion, however deep the input
+        let mut deep = JSON::Int(1);
+        for _ in 0..1000 {
+            deep = JSON::Lst(vec![deep]);
+        }
+        assert_eq!(run_on(&deep, "[..] | length"), "1001");
+        assert_eq!(
+            deep.jq("[recurse(.[])] | length").unwrap_err().to_string(),
+            "recurse() went too deep"
+        );
+    }
+
+    #[test]
+    fn test_jq_program_depth() {
+        assert_eq!(run(&format!("[{}] | length", ["1"; 60].join(", "))), "60");
+        let nested = format!("{}1{}", "[".repeat(60), "]".repeat(60));
+        assert_eq!(run(&nested), nested);
+        let deep = [
+            ".a".repeat(10000),
+            format!("1{}", " + 1".repeat(10000)),
+            format!("{}1", "-".repeat(50000)),
+            format!("{}.", "try ".repeat(10000)),
+            format!("null{}", " // null".repeat(10000)),
+            format!("{}.{}", "(".repeat(10000), ")".repeat(10000)),
+            format!("{}1{}", "(1 + ".repeat(1000), ")".repeat(1000)),
+        ];
+        for program in deep {
+            match Jq::compile(&program) {
+                Err(JqErr::Syntax { msg, .. }) => assert_eq!(msg, "The program is nested too deep"),
+                other => panic!("Expected a syntax error, got {other:?}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,1318 @@
+use core::fmt;
+use std::str::FromStr;
+
+use super::iregexp::Regex;
+use super::number::check_number;
+use super::JSON;
+
+// Filters and parentheses can't nest deeper than this
+const MAX_NESTING: usize = 64;
+
+// Indices and slices stay in the I-JSON range of integers, ±(2^53 - 1)
+const MAX_INT: i64 = (1 << 53) - 1;
+
+// A compiled JSONPath query (RFC 9535), like "$.store.book[?@.price < 10].title".
+// It supports:
+//  - the root `$`, `.name`, `['name']`, `[0]`, `[-1]`, `.*` and `[*]`
+//  - descendants with `..name`, `..*` and `..[...]`
+//  - slices `[start:end:step]` and unions `[0, 'a', 1:3]`
+//  - filters `[?@.price < 10 && !@.sold]` with ==, !=, <, <=, >, >=, ||, &&, !
+//    and the functions length(), count(), match(), search() and value()
+// The patterns of match() and search() have size limits, see iregexp.rs.
+#[derive(Debug)]
+pub struct JsonPath {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug)]
+enum Segment {
+    Child(Vec<Selector>),
+    Descendant(Vec<Selector>),
+}
+
+#[derive(Debug)]
+enum Selector {
+    Name(String),
+    Wildcard,
+    Index(i64),
+    Slice(Option<i64>, Option<i64>, Option<i64>),
+    Filter(Expr),
+}
+
+// A query inside a filter, from the root `$` or from the current node `@`
+#[derive(Debug)]
+struct Query {
+    root: bool,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug)]
+enum Expr {
+    Or(Vec<Expr>),
+    And(Vec<Expr>),
+    Not(Box<Expr>),
+    Exists(Query), // True when the query finds some node
+    Call(Call),    // A function giving a logical value, like match()
+    Cmp(Box<(Operand, Op, Operand)>),
+}
+
+#[derive(Debug)]
+enum Operand {
+    Lit(JSON),
+    Query(Query), // Only singular queries, they give one node at most
+    Call(Call),
+}
+
+#[derive(Debug)]
+struct Call {
+    func: Func,
+    args: Vec<Arg>,
+    // The pattern of match() or search() when it is a string literal,
+    // compiled once. None too when it is no I-Regexp, the call is false then.
+    regex: Option<Regex>,
+}
+
+#[derive(Debug)]
+enum Arg {
+    Lit(JSON),
+    Query(Query),
+    Call(Call),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Func {
+    Length,
+    Count,
+    Match,
+    Search,
+    Value,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+// The types of the function extension of RFC 9535
+#[derive(Clone, Copy, PartialEq)]
+enum Type {
+    Value,
+    Logical,
+    Nodes,
+}
+
+impl Func {
+    fn params(self) -> &'static [Type] {
+        match self {
+            Func::Length => &[Type::Value],
+            Func::Count | Func::Value => &[Type::Nodes],
+            Func::Match | Func::Search => &[Type::Value, Type::Value],
+        }
+    }
+
+    fn result(self) -> Type {
+        match self {
+            Func::Length | Func::Count | Func::Value => Type::Value,
+            Func::Match | Func::Search => Type::Logical,
+        }
+    }
+}
+
+impl Query {
+    // Only names and indices without `..`, so at most one node
+    fn singular(&self) -> bool {
+        self.segments.iter().all(|seg| {
+            matches!(seg, Segment::Child(sels)
+                if matches!(sels[..], [Selector::Name(_) | Selector::Index(_)]))
+        })
+    }
+}
+
+// Where a node is in the document, like $['store']['book'][0]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NormalizedPath(Vec<PathElem>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathElem {
+    Key(String),
+    Index(usize),
+}
+
+impl NormalizedPath {
+    pub fn elems(&self) -> &[PathElem] {
+        &self.0
+    }
+
+    // The same place as a JSON Pointer, for JSON::pointer() and the others
+    pub fn to_pointer(&self) -> String {
+        let mut ptr = String::new();
+        for elem in &self.0 {
+            ptr.push('/');
+            match elem {
+                PathElem::Key(key) => ptr.push_str(&key.replace('~', "~0").replace('/', "~1")),
+                PathElem::Index(i) => ptr.push_str(&i.to_string()),
+            }
+        }
+        ptr
+    }
+
+    fn with(&self, elem: PathElem) -> NormalizedPath {
+        let mut path = self.clone();
+        path.0.push(elem);
+        path
+    }
+}
+
+// The normalized form of RFC 9535, the keys in single quotes
+impl fmt::Display for NormalizedPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("$")?;
+        for elem in &self.0 {
+            match elem {
+                PathElem::Index(i) => write!(f, "[{i}]")?,
+                PathElem::Key(key) => {
+                    f.write_str("['")?;
+                    for ch in key.chars() {
+                        match ch {
+                            '\u{0008}' => f.write_str("\\b")?,
+                            '\u{000C}' => f.write_str("\\f")?,
+                            '\n' => f.write_str("\\n")?,
+                            '\r' => f.write_str("\\r")?,
+                            '\t' => f.write_str("\\t")?,
+                            '\'' => f.write_str("\\'")?,
+                            '\\' => f.write_str("\\\\")?,
+                            '\u{0000}'..='\u{001F}' => write!(f, "\\u{:04x}", ch as u32)?,
+                            _ => write!(f, "{ch}")?,
+                        }
+                    }
+                    f.write_str("']")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// What is wrong in a JSONPath query, and where
+#[derive(Debug)]
+pub struct PathErr {
+    ch_pos: usize,
+    msg: &'static str,
+}
+
+impl PathErr {
+    // Character of the error in the query, starting from 1
+    pub fn column(&self) -> usize {
+        self.ch_pos
+    }
+
+    pub fn msg(&self) -> &str {
+        self.msg
+    }
+}
+
+impl fmt::Display for PathErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Char[{}]: {}", self.ch_pos, self.msg)
+    }
+}
+
+impl std::error::Error for PathErr {}
+
+impl JsonPath {
+    pub fn parse(path: &str) -> Result<JsonPath, PathErr> {
+        let mut p = Parser {
+            chars: path.chars().collect(),
+            pos: 0,
+            depth: 0,
+        };
+        if !p.eat('$') {
+            return p.err("A JSONPath query begins with '$'");
+        }
+        let segments = p.segments()?;
+        if p.pos < p.chars.len() {
+            return p.err("Unexpected character in the query");
+        }
+        Ok(JsonPath { segments })
+    }
+
+    // The nodes found with their paths, in the order of RFC 9535. A node can
+    // come more than once, like with `$[0, 0]`.
+    pub fn query<'a>(&self, json: &'a JSON) -> Vec<(NormalizedPath, &'a JSON)> {
+        select(
+            &self.segments,
+            json,
+            vec![(NormalizedPath::default(), json)],
+        )
+    }
+}
+
+impl FromStr for JsonPath {
+    type Err = PathErr;
+
+    fn from_str(path: &str) -> Result<JsonPath, PathErr> {
+        JsonPath::parse(path)
+    }
+}
+
+impl JSON {
+    // Run a JSONPath query on this document, see JsonPath
+    pub fn query(&self, path: &str) -> Result<Vec<(NormalizedPath, &JSON)>, PathErr> {
+        Ok(JsonPath::parse(path)?.query(self))
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn err<T>(&self, msg: &'static str) -> Result<T, PathErr> {
+        Err(PathErr {
+            ch_pos: self.pos + 1,
+            msg,
+        })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, ch: char) -> bool {
+        let found = self.peek() == Some(ch);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_str(&mut self, s: &str) -> bool {
+        let found = s
+            .chars()
+            .enumerate()
+            .all(|(i, ch)| self.chars.get(self.pos + i) == Some(&ch));
+        if found {
+            self.pos += s.chars().count();
+        }
+        found
+    }
+
+    fn space(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn nest(&mut self) -> Result<(), PathErr> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            return self.err("The query is nested too deep");
+        }
+        Ok(())
+    }
+
+    // The segments after `$` or `@`, whitespace is allowed before each one
+    fn segments(&mut self) -> Result<Vec<Segment>, PathErr> {
+        let mut segments = Vec::new();
+        loop {
+            let before = self.pos;
+            self.space();
+            if self.eat_str("..") {
+                let sels = match self.peek() {
+                    Some('[') => self.bracketed()?,
+                    _ => vec![self.shorthand()?],
+                };
+                segments.push(Segment::Descendant(sels));
+            } else if self.eat('.') {
+                segments.push(Segment::Child(vec![self.shorthand()?]));
+            } else if self.peek() == Some('[') {
+                segments.push(Segment::Child(self.bracketed()?));
+            } else {
+                self.pos = before;
+                return Ok(segments);
+            }
+        }
+    }
+
+    // `*` or a member name after `.` or `..`
+    fn shorthand(&mut self) -> Result<Selector, PathErr> {
+        if self.eat('*') {
+            return Ok(Selector::Wildcard);
+        }
+        let first = |ch: char| ch.is_ascii_alphabetic() || ch == '_' || !ch.is_ascii();
+        if !self.peek().is_some_and(first) {
+            return self.err("Expected a member name or '*'");
+        }
+        let mut name = String::new();
+        while let Some(ch) = self.peek().filter(|ch| first(*ch) || ch.is_ascii_digit()) {
+            name.push(ch);
+            self.pos += 1;
+        }
+        Ok(Selector::Name(name))
+    }
+
+    fn bracketed(&mut self) -> Result<Vec<Selector>, PathErr> {
+        self.pos += 1; // '['
+        let mut sels = Vec::new();
+        loop {
+            self.space();
+            sels.push(self.selector()?);
+            self.space();
+            if self.eat(']') {
+                return Ok(sels);
+            }
+            if !self.eat(',') {
+                return self.err("Expected ',' or ']' after the selector");
+            }
+        }
+    }
+
+    fn selector(&mut self) -> Result<Selector, PathErr> {
+        match self.peek() {
+            Some('\'' | '"') => Ok(Selector::Name(self.string()?)),
+            Some('*') => {
+                self.pos += 1;
+                Ok(Selector::Wildcard)
+            }
+            Some('?') => {
+                self.pos += 1;
+                self.space();
+                Ok(Selector::Filter(self.logical()?))
+            }
+            Some('-' | '0'..='9' | ':') => self.index_or_slice(),
+            _ => self.err("Expected a name, an index, a slice, '*' or a filter"),
+        }
+    }
+
+    fn index_or_slice(&mut self) -> Result<Selector, PathErr> {
+        let start = match self.peek() {
+            Some(':') => None,
+            _ => Some(self.int()?),
+        };
+        self.space();
+        if !self.eat(':') {
+            // Checked by selector(), there is a start here
+            return Ok(Selector::Index(start.unwrap_or_default()));
+        }
+        self.space();
+        let end = self.opt_int()?;
+        self.space();
+        let step = if self.eat(':') {
+            self.space();
+            self.opt_int()?
+        } else {
+            None
+        };
+        Ok(Selector::Slice(start, end, step))
+    }
+
+    fn opt_int(&mut self) -> Result<Option<i64>, PathErr> {
+        match self.peek() {
+            Some('-' | '0'..='9') => Ok(Some(self.int()?)),
+            _ => Ok(None),
+        }
+    }
+
+    // 0 or an integer without leading zeros, -0 is not one
+    fn int(&mut self) -> Result<i64, PathErr> {
+        let from = self.pos;
+        let neg = self.eat('-');
+        match self.peek() {
+            Some('0') if neg => return self.err("-0 is not a valid index"),
+            Some('0') => {
+                self.pos += 1;
+                if self.peek().is_some_and(|ch| ch.is_ascii_digit()) {
+                    return self.err("Leading zeros are not allowed");
+                }
+                return Ok(0);
+            }
+            Some('1'..='9') => {}
+            _ => return self.err("Expected a digit"),
+        }
+        while self.peek().is_some_and(|ch| ch.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let text: String = self.chars[from..self.pos].iter().collect();
+        match text.parse::<i64>() {
+            Ok(int) if int.abs() <= MAX_INT => Ok(int),
+            _ => {
+                self.pos = from;
+                self.err("The integer is out of the range ±(2^53 - 1)")
+            }
+        }
+    }
+
+    // A string literal in single or double quotes
+    fn string(&mut self) -> Result<String, PathErr> {
+        let quote = self.chars[self.pos];
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let Some(ch) = self.peek() else {
+                return self.err("The string is not closed");
+            };
+            self.pos += 1;
+            match ch {
+                _ if ch == quote => return Ok(out),
+                '\\' => out.push(self.escape(quote)?),
+                '\u{0000}'..='\u{001F}' => {
+                    self.pos -= 1;
+                    return self.err("Control characters must be escaped in strings");
+                }
+                _ => out.push(ch),
+            }
+        }
+    }
+
+    fn escape(&mut self, quote: char) -> Result<char, PathErr> {
+        let ch = match self.peek() {
+            Some('b') => '\u{0008}',
+            Some('f') => '\u{000C}',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some(ch @ ('/' | '\\')) => ch,
+            Some(ch) if ch == quote => ch,
+            Some('u') => {
+                self.pos += 1;
+                let high = self.hex4()?;
+                let code = match high {
+                    0xDC00..=0xDFFF => return self.err("Lone low surrogate"),
+                    0xD800..=0xDBFF => {
+                        if !self.eat_str("\\u") {
+                            return self.err("Expected a low surrogate \\uDC00-\\uDFFF");
+                        }
+                        let low = self.hex4()?;
+                        if !(0xDC00..=0xDFFF).contains(&low) {
+                            return self.err("Expected a low surrogate \\uDC00-\\uDFFF");
+                        }
+                        0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                    }
+                    _ => high,
+                };
+                // Surrogates are out, any other code is a char
+                return Ok(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+            }
+            _ => return self.err("Invalid escape in the string"),
+        };
+        self.pos += 1;
+        Ok(ch)
+    }
+
+    fn hex4(&mut self) -> Result<u32, PathErr> {
+        let hex: String = self.chars.iter().skip(self.pos).take(4).collect();
+        if hex.len() != 4 || !hex.chars().all(|ch| ch.is_ascii_hexdigit()) {
+            return self.err("Expected 4 hex digits after \\u");
+        }
+        self.pos += 4;
+        u32::from_str_radix(&hex, 16).or_else(|_| self.err("Expected 4 hex digits after \\u"))
+    }
+
+    fn logical(&mut self) -> Result<Expr, PathErr> {
+        self.nest()?;
+        let mut ors = vec![self.and()?];
+        loop {
+            let before = self.pos;
+            self.space();
+            if !self.eat_str("||") {
+                self.pos = before;
+                break;
+            }
+            self.space();
+            ors.push(self.and()?);
+        }
+        self.depth -= 1;
+        Ok(match ors.len() {
+            1 => ors.remove(0),
+            _ => Expr::Or(ors),
+        })
+    }
+
+    fn and(&mut self) -> Result<Expr, PathErr> {
+        let mut ands = vec![self.basic()?];
+        loop {
+            let before = self.pos;
+            self.space();
+            if !self.eat_str("&&") {
+                self.pos = before;
+                break;
+            }
+            self.space();
+            ands.push(self.basic()?);
+        }
+        Ok(match ands.len() {
+            1 => ands.remove(0),
+            _ => Expr::And(ands),
+        })
+    }
+
+    fn basic(&mut self) -> Result<Expr, PathErr> {
+        if self.eat('!') {
+            self.space();
+            let at = self.pos;
+            let expr = match self.peek() {
+                Some('(') => self.paren()?,
+                _ => match self.operand()? {
+                    Operand::Query(query) => Expr::Exists(query),
+                    Operand::Call(call) => self.test_call(call, at)?,
+                    Operand::Lit(_) => {
+                        self.pos = at;
+                        return self.err("A literal can't be tested, only compared");
+                    }
+                },
+            };
+            return Ok(Expr::Not(Box::new(expr)));
+        }
+        if self.peek() == Some('(') {
+            return self.paren();
+        }
+
+        let at = self.pos;
+        let left = self.operand()?;
+        let before = self.pos;
+        self.space();
+        let Some(op) = self.op() else {
+            self.pos = before;
+            return match left {
+                Operand::Query(query) => Ok(Expr::Exists(query)),
+                Operand::Call(call) => self.test_call(call, at),
+                Operand::Lit(_) => {
+                    self.pos = at;
+                    self.err("A literal can't be tested, only compared")
+                }
+            };
+        };
+        self.space();
+        let right_at = self.pos;
+        let right = self.operand()?;
+        self.comparable(&left, at)?;
+        self.comparable(&right, right_at)?;
+        Ok(Expr::Cmp(Box::new((left, op, right))))
+    }
+
+    fn paren(&mut self) -> Result<Expr, PathErr> {
+        self.pos += 1; // '('
+        self.space();
+        let expr = self.logical()?;
+        self.space();
+        if !self.eat(')') {
+            return self.err("Expected ')'");
+        }
+        Ok(expr)
+    }
+
+    fn op(&mut self) -> Option<Op> {
+        let ops = [
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ];
+        ops.into_iter()
+            .find(|(s, _)| self.eat_str(s))
+            .map(|(_, op)| op)
+    }
+
+    // A function alone in a filter must give a logical value or nodes
+    fn test_call(&mut self, call: Call, at: usize) -> Result<Expr, PathErr> {
+        if call.func.result() == Type::Value {
+            self.pos = at;
+            return self.err("This function gives a value, compare it with something");
+        }
+        Ok(Expr::Call(call))
+    }
+
+    fn comparable(&mut self, operand: &Operand, at: usize) -> Result<(), PathErr> {
+        let ok = match operand {
+            Operand::Lit(_) => true,
+            Operand::Query(query) => query.singular(),
+            Operand::Call(call) => call.func.result() == Type::Value,
+        };
+        if !ok {
+            self.pos = at;
+            return self.err("Only literals, singular queries and value functions can be compared");
+        }
+        Ok(())
+    }
+
+    // A literal, a query or a function call
+    fn operand(&mut self) -> Result<Operand, PathErr> {
+        match self.peek() {
+            Some(root @ ('$' | '@')) => {
+                self.pos += 1;
+                let segments = self.segments()?;
+                Ok(Operand::Query(Query {
+                    root: root == '$',
+                    segments,
+                }))
+            }
+            Some('\'' | '"') => Ok(Operand::Lit(JSON::Str(self.string()?))),
+            Some('-' | '0'..='9') => Ok(Operand::Lit(self.number()?)),
+            Some('a'..='z') => {
+                let at = self.pos;
+                let mut name = String::new();
+                while let Some(ch) = self
+                    .peek()
+                    .filter(|ch| ch.is_ascii_lowercase() || ch.is_ascii_digit() || *ch == '_')
+                {
+                    name.push(ch);
+                    self.pos += 1;
+                }
+                if self.peek() == Some('(') {
+                    return Ok(Operand::Call(self.call(&name, at)?));
+                }
+                match name.as_str() {
+                    "true" => Ok(Operand::Lit(JSON::Bol(true))),
+                    "false" => Ok(Operand::Lit(JSON::Bol(false))),
+                    "null" => Ok(Operand::Lit(JSON::Nul)),
+                    _ => {
+                        self.pos = at;
+                        self.err("Expected true, false, null or a function")
+                    }
+                }
+            }
+            _ => self.err("Expected a literal, a query or a function"),
+        }
+    }
+
+    fn number(&mut self) -> Result<JSON, PathErr> {
+        let from = self.pos;
+        while matches!(self.peek(), Some('-' | '+' | '.' | 'e' | 'E' | '0'..='9')) {
+            self.pos += 1;
+        }
+        let text: String = self.chars[from..self.pos].iter().collect();
+        if let Err((at, msg)) = check_number(&text) {
+            self.pos = from + text[..at].chars().count();
+            return self.err(msg);
+        }
+        match (text.parse::<i64>(), text.parse::<f64>()) {
+            (Ok(int), _) => Ok(JSON::Int(int)),
+            (_, Ok(flt)) if flt.is_finite() => Ok(JSON::Flt(flt)),
+            _ => {
+                self.pos = from;
+                self.err("The number is too large")
+            }
+        }
+    }
+
+    fn call(&mut self, name: &str, at: usize) -> Result<Call, PathErr> {
+        let func = match name {
+            "length" => Func::Length,
+            "count" => Func::Count,
+            "match" => Func::Match,
+            "search" => Func::Search,
+            "value" => Func::Value,
+            _ => {
+                self.pos = at;
+                return self.err("Unknown function");
+            }
+        };
+        self.nest()?;
+        self.pos += 1; // '('
+        self.space();
+        let mut args = Vec::new();
+        if !self.eat(')') {
+            loop {
+                args.push(self.arg()?);
+                self.space();
+                if self.eat(')') {
+                    break;
+                }
+                if !self.eat(',') {
+                    return self.err("Expected ',' or ')' after the argument");
+                }
+                self.space();
+            }
+        }
+        self.depth -= 1;
+
+        let params = func.params();
+        let typed = |(arg, param): (&Arg, &Type)| match (param, arg) {
+            (Type::Value, Arg::Lit(_)) => true,
+            (Type::Value, Arg::Query(query)) => query.singular(),
+            (Type::Value, Arg::Call(call)) => call.func.result() == Type::Value,
+            (Type::Nodes, Arg::Query(_)) => true,
+            _ => false,
+        };
+        if args.len() != params.len() || !args.iter().zip(params).all(typed) {
+            self.pos = at;
+            return self.err("Wrong number or type of arguments for the function");
+        }
+        let regex = match (func, &args[..]) {
+            (Func::Match | Func::Search, [_, Arg::Lit(JSON::Str(pattern))]) => Regex::new(pattern),
+            _ => None,
+        };
+        Ok(Call { func, args, regex })
+    }
+
+    // None of the functions takes a logical argument, so no comparisons here
+    fn arg(&mut self) -> Result<Arg, PathErr> {
+        Ok(match self.operand()? {
+            Operand::Lit(lit) => Arg::Lit(lit),
+            Operand::Query(query) => Arg::Query(query),
+            Operand::Call(call) => Arg::Call(call),
+        })
+    }
+}
+
+fn select<'a>(
+    segments: &[Segment],
+    root: &'a JSON,
+    mut nodes: Vec<(NormalizedPath, &'a JSON)>,
+) -> Vec<(NormalizedPath, &'a JSON)> {
+    for seg in segments {
+        let mut out = Vec::new();
+        for (path, node) in &nodes {
+            match seg {
+                Segment::Child(sels) => apply(sels, root, path, node, &mut out),
+                Segment::Descendant(sels) => {
+                    // The node and all below it, each one before its children
+                    let mut stack = vec![(path.clone(), *node)];
+                    while let Some((path, node)) = stack.pop() {
+                        apply(sels, root, &path, node, &mut out);
+                        stack.extend(children(&path, node).into_iter().rev());
+                    }
+                }
+            }
+        }
+        nodes = out;
+    }
+    nodes
+}
+
+fn children<'a>(path: &NormalizedPath, node: &'a JSON) -> Vec<(NormalizedPath, &'a JSON)> {
+    match node {
+        JSON::Lst(lst) => lst
+            .iter()
+            .enumerate()
+            .map(|(i, item)| (path.with(PathElem::Index(i)), item))
+            .collect(),
+        JSON::Obj(obj) => obj
+            .iter()
+            .map(|(k, v)| (path.with(PathElem::Key(k.clone())), v))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn apply<'a>(
+    sels: &[Selector],
+    root: &'a JSON,
+    path: &NormalizedPath,
+    node: &'a JSON,
+    out: &mut Vec<(NormalizedPath, &'a JSON)>,
+) {
+    for sel in sels {
+        match (sel, node) {
+            (Selector::Name(name), JSON::Obj(obj)) => {
+                if let Some(val) = obj.get(name.as_str()) {
+                    out.push((path.with(PathElem::Key(name.clone())), val));
+                }
+            }
+            (Selector::Wildcard, _) => out.extend(children(path, node)),
+            (Selector::Index(i), JSON::Lst(lst)) => {
+                let len = lst.len() as i64;
+                let i = if *i < 0 { len + i } else { *i };
+                if (0..len).contains(&i) {
+                    out.push((path.with(PathElem::Index(i as usize)), &lst[i as usize]));
+                }
+            }
+            (Selector::Slice(start, end, step), JSON::Lst(lst)) => {
+                for i in slice(lst.len() as i64, *start, *end, step.unwrap_or(1)) {
+                    out.push((path.with(PathElem::Index(i)), &lst[i]));
+                }
+            }
+            (Selector::Filter(expr), _) => {
+                for (path, child) in children(path, node) {
+                    if test(expr, root, child) {
+                        out.push((path, child));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// The indices of a slice, as in section 2.3.4.2.2 of RFC 9535
+fn slice(len: i64, start: Option<i64>, end: Option<i64>, step: i64) -> Vec<usize> {
+    let norm = |i: i64| if i >= 0 { i } else { len + i };
+    let mut indices = Vec::new();
+    if step > 0 {
+        let lower = norm(start.unwrap_or(0)).clamp(0, len);
+        let upper = norm(end.unwrap_or(len)).clamp(0, len);
+        let mut i = lower;
+        while i < upper {
+            indices.push(i as usize);
+            i += step;
+        }
+    } else if step < 0 {
+        let upper = norm(start.unwrap_or(len - 1)).clamp(-1, len - 1);
+        let lower = norm(end.unwrap_or(-len - 1)).clamp(-1, len - 1);
+        let mut i = upper;
+        while lower < i {
+            indices.push(i as usize);
+            i += step;
+        }
+    }
+    indices
+}
+
+// A value in a filter: Nothing, a node of the document or a literal, or what
+// a function gave
+enum Val<'a> {
+    Nothing,
+    Ref(&'a JSON),
+    Own(JSON),
+}
+
+impl Val<'_> {
+    fn json(&self) -> Option<&JSON> {
+        match self {
+            Val::Nothing => None,
+            Val::Ref(json) => Some(json),
+            Val::Own(json) => Some(json),
+        }
+    }
+}
+
+fn test(expr: &Expr, root: &JSON, cur: &JSON) -> bool {
+    match expr {
+        Expr::Or(exprs) => exprs.iter().any(|e| test(e, root, cur)),
+        Expr::And(exprs) => exprs.iter().all(|e| test(e, root, cur)),
+        Expr::Not(expr) => !test(expr, root, cur),
+        Expr::Exists(query) => !nodes(query, root, cur).is_empty(),
+        Expr::Call(call) => logical_call(call, root, cur),
+        Expr::Cmp(cmp) => {
+            let (left, op, right) = &**cmp;
+            let left = operand(left, root, cur);
+            let right = operand(right, root, cur);
+            let (a, b) = (left.json(), right.json());
+            match op {
+                Op::Eq => equal(a, b),
+                Op::Ne => !equal(a, b),
+                Op::Lt => less(a, b),
+                Op::Le => less(a, b) || equal(a, b),
+                Op::Gt => less(b, a),
+                Op::Ge => less(b, a) || equal(a, b),
+            }
+        }
+    }
+}
+
+fn nodes<'a>(query: &Query, root: &'a JSON, cur: &'a JSON) -> Vec<&'a JSON> {
+    let start = if query.root { root } else { cur };
+    select(
+        &query.segments,
+        root,
+        vec![(NormalizedPath::default(), start)],
+    )
+    .into_iter()
+    .map(|(_, node)| node)
+    .collect()
+}
+
+fn operand<'a>(operand: &'a Operand, root: &'a JSON, cur: &'a JSON) -> Val<'a> {
+    match operand {
+        Operand::Lit(lit) => Val::Ref(lit),
+        Operand::Query(query) => single(query, root, cur),
+        Operand::Call(call) => value_call(call, root, cur),
+    }
+}
+
+fn single<'a>(query: &Query, root: &'a JSON, cur: &'a JSON) -> Val<'a> {
+    match nodes(query, root, cur)[..] {
+        [node] => Val::Ref(node),
+        _ => Val::Nothing,
+    }
+}
+
+fn arg_value<'a>(arg: &'a Arg, root: &'a JSON, cur: &'a JSON) -> Val<'a> {
+    match arg {
+        Arg::Lit(lit) => Val::Ref(lit),
+        Arg::Query(query) => single(query, root, cur),
+        Arg::Call(call) => value_call(call, root, cur),
+    }
+}
+
+fn value_call<'a>(call: &'a Call, root: &'a JSON, cur: &'a JSON) -> Val<'a> {
+    let count = |len: usize| Val::Own(JSON::Int(len as i64));
+    match (call.func, &call.args[..]) {
+        (Func::Length, [arg]) => match arg_value(arg, root, cur).json() {
+            Some(JSON::Str(s)) => count(s.chars().count()),
+            Some(JSON::Lst(lst)) => count(lst.len()),
+            Some(JSON::Obj(obj)) => count(obj.len()),
+            _ => Val::Nothing,
+        },
+        (Func::Count, [Arg::Query(query)]) => count(nodes(query, root, cur).len()),
+        (Func::Value, [Arg::Query(query)]) => single(query, root, cur),
+        _ => Val::Nothing,
+    }
+}
+
+// match() and search(), false unless both are strings and the pattern is an
+// I-Regexp within the limits of iregexp.rs
+fn logical_call(call: &Call, root: &JSON, cur: &JSON) -> bool {
+    let [text, pattern] = &call.args[..] else {
+        return false;
+    };
+    let text = arg_value(text, root, cur);
+    let Some(JSON::Str(text)) = text.json() else {
+        return false;
+    };
+    // Only the patterns taken from the document are compiled for each node
+    let computed;
+    let regex = match pattern {
+        Arg::Lit(_) => call.regex.as_ref(),
+        _ => {
+            let pattern = arg_value(pattern, root, cur);
+            let Some(JSON::Str(pattern)) = pattern.json() else {
+                return false;
+            };
+            computed = Regex::new(pattern);
+            computed.as_ref()
+        }
+    };
+    match (call.func, regex) {
+        (Func::Match, Some(re)) => re.is_match(text),
+        (Func::Search, Some(re)) => re.is_found(text),
+        _ => false,
+    }
+}
+
+fn equal(a: Option<&JSON>, b: Option<&JSON>) -> bool {
+    match (a, b) {
+        (None, None) => true,
+        (Some(a), Some(b)) => json_eq(a, b),
+        _ => false,
+    }
+}
+
+fn json_eq(a: &JSON, b: &JSON) -> bool {
+    match (a, b) {
+        (JSON::Str(a), JSON::Str(b)) => a == b,
+        (JSON::Bol(a), JSON::Bol(b)) => a == b,
+        (JSON::Nul, JSON::Nul) => true,
+        (JSON::Lst(a), JSON::Lst(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| json_eq(a, b))
+        }
+        (JSON::Obj(a), JSON::Obj(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(k, v)| b.get(k.as_str()).is_some_and(|w| json_eq(v, w)))
+        }
+        (JSON::Int(a), JSON::Int(b)) => a == b,
+        _ => matches!((num(a), num(b)), (Some(a), Some(b)) if a == b),
+    }
+}
+
+fn less(a: Option<&JSON>, b: Option<&JSON>) -> bool {
+    match (a, b) {
+        (Some(JSON::Int(a)), Some(JSON::Int(b))) => a < b,
+        (Some(JSON::Str(a)), Some(JSON::Str(b))) => a < b,
+        (Some(a), Some(b)) => matches!((num(a), num(b)), (Some(a), Some(b)) if a < b),
+        _ => false,
+    }
+}
+
+fn num(json: &JSON) -> Option<f64> {
+    match json {
+        JSON::Int(i) => Some(*i as f64),
+        JSON::Flt(f) => Some(*f),
+        JSON::Num(n) => n.as_f64(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, to_canonical_string};
+
+    // The cases written here from the RFC, in the layout of the JSONPath
+    // Compliance Test Suite
+    #[test]
+    fn test_jsonpath_cases() {
+        run_cts(&parse(include_str!("../json_test/jsonpath_cases.json")).unwrap());
+    }
+
+    // Every case of the official suite, vendored as json_test/cts.json by
+    // tools/fetch_cts.sh with the commit it comes from in json_test/cts.SOURCE
+    #[test]
+    fn test_jsonpath_cts() {
+        let file = concat!(env!("CARGO_MANIFEST_DIR"), "/json_test/cts.json");
+        let text = std::fs::read_to_string(file)
+            .unwrap_or_else(|err| panic!("{file}: {err}, vendor it with tools/fetch_cts.sh"));
+        run_cts(&parse(&text).unwrap());
+    }
+
+    fn run_cts(cts: &JSON) {
+        let Some(JSON::Lst(cases)) = cts.pointer("/tests") else {
+            panic!("Expected the list of tests");
+        };
+        let texts = |json: &JSON, canon: bool| -> Vec<String> {
+            let JSON::Lst(lst) = json else {
+                panic!("Expected a list");
+            };
+            lst.iter()
+                .map(|item| match item {
+                    JSON::Str(s) if !canon => s.clone(),
+                    _ => to_canonical_string(item).unwrap(),
+                })
+                .collect()
+        };
+        for case in cases {
+            let Some(JSON::Str(name)) = case.pointer("/name") else {
+                panic!("Expected a name");
+            };
+            let Some(JSON::Str(selector)) = case.pointer("/selector") else {
+                panic!("{name}: expected a selector");
+            };
+            let path = JsonPath::parse(selector);
+            if let Some(JSON::Bol(true)) = case.pointer("/invalid_selector") {
+                assert!(path.is_err(), "{name}: {selector} should be invalid");
+                continue;
+            }
+            let path = path.unwrap_or_else(|err| panic!("{name}: {selector}: {err}"));
+            let found = path.query(case.pointer("/document").unwrap());
+            let values: Vec<_> = found
+                .iter()
+                .map(|(_, v)| to_canonical_string(v).unwrap())
+                .collect();
+            let paths: Vec<_> = found.iter().map(|(p, _)| p.to_string()).collect();
+
+            // One result, or a few when the order of object members can change it
+            let (results, results_paths) = match case.pointer("/result") {
+                Some(result) => (vec![result], case.pointer("/result_paths").map(|p| vec![p])),
+                None => {
+                    let Some(JSON::Lst(results)) = case.pointer("/results") else {
+                        panic!("{name}: expected a result");
+                    };
+                    let paths = match case.pointer("/results_paths") {
+                        Some(JSON::Lst(paths)) => Some(paths.iter().collect()),
+                        _ => None,
+                    };
+                    (results.iter().collect(), paths)
+                }
+            };
+            let ok = results.iter().enumerate().any(|(i, result)| {
+                texts(result, true) == values
+                    && results_paths
+                        .as_ref()
+                        .is_none_or(|rp: &Vec<&JSON>| texts(rp[i], false) == paths)
+            });
+            assert!(ok, "{name}: {selector} gave {values:?} at {paths:?}");
+        }
+    }
+
+    #[test]
+    fn test_jsonpath() {
+        let doc = parse(
+            r#"{"store": {"book": [
+                {"title": "Sayings", "price": 8.95, "tags": ["old"]},
+                {"title": "Sword", "price": 12.99},
+                {"title": "Moby Dick", "price": 8.99, "isbn": "0-553-21311-3"}
+            ], "bicycle": {"price": 19.95}}}"#,
+        )
+        .unwrap();
+        let titles = |path: &str| -> Vec<String> {
+            doc.query(path)
+                .unwrap()
+                .into_iter()
+                .map(|(_, v)| match v {
+                    JSON::Str(s) => s.clone(),
+                    _ => v.to_string(),
+                })
+                .collect()
+        };
+        assert_eq!(
+            titles("$.store.book[?@.price < 10].title"),
+            ["Sayings", "Moby Dick"]
+        );
+        assert_eq!(titles("$..book[-1:].title"), ["Moby Dick"]);
+        assert_eq!(titles("$.store.book[?@.isbn].title"), ["Moby Dick"]);
+        assert_eq!(
+            titles("$.store.book[?match(@.title, 'S.*')].title"),
+            ["Sayings", "Sword"]
+        );
+        assert_eq!(
+            titles("$.store.book[?search(@.title, 'i')].title"),
+            ["Sayings", "Moby Dick"]
+        );
+        assert_eq!(
+            titles("$.store.book[?count(@.*) == 3].title"),
+            ["Sayings", "Moby Dick"]
+        );
+        assert_eq!(
+            titles("$.store.book[?length(@.title) > 6].title"),
+            ["Sayings", "Moby Dick"]
+        );
+        assert_eq!(
+            titles("$.store.book[::2]['title', 'price']"),
+            ["Sayings", "8.95", "Moby Dick", "8.99"]
+        );
+        assert_eq!(titles("$..price").len(), 4);
+
+        let found = doc.query("$.store.book[0].tags[0]").unwrap();
+        let path = &found[0].0;
+        assert_eq!(path.to_string(), "$['store']['book'][0]['tags'][0]");
+        assert!(matches!(doc.pointer(&path.to_pointer()), Some(JSON::Str(s)) if s == "old"));
+        let key = NormalizedPath(vec![PathElem::Key("it's\n\u{1}".into())]);
+        assert_eq!(key.to_string(), "$['it\\'s\\n\\u0001']");
+
+        assert!("$[?length(@.a)]".parse::<JsonPath>().is_err());
+        assert!("$[?@.a[*] == 1]".parse::<JsonPath>().is_err());
+        assert!(JsonPath::parse(&format!("$[?{}@{}]", "(".repeat(100), ")".repeat(100))).is_err());
+    }
+
+    #[test]
+    fn test_jsonpath_regex() {
+        let doc =
+            parse(r#"[{"s": "abc", "p": "a.c"}, {"s": "xyz", "p": "["}, {"s": "ab", "p": "b"}]"#)
+                .unwrap();
+        let indexes = |path: &str| -> Vec<String> {
+            let found = doc
+                .query(path)
+                .unwrap_or_else(|err| panic!("{path}: {err}"));
+            found.iter().map(|(p, _)| p.to_string()).collect()
+        };
+        // A literal pattern, compiled with the path
+        assert_eq!(indexes("$[?match(@.s, 'a.c')]"), ["$[0]"]);
+        assert_eq!(indexes("$[?search(@.s, 'b')]"), ["$[0]", "$[2]"]);
+        // The patterns of the document, one for each node
+        assert_eq!(indexes("$[?match(@.s, @.p)]"), ["$[0]"]);
+        assert_eq!(indexes("$[?search(@.s, @.p)]"), ["$[0]", "$[2]"]);
+        // A pattern which is no I-Regexp makes the call false, like RFC 9535 asks
+        assert!(indexes("$[?match(@.s, '[')]").is_empty());
+        assert_eq!(indexes("$[?!search(@.s, 'a{1001}')]").len(), 3);
+        assert!(indexes("$[?match(@.s, '((){60000}){60000}')]").is_empty());
+    }
+
+    // The values found, in canonical form
+    fn values(doc: &str, path: &str) -> Vec<String> {
+        let doc = parse(doc).unwrap();
+        let found = doc
+            .query(path)
+            .unwrap_or_else(|err| panic!("{path}: {err}"));
+        found
+            .iter()
+            .map(|(_, v)| to_canonical_string(v).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_jsonpath_slices() {
+        let digits = "[0, 1, 2, 3, 4, 5, 6]";
+        let cases: [(&str, &[&str]); 14] = [
+            ("$[1:3]", &["1", "2"]),
+            ("$[5:]", &["5", "6"]),
+            ("$[-2:]", &["5", "6"]),
+            ("$[:-5]", &["0", "1"]),
+            ("$[-10:2]", &["0", "1"]),
+            ("$[10:]", &[]),
+            ("$[3:1]", &[]),
+            ("$[::3]", &["0", "3", "6"]),
+            // A negative step goes backwards from the end by default
+            ("$[::-1]", &["6", "5", "4", "3", "2", "1", "0"]),
+            ("$[5:0:-2]", &["5", "3", "1"]),
+            ("$[-1:-4:-1]", &["6", "5", "4"]),
+            ("$[1:5:-1]", &[]),
+            ("$[10:-10:-3]", &["6", "3", "0"]),
+            // A zero step selects nothing
+            ("$[::0]", &[]),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(values(digits, path), expected, "{path}");
+        }
+        assert!(values("{\"a\": 1}", "$[::-1]").is_empty());
+        assert!(values("[]", "$[::-1]").is_empty());
+        assert_eq!(
+            values(digits, "$[0:9007199254740991:9007199254740991]"),
+            ["0"]
+        );
+    }
+
+    #[test]
+    fn test_jsonpath_descendants() {
+        // A node comes before the nodes under it, lists in their order
+        let doc = "[[1, [2]], 3]";
+        assert_eq!(values(doc, "$..[0]"), ["[1,[2]]", "1", "2"]);
+        assert_eq!(values(doc, "$..*"), ["[1,[2]]", "3", "1", "[2]", "2"]);
+        assert_eq!(values(doc, "$..[-1]"), ["3", "[2]", "2"]);
+        let doc = parse(doc).unwrap();
+        let paths: Vec<_> = doc
+            .query("$..[*]")
+            .unwrap()
+            .iter()
+            .map(|(p, _)| p.to_string())
+            .collect();
+        assert_eq!(paths, ["$[0]", "$[1]", "$[0][0]", "$[0][1]", "$[0][1][0]"]);
+        // Through objects too, one member at a time
+        assert_eq!(
+            values(r#"{"a": {"b": [{"b": 1}]}}"#, "$..b"),
+            [r#"[{"b":1}]"#, "1"]
+        );
+        assert_eq!(values("[1, {\"a\": [2]}]", "$..a[0]"), ["2"]);
+    }
+
+    #[test]
+    fn test_jsonpath_errors() {
+        let cases = [
+            ("", 1, "A JSONPath query begins with '$'"),
+            (" $", 1, "A JSONPath query begins with '$'"),
+            ("$x", 2, "Unexpected character in the query"),
+            (
+                "$[",
+                3,
+                "Expected a name, an index, a slice, '*' or a filter",
+            ),
+            (
+                "$[1,]",
+                5,
+                "Expected a name, an index, a slice, '*' or a filter",
+            ),
+            ("$.", 3, "Expected a member name or '*'"),
+            ("$..", 4, "Expected a member name or '*'"),
+            ("$[1:2:3:4]", 8, "Expected ',' or ']' after the selector"),
+            ("$['a'", 6, "Expected ',' or ']' after the selector"),
+            ("$[-0]", 4, "-0 is not a valid index"),
+            (
+                "$[9007199254740992]",
+                3,
+                "The integer is out of the range ±(2^53 - 1)",
+            ),
+            ("$[\"\\q\"]", 5, "Invalid escape in the string"),
+            (
+                "$[?@.a ==]",
+                10,
+                "Expected a literal, a query or a function",
+            ),
+            ("$[?(@.a]", 8, "Expected ')'"),
+            ("$[?foo(@)]", 4, "Unknown function"),
+            (
+                "$[?match(@)]",
+                4,
+                "Wrong number or type of arguments for the function",
+            ),
+            (
+                "$.store[?@.price <]",
+                19,
+                "Expected a literal, a query or a function",
+            ),
+        ];
+        for (path, column, msg) in cases {
+            let err = JsonPath::parse(path).unwrap_err();
+            assert_eq!((err.column(), err.msg()), (column, msg), "{path}");
+            assert_eq!(err.to_string(), format!("Char[{column}]: {msg}"));
+        }
+    }
+}
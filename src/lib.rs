@@ -1,24 +1,82 @@
 use core::fmt;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+use std::io::{self, Read};
+use std::ops::Range;
 
-#[derive(Debug)]
+mod access;
+mod borrowed;
+mod canonical;
+mod categories;
+mod iregexp;
+mod jq;
+mod jsonpath;
+mod lines;
+mod map;
+mod multi;
+mod number;
+mod pointer;
+mod recover;
+mod report;
+mod ser;
+mod sha256;
+pub use borrowed::{parse_borrowed, JsonRef};
+pub use canonical::{canonical_hash, to_canonical_string};
+pub use jq::{Jq, JqErr};
+pub use jsonpath::{JsonPath, NormalizedPath, PathElem, PathErr};
+pub use lines::{parse_lines, Lines, LinesWriter};
+pub use map::ObjMap;
+pub use multi::{parse_json_seq, parse_multi, Documents};
+pub use number::Number;
+pub use recover::{parse_recovering, Recovered};
+pub use ser::{to_writer_pretty, NonFinite, PrettyOptions};
+
+use number::{check_number, is_exact_f64};
+
+// The map of JSON objects. With the `preserve_order` feature it keeps the
+// keys in the order of the source, otherwise it is a plain HashMap.
+#[cfg(not(feature = "preserve_order"))]
+pub type Map<K, V> = std::collections::HashMap<K, V>;
+#[cfg(feature = "preserve_order")]
+pub type Map<K, V> = ObjMap<K, V>;
+
+#[derive(Debug, Clone)]
 pub enum JSON {
     Int(i64),
     Flt(f64),
+    Num(Number), // Exact lexeme of a number which doesn't fit in Int or Flt
     Str(String),
     Lst(Vec<JSON>),
-    Obj(HashMap<String, JSON>),
+    Obj(Map<String, JSON>),
     Bol(bool),
     Nul,
 }
 
+// The trees which can be packed up out of the Preb stack (JSON and JsonRef)
+trait Node: Sized {
+    type Key: Eq + Hash;
+    fn obj(obj: Map<Self::Key, Self>) -> Self;
+    fn lst(lst: Vec<Self>) -> Self;
+}
+
+impl Node for JSON {
+    type Key = String;
+    fn obj(obj: Map<String, JSON>) -> JSON {
+        JSON::Obj(obj)
+    }
+    fn lst(lst: Vec<JSON>) -> JSON {
+        JSON::Lst(lst)
+    }
+}
+
 #[derive(Debug)]
-enum Preb {
+enum Preb<N: Node = JSON> {
     BgnObj,
     BgnLst,
-    Val(JSON),
-    Key(String),
-    Ent(String, JSON),
+    Val(N),
+    Key(N::Key),
+    Ent(N::Key, N),
 }
 
 #[derive(PartialEq)]
@@ -29,6 +87,31 @@ enum Inside {
     List, // 3
 }
 
+// Where we are in a comment
+#[derive(Clone, Copy, PartialEq)]
+enum Comment {
+    Slash,     // Found '/', waiting for the next '/' or '*'
+    Line,      // Inside // ... until the end of the line
+    Block,     // Inside /* ... */
+    BlockStar, // Found '*' inside /* */, a '/' would close it
+}
+
+impl Comment {
+    // Where ch leads, None once the comment is over. A Slash must be
+    // followed by '/' or '*', that is up to the caller.
+    fn after(self, ch: char) -> Option<Comment> {
+        match (self, ch) {
+            (Comment::Slash, '/') => Some(Comment::Line),
+            (Comment::Slash, _) => Some(Comment::Block),
+            (Comment::Line, '\n' | '\r' | '\u{2028}' | '\u{2029}') => None,
+            (Comment::Line, _) => Some(Comment::Line),
+            (Comment::Block | Comment::BlockStar, '*') => Some(Comment::BlockStar),
+            (Comment::BlockStar, '/') => None,
+            (Comment::Block | Comment::BlockStar, _) => Some(Comment::Block),
+        }
+    }
+}
+
 #[derive(PartialEq)]
 enum S {
     Ready,     // 0
@@ -43,7 +126,7 @@ enum S {
                //EndCtnr,   // 9
 }
 
-fn inside_what(mem: &Vec<Preb>) -> Inside {
+fn inside_what<N: Node>(mem: &[Preb<N>]) -> Inside {
     // Find what current inside state is
     let mem_size = mem.len();
     for i in (0..mem_size).rev() {
@@ -60,138 +143,487 @@ fn inside_what(mem: &Vec<Preb>) -> Inside {
     Inside::End
 }
 
-fn get_esc_char(ch: char) -> Result<String, &'static str> {
-    match ch {
-        'b' => Ok(String::from(r"\b")),
-        'f' => Ok(String::from(r"\f")),
-        'n' => Ok(String::from("\n")),
-        't' => Ok(String::from("\t")),
-        'r' => Ok(String::from("\r")),
-        '\\' => Ok(String::from("\\")),
-        '\"' => Ok(String::from("\"")),
-        _ => Err("Unavailable Escape Character"),
+// String escape decoder, shared by keys and string values
+#[derive(Default)]
+struct Escape {
+    on: bool,            // Just found '\\', waiting for escape character
+    hex: Option<String>, // Collecting the XXXX of \uXXXX (or the HH of \xHH in JSON5)
+    hex_len: usize,      // How many hex digits are expected
+    high: Option<u32>,   // High surrogate waiting for its low pair
+    cr: bool,            // JSON5 line continuation with "\\\r", a '\n' may follow
+}
+
+impl Escape {
+    // Return Ok(true) when the character was consumed by the escape handling,
+    // Ok(false) when it is a normal character of the string (or the closing quote)
+    fn feed(&mut self, ch: char, buf: &mut String, json5: bool) -> Result<bool, &'static str> {
+        if self.cr {
+            self.cr = false;
+            if ch == '\n' {
+                return Ok(true);
+            }
+        }
+
+        if let Some(hex) = &mut self.hex {
+            if !ch.is_ascii_hexdigit() {
+                return Err(match self.hex_len {
+                    2 => "Expected 2 hex digits after \\x",
+                    _ => "Expected 4 hex digits after \\u",
+                });
+            }
+            hex.push(ch);
+            if hex.len() < self.hex_len {
+                return Ok(true);
+            }
+            let code = u32::from_str_radix(hex, 16).map_err(|_| "Invalid \\u escape")?;
+            self.hex = None;
+            match (self.high.take(), code) {
+                (Some(high), 0xDC00..=0xDFFF) => {
+                    let joined = 0x10000 + ((high - 0xD800) << 10) + (code - 0xDC00);
+                    buf.push(char::from_u32(joined).ok_or("Invalid surrogate pair")?);
+                }
+                (Some(_), _) => {
+                    return Err("Lone high surrogate, expected a low surrogate \\uDC00-\\uDFFF");
+                }
+                (None, 0xD800..=0xDBFF) => {
+                    self.high = Some(code);
+                }
+                (None, 0xDC00..=0xDFFF) => {
+                    return Err("Lone low surrogate without a preceding high surrogate");
+                }
+                (None, _) => {
+                    buf.push(char::from_u32(code).ok_or("Invalid \\u escape")?);
+                }
+            }
+            return Ok(true);
+        }
+
+        if self.on {
+            self.on = false;
+            if ch == 'u' || json5 && ch == 'x' {
+                self.hex = Some(String::new());
+                self.hex_len = if ch == 'u' { 4 } else { 2 };
+                return Ok(true);
+            }
+            if self.high.is_some() {
+                return Err("Lone high surrogate, expected a low surrogate \\uDC00-\\uDFFF");
+            }
+            buf.push(match ch {
+                'b' => '\u{0008}',
+                'f' => '\u{000C}',
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                '/' => '/',
+                '\\' => '\\',
+                '\"' => '\"',
+                // JSON5 takes the escapes of JavaScript
+                _ if !json5 => return Err("Unavailable Escape Character"),
+                'v' => '\u{000B}',
+                '0' => '\0',
+                '1'..='9' => return Err("Octal escapes are not allowed"),
+                '\n' | '\u{2028}' | '\u{2029}' => return Ok(true), // Line continuation
+                '\r' => {
+                    self.cr = true;
+                    return Ok(true);
+                }
+                _ => ch, // Any other character stands for itself, like \'
+            });
+            return Ok(true);
+        }
+
+        if ch == '\\' {
+            self.on = true;
+            return Ok(true);
+        }
+        if self.high.is_some() {
+            return Err("Lone high surrogate, expected a low surrogate \\uDC00-\\uDFFF");
+        }
+        Ok(false)
     }
 }
 
+// Characters of the JSON5 unquoted keys (ECMAScript IdentifierName,
+// without the \uXXXX escapes)
+fn is_ident_start(ch: char) -> bool {
+    ch.is_alphabetic() || ch == '_' || ch == '$'
+}
+
+fn is_ident_part(ch: char) -> bool {
+    ch.is_alphanumeric() || matches!(ch, '_' | '$' | '\u{200C}' | '\u{200D}')
+}
+
+// The Zs category of Unicode, the spaces JSON5 allows besides the JSON ones
+fn is_unicode_space(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{1680}' | '\u{2000}'..='\u{200A}' | '\u{202F}' | '\u{205F}' | '\u{3000}'
+    )
+}
+
 // Memory updater function
-fn pack_object(mem: &mut Vec<Preb>) -> Result<(), &'static str> {
-    let mut temp_obj: HashMap<String, JSON> = HashMap::new();
+#[allow(clippy::map_entry)] // ObjMap has no entry API
+fn pack_object<N: Node>(mem: &mut Vec<Preb<N>>, dup: DuplicateKeys) -> Result<(), Fail> {
+    let mut entries: Vec<(N::Key, N)> = Vec::new();
     while let Some(preb) = mem.pop() {
         match preb {
             Preb::Ent(k, v) => {
-                temp_obj.insert(String::from(k), v);
+                entries.push((k, v));
             }
             Preb::BgnObj => {
                 break; // Go out from loop since there is no more to packup
             }
             _ => {
-                return Err("There are some leftovers which not been processed yet...");
+                return Err(Fail::unexpected(
+                    "There are some leftovers which not been processed yet...",
+                ));
+            }
+        }
+    }
+    // Entries were popped from the top of the stack, insert them in the source order
+    let mut temp_obj: Map<N::Key, N> = Map::new();
+    match dup {
+        // With Error, the builder already refused the repeated key
+        DuplicateKeys::FirstWins | DuplicateKeys::Error => {
+            for (k, v) in entries.into_iter().rev() {
+                if !temp_obj.contains_key(&k) {
+                    temp_obj.insert(k, v);
+                }
             }
         }
+        DuplicateKeys::LastWins => {
+            temp_obj.extend(entries.into_iter().rev());
+        }
+        DuplicateKeys::Collect => {
+            let mut grouped: Map<N::Key, Vec<N>> = Map::new();
+            for (k, v) in entries.into_iter().rev() {
+                match grouped.get_mut(&k) {
+                    Some(vals) => vals.push(v),
+                    None => {
+                        grouped.insert(k, vec![v]);
+                    }
+                }
+            }
+            // A list even for one value, else {"a":[1,2]} could not be told
+            // apart from {"a":1,"a":2}
+            temp_obj = grouped
+                .into_iter()
+                .map(|(k, vals)| (k, N::lst(vals)))
+                .collect();
+        }
     }
-    mem.push(Preb::Val(JSON::Obj(temp_obj)));
+    mem.push(Preb::Val(N::obj(temp_obj)));
     Ok(())
 }
 
-fn pack_list(mem: &mut Vec<Preb>) -> Result<(), &'static str> {
-    let mut temp_list: Vec<JSON> = Vec::new();
+fn pack_list<N: Node>(mem: &mut Vec<Preb<N>>) -> Result<(), Fail> {
+    let mut temp_list: Vec<N> = Vec::new();
     while let Some(preb) = mem.pop() {
         match preb {
             Preb::BgnObj => {
-                return Err("Unexpected '{' Begin of Object token was found!");
+                return Err(Fail::unexpected(
+                    "Unexpected '{' Begin of Object token was found!",
+                ));
             }
             Preb::BgnLst => {
-                mem.push(Preb::Val(JSON::Lst(temp_list)));
+                // Values were popped from the top of the stack, restore the source order
+                temp_list.reverse();
+                mem.push(Preb::Val(N::lst(temp_list)));
                 return Ok(());
             }
             Preb::Val(v) => {
                 temp_list.push(v);
             }
             Preb::Key(_) => {
-                return Err("A key shouldn't be exist in side the list");
+                return Err(Fail::unexpected(
+                    "A key shouldn't be exist in side the list",
+                ));
             }
             Preb::Ent(_, _) => {
-                return Err("An entry shouldn't be exist in side the list");
+                return Err(Fail::unexpected(
+                    "An entry shouldn't be exist in side the list",
+                ));
             }
         }
     }
     Ok(())
 }
 
-fn pack_entry(mem: &mut Vec<Preb>) -> Result<(), &'static str> {
+fn pack_entry<N: Node>(mem: &mut Vec<Preb<N>>) -> Result<(), Fail> {
     // pop 2 element where the first will be value and the next will be key
-    let mut val: JSON = JSON::Nul;
-    if let Some(val_preb) = mem.pop() {
+    let val: N = if let Some(val_preb) = mem.pop() {
         match val_preb {
-            Preb::Val(v) => {
-                val = v;
-            }
+            Preb::Val(v) => v,
             _ => {
-                return Err("Expected to be value.");
+                return Err(Fail::unexpected("Expected to be value."));
             }
-        };
+        }
     } else {
-        return Err("Stack mem is empty, Possibly invalid JSON format.");
-    }
+        return Err(Fail::unexpected(
+            "Stack mem is empty, Possibly invalid JSON format.",
+        ));
+    };
 
-    let mut key: String = String::new();
-    if let Some(key_preb) = mem.pop() {
+    let key: N::Key = if let Some(key_preb) = mem.pop() {
         match key_preb {
-            Preb::Key(k) => {
-                key = String::from(k);
-            }
+            Preb::Key(k) => k,
             _ => {
-                return Err("Expected to be key.");
+                return Err(Fail::unexpected("Expected to be key."));
             }
-        };
+        }
     } else {
-        return Err("Stack mem is empty, Possibly invalid JSON format.");
-    }
+        return Err(Fail::unexpected(
+            "Stack mem is empty, Possibly invalid JSON format.",
+        ));
+    };
     mem.push(Preb::Ent(key, val));
     Ok(())
 }
 
-fn primitive_parse(val_str: &String) -> Result<JSON, &'static str> {
-    if val_str == "null" {
-        Ok(JSON::Nul)
-    } else if val_str == "true" {
-        Ok(JSON::Bol(true))
-    } else if val_str == "false" {
-        Ok(JSON::Bol(false))
-    } else {
-        if val_str.len() >= 2 {
-            if val_str.chars().nth(0).unwrap() == '0' && val_str.chars().nth(1).unwrap() == '0' {
-                return Err("Too many 0 on front!");
-            }
+// A value was just put on top of mem, pack it into an entry if it belongs to an object
+fn pack_val<N: Node>(mem: &mut Vec<Preb<N>>) -> Result<(), Fail> {
+    if inside_what(&mem[..mem.len() - 1]) == Inside::Obj {
+        pack_entry(mem)?;
+    }
+    Ok(())
+}
+
+fn take_root<N: Node>(mem: &mut Vec<Preb<N>>) -> Result<N, Fail> {
+    // Extract value from mem<Preb> to final_obj
+    if mem.len() > 1 && mem.iter().all(|preb| matches!(preb, Preb::Val(_))) {
+        let err_msg = "There is no or more than one JSON structure in a single file";
+        return Err(Fail::new(ParseErrorKind::TrailingData, err_msg));
+    }
+    // Anything else left over is a container the input didn't close
+    let err_msg = match (mem.pop(), mem.is_empty()) {
+        (Some(Preb::Val(final_object)), true) => return Ok(final_object),
+        (Some(_), _) => "Unexpected final tokens in parser memory",
+        (None, _) => "No data in parser memory",
+    };
+    Err(Fail::new(ParseErrorKind::UnexpectedEof, err_msg))
+}
+
+// Parse true, false, null or a number. On error, give the byte offset of the
+// faulty character in the token
+fn primitive_parse(val_str: &str, opts: &ParseOptions) -> Result<JSON, (usize, Fail)> {
+    match val_str {
+        "null" => return Ok(JSON::Nul),
+        "true" => return Ok(JSON::Bol(true)),
+        "false" => return Ok(JSON::Bol(false)),
+        _ => {}
+    }
+    if opts.lenient_numbers {
+        return lenient_number(val_str)
+            .map_err(|err_msg| (0, Fail::new(ParseErrorKind::InvalidNumber, err_msg)));
+    }
+    if opts.json5 {
+        if let Some(res) = json5_number(val_str) {
+            return res;
+        }
+    }
+
+    let lower = val_str.trim_start_matches(['-', '+']).to_ascii_lowercase();
+    if lower == "nan" || lower.starts_with("inf") {
+        let err_msg = "Infinity and NaN are not valid JSON numbers";
+        return Err((0, Fail::new(ParseErrorKind::InvalidNumber, err_msg)));
+    }
+    if val_str.starts_with(['t', 'f', 'n']) {
+        let err_msg = "Invalid literal, expected true, false or null";
+        return Err((0, Fail::new(ParseErrorKind::InvalidLiteral, err_msg)));
+    }
+    json_number(val_str)
+}
+
+fn json_number(val_str: &str) -> Result<JSON, (usize, Fail)> {
+    check_number(val_str)
+        .map_err(|(at, err_msg)| (at, Fail::new(ParseErrorKind::InvalidNumber, err_msg)))?;
+
+    exact_number(val_str).ok_or((
+        0,
+        Fail::new(
+            ParseErrorKind::InvalidNumber,
+            "Unparsable prmitive data... sorry",
+        ),
+    ))
+}
+
+// The variant follows the value, not the spelling: Int for the integers of
+// the i64 range, Flt for the floats which an f64 holds exactly (1.50, 1E2 and
+// -0.0 too, they are written back as 1.5, 100.0 and -0.0). Num keeps the
+// lexeme of the others, which would lose something: 18446744073709551615,
+// 0.1000000000000000055511, 1e400.
+fn exact_number(val_str: &str) -> Option<JSON> {
+    let integer = !val_str.contains(['.', 'e', 'E']);
+    let flt = val_str.parse::<f64>().ok();
+    match (val_str.parse::<i64>(), flt, val_str.parse::<Number>()) {
+        (Ok(int), _, _) => Some(JSON::Int(int)),
+        (_, Some(flt), _) if !integer && is_exact_f64(val_str, flt) => Some(JSON::Flt(flt)),
+        (_, _, Ok(num)) => Some(JSON::Num(num)),
+        // Only the lenient numbers get here, like +.5
+        (_, flt, _) => flt.map(JSON::Flt),
+    }
+}
+
+// The JSON5 numbers which are not JSON numbers: hex, Infinity, NaN, a '+'
+// sign and a leading or trailing '.'. None for the others.
+fn json5_number(val_str: &str) -> Option<Result<JSON, (usize, Fail)>> {
+    let (neg, body) = match val_str.strip_prefix('-') {
+        Some(body) => (true, body),
+        None => (false, val_str.strip_prefix('+').unwrap_or(val_str)),
+    };
+    let sign_len = val_str.len() - body.len();
+    let invalid =
+        |at: usize, err_msg: &str| Err((at, Fail::new(ParseErrorKind::InvalidNumber, err_msg)));
+
+    match body {
+        "Infinity" if neg => return Some(Ok(JSON::Flt(f64::NEG_INFINITY))),
+        "Infinity" => return Some(Ok(JSON::Flt(f64::INFINITY))),
+        "NaN" => return Some(Ok(JSON::Flt(f64::NAN))),
+        _ => {}
+    }
+
+    if let Some(hex) = body.strip_prefix("0x").or_else(|| body.strip_prefix("0X")) {
+        if let Some(bad) = hex.find(|c: char| !c.is_ascii_hexdigit()) {
+            return Some(invalid(sign_len + 2 + bad, "Expected a hex digit"));
         }
-        // Must be a number then
-        if let Ok(int) = val_str.parse::<i64>() {
-            return Ok(JSON::Int(int));
+        if hex.is_empty() {
+            return Some(invalid(sign_len + 1, "Expected a hex digit after 0x"));
         }
-        return if let Ok(flt) = val_str.parse::<f64>() {
-            Ok(JSON::Flt(flt))
+        let Ok(num) = u128::from_str_radix(hex, 16) else {
+            return Some(invalid(0, "The hex number is too large"));
+        };
+        let dec = if neg {
+            format!("-{num}")
         } else {
-            Err("Unparsable prmitive data... sorry")
+            num.to_string()
         };
+        return Some(json_number(&dec));
+    }
+
+    // Make the other ones JSON numbers: 0 around a lonely '.' and no '+'
+    let (mantissa, exp) = body.split_at(body.find(['e', 'E']).unwrap_or(body.len()));
+    let lead = mantissa.starts_with('.');
+    let trail = mantissa.ends_with('.');
+    if !lead && !trail && !val_str.starts_with('+') {
+        return None;
+    }
+    if mantissa == "." {
+        return Some(invalid(sign_len, "Expected a digit around '.'"));
+    }
+    let fixed = format!(
+        "{}{}{mantissa}{}{exp}",
+        if neg { "-" } else { "" },
+        if lead { "0" } else { "" },
+        if trail { "0" } else { "" },
+    );
+    // Point the errors back at the token as written
+    let shift = |at: usize| (at + sign_len).saturating_sub(neg as usize + lead as usize);
+    Some(json_number(&fixed).map_err(|(at, fail)| (shift(at).min(val_str.len() - 1), fail)))
+}
+
+// The number parsing from before the strict grammar check, whatever
+// Rust's str::parse accepts is taken
+fn lenient_number(val_str: &str) -> Result<JSON, &'static str> {
+    if val_str.starts_with("00") {
+        return Err("Too many 0 on front!");
     }
+    // Must be a number then
+    exact_number(val_str).ok_or("Unparsable prmitive data... sorry")
+}
+
+// What went wrong, to tell the errors apart without comparing the messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnexpectedChar,     // A character which can't be there, like a missing ','
+    ExpectedColon,      // A key not followed by ':'
+    ExpectedKey,        // Something else than a string where an object key goes
+    UnterminatedString, // The input ended inside a string
+    InvalidEscape,      // Unknown escape, bad \uXXXX or lone surrogate
+    InvalidNumber,      // A number out of the JSON grammar
+    InvalidLiteral,     // Something else than true, false or null
+    TrailingData,       // Characters after the end of the root value
+    UnexpectedEof,      // The input ended before the root value was complete
+    InvalidUtf8,        // The bytes of a reader are not UTF-8
+    Io,                 // The reader failed
+    Handler,            // A JsonHandler callback gave an error
+    DepthExceeded,      // Over ParseOptions::max_depth
+    StringTooLong,      // Over ParseOptions::max_string_len
+    ContainerTooLong,   // Over ParseOptions::max_container_len
+    InputTooLarge,      // Over ParseOptions::max_total_bytes
+    DuplicateKey,       // A key repeated in an object, with DuplicateKeys::Error
 }
 
 // My custom error
 #[derive(Debug)]
 pub struct ParseErr {
+    kind: ParseErrorKind,
     line_idx: usize,
     ch_pos: usize,
+    byte_pos: usize,
     msg: String,
 }
 
 impl ParseErr {
-    fn e(lidx: usize, chpos: usize, err_msg: String) -> ParseErr {
+    fn e(kind: ParseErrorKind, at: Pos, byte_pos: usize, err_msg: String) -> ParseErr {
         ParseErr {
-            line_idx: lidx,
-            ch_pos: chpos,
+            kind,
+            line_idx: at.line_idx,
+            ch_pos: at.ch_pos,
+            byte_pos,
             msg: err_msg,
         }
     }
+
+    pub fn kind(&self) -> ParseErrorKind {
+        self.kind
+    }
+
+    // Line of the error, starting from 1
+    pub fn line(&self) -> usize {
+        self.line_idx
+    }
+
+    // Character of the error in its line, starting from 1
+    pub fn column(&self) -> usize {
+        self.ch_pos
+    }
+
+    // Byte offset of the error from the beginning of the input
+    pub fn byte_offset(&self) -> usize {
+        self.byte_pos
+    }
+
+    pub fn msg(&self) -> &str {
+        &self.msg
+    }
+}
+
+impl std::error::Error for ParseErr {}
+
+// An error of the PDA, it becomes a ParseErr once the PDA tells where it is
+struct Fail {
+    kind: ParseErrorKind,
+    msg: String,
+}
+
+impl Fail {
+    fn new(kind: ParseErrorKind, msg: impl Into<String>) -> Fail {
+        Fail {
+            kind,
+            msg: msg.into(),
+        }
+    }
+
+    fn unexpected(msg: &str) -> Fail {
+        Fail::new(ParseErrorKind::UnexpectedChar, msg)
+    }
+}
+
+// The handler callbacks return their errors as String
+impl From<String> for Fail {
+    fn from(msg: String) -> Fail {
+        Fail::new(ParseErrorKind::Handler, msg)
+    }
 }
 
 impl fmt::Display for ParseErr {
@@ -204,923 +636,1006 @@ impl fmt::Display for ParseErr {
     }
 }
 
-pub fn parse(json_str: &String) -> Result<JSON, ParseErr> {
-    let mut mem: Vec<Preb> = Vec::new();
-    let mut state: (Inside, S) = (Inside::Bgn, S::Ready);
+// SAX style callbacks, called by the PDA while it walks through the document.
+// Returning an Err stops the parsing, the message is reported as a ParseErr.
+pub trait JsonHandler {
+    fn start_object(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+    fn key(&mut self, _key: String) -> Result<(), String> {
+        Ok(())
+    }
+    fn end_object(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+    fn start_list(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+    fn end_list(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+    // Any value which is not a container: string, number, bool or null
+    fn scalar(&mut self, _val: JSON) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+// A string token as the PDA gives it to a Sink. In lazy mode nothing is copied
+// until the first escape, the unescaped beginning is only a range of the input.
+struct Text {
+    raw: Range<usize>,    // Byte range of the unescaped beginning in the input
+    tail: Option<String>, // Decoded rest, None when the whole string is in raw
+}
+
+impl Text {
+    // Only for the copying mode, where raw is always empty
+    fn into_string(self) -> String {
+        self.tail.unwrap_or_default()
+    }
+
+    fn into_cow(self, src: &str) -> Cow<'_, str> {
+        match self.tail {
+            None => Cow::Borrowed(&src[self.raw]),
+            Some(tail) if self.raw.is_empty() => Cow::Owned(tail),
+            Some(tail) => Cow::Owned(src[self.raw].to_owned() + &tail),
+        }
+    }
+}
+
+// What the PDA actually reports to. Every JsonHandler is a Sink, the borrowed
+// tree builder implements it directly to get the string ranges.
+trait Sink {
+    fn bgn_obj(&mut self) -> Result<(), Fail>;
+    fn end_obj(&mut self) -> Result<(), Fail>;
+    fn bgn_lst(&mut self) -> Result<(), Fail>;
+    fn end_lst(&mut self) -> Result<(), Fail>;
+    fn key_text(&mut self, key: Text) -> Result<(), Fail>;
+    fn str_text(&mut self, val: Text) -> Result<(), Fail>;
+    fn prim(&mut self, val: JSON) -> Result<(), Fail>;
+}
+
+impl<H: JsonHandler + ?Sized> Sink for H {
+    fn bgn_obj(&mut self) -> Result<(), Fail> {
+        self.start_object().map_err(Fail::from)
+    }
+    fn end_obj(&mut self) -> Result<(), Fail> {
+        self.end_object().map_err(Fail::from)
+    }
+    fn bgn_lst(&mut self) -> Result<(), Fail> {
+        self.start_list().map_err(Fail::from)
+    }
+    fn end_lst(&mut self) -> Result<(), Fail> {
+        self.end_list().map_err(Fail::from)
+    }
+    fn key_text(&mut self, key: Text) -> Result<(), Fail> {
+        self.key(key.into_string()).map_err(Fail::from)
+    }
+    fn str_text(&mut self, val: Text) -> Result<(), Fail> {
+        self.scalar(JSON::Str(val.into_string()))
+            .map_err(Fail::from)
+    }
+    fn prim(&mut self, val: JSON) -> Result<(), Fail> {
+        self.scalar(val).map_err(Fail::from)
+    }
+}
+
+// What to do with a key repeated in an object. It only matters for the
+// functions building a tree, the events report every key as it comes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateKeys {
+    Error, // Stop with a DuplicateKey error at the repeated key
+    #[default]
+    FirstWins, // Keep the first value
+    LastWins, // Keep the last value, like most other parsers do
+    // Every value becomes a list of the values its key had, in source order:
+    // {"a": 1, "a": [2], "b": 3} gives {"a": [1, [2]], "b": [3]}
+    Collect,
+}
+
+// The keys of every open object, so that with DuplicateKeys::Error a
+// repeated key is caught as soon as it is read
+struct SeenKeys<K> {
+    dup: DuplicateKeys,
+    stack: Vec<HashSet<K>>,
+}
+
+impl<K: Hash + Eq + Clone + AsRef<str>> SeenKeys<K> {
+    fn new(dup: DuplicateKeys) -> SeenKeys<K> {
+        SeenKeys {
+            dup,
+            stack: Vec::new(),
+        }
+    }
+
+    fn open(&mut self) {
+        if self.dup == DuplicateKeys::Error {
+            self.stack.push(HashSet::new());
+        }
+    }
+
+    fn close(&mut self) {
+        self.stack.pop();
+    }
+
+    fn check(&mut self, key: &K) -> Result<(), Fail> {
+        if let Some(keys) = self.stack.last_mut() {
+            if !keys.insert(key.clone()) {
+                return Err(Fail::new(
+                    ParseErrorKind::DuplicateKey,
+                    format!("Duplicate key {:?} in the object", key.as_ref()),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Packs the events into the JSON tree, this is what parse() uses
+struct TreeBuilder {
+    mem: Vec<Preb>,
+    keys: SeenKeys<String>,
+}
+
+impl TreeBuilder {
+    fn new(dup: DuplicateKeys) -> TreeBuilder {
+        TreeBuilder {
+            mem: Vec::new(),
+            keys: SeenKeys::new(dup),
+        }
+    }
+}
+
+impl Sink for TreeBuilder {
+    fn bgn_obj(&mut self) -> Result<(), Fail> {
+        self.keys.open();
+        self.mem.push(Preb::BgnObj);
+        Ok(())
+    }
+    fn end_obj(&mut self) -> Result<(), Fail> {
+        self.keys.close();
+        pack_object(&mut self.mem, self.keys.dup)?;
+        pack_val(&mut self.mem)
+    }
+    fn bgn_lst(&mut self) -> Result<(), Fail> {
+        self.mem.push(Preb::BgnLst);
+        Ok(())
+    }
+    fn end_lst(&mut self) -> Result<(), Fail> {
+        pack_list(&mut self.mem)?;
+        pack_val(&mut self.mem)
+    }
+    fn key_text(&mut self, key: Text) -> Result<(), Fail> {
+        let key = key.into_string();
+        self.keys.check(&key)?;
+        self.mem.push(Preb::Key(key));
+        Ok(())
+    }
+    fn str_text(&mut self, val: Text) -> Result<(), Fail> {
+        self.mem.push(Preb::Val(JSON::Str(val.into_string())));
+        pack_val(&mut self.mem)
+    }
+    fn prim(&mut self, val: JSON) -> Result<(), Fail> {
+        self.mem.push(Preb::Val(val));
+        pack_val(&mut self.mem)
+    }
+}
+
+// Knobs of the parser, the default is a strict RFC 8259 parser
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    // Take any number Rust's str::parse understands (+1, 1., .5, 01.5, inf, NaN...)
+    // instead of checking the JSON number grammar
+    pub lenient_numbers: bool,
+
+    // Limits for untrusted input, None for no limit and None by default. The
+    // tree is dropped by recursion, so set max_depth for hostile input.
+    pub max_depth: Option<usize>,         // Containers inside each other
+    pub max_string_len: Option<usize>,    // Bytes of a key or string as written in the source
+    pub max_container_len: Option<usize>, // Values of a list or entries of an object
+    pub max_total_bytes: Option<usize>,   // Size of the whole input
+
+    pub duplicate_keys: DuplicateKeys,
+
+    // Relaxed dialects for hand-written files
+    pub comments: bool,        // Allow // and /* */ comments (JSONC)
+    pub trailing_commas: bool, // Allow a ',' before the closing '}' or ']'
+    // Full JSON5: comments, trailing commas, single quoted strings, unquoted
+    // keys, hex, Infinity, NaN, '+1', '.5', '5.' and the JavaScript escapes
+    pub json5: bool,
+}
+
+impl ParseOptions {
+    pub fn parse(&self, json_str: &str) -> Result<JSON, ParseErr> {
+        let mut parser = Parser::with_options(self);
+        parser.feed(json_str)?;
+        parser.finish()
+    }
+
+    // Same as parse() but read the JSON from any reader chunk by chunk,
+    // so the whole document never has to be in memory as a String
+    pub fn parse_reader<R: Read>(&self, mut reader: R) -> Result<JSON, ParseErr> {
+        let mut parser = Parser::with_options(self);
+        let mut buf = [0u8; 8192];
+        let mut pending: Vec<u8> = Vec::new(); // Bytes waiting for the rest of their UTF-8 sequence
+
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    let fail = Fail::new(ParseErrorKind::Io, format!("I/O error: {e}"));
+                    return Err(parser.pda.locate(fail));
+                }
+            };
+            pending.extend_from_slice(&buf[..n]);
+
+            let (valid, invalid) = match std::str::from_utf8(&pending) {
+                Ok(s) => (s.len(), false),
+                // error_len() is None when the sequence is only cut by the end of the chunk
+                Err(e) => (e.valid_up_to(), e.error_len().is_some()),
+            };
+            if let Ok(s) = std::str::from_utf8(&pending[..valid]) {
+                parser.feed(s)?;
+            }
+            if invalid {
+                return Err(parser
+                    .pda
+                    .next_err(ParseErrorKind::InvalidUtf8, "Invalid UTF-8 sequence"));
+            }
+            pending.drain(..valid);
+        }
+
+        if !pending.is_empty() {
+            return Err(parser.pda.next_err(
+                ParseErrorKind::InvalidUtf8,
+                "Incomplete UTF-8 sequence at the end of input",
+            ));
+        }
+        parser.finish()
+    }
+
+    // Walk through the document and only report the events to the handler,
+    // no JSON tree is built so only what the handler keeps is allocated
+    pub fn parse_events<H: JsonHandler + ?Sized>(
+        &self,
+        json_str: &str,
+        handler: &mut H,
+    ) -> Result<(), ParseErr> {
+        let mut pda = Pda::new(self);
+        for ch in json_str.chars() {
+            pda.step(ch, handler)?;
+        }
+        pda.end(handler)
+    }
+}
+
+// The PDA itself, it is fed one character at a time so the input can come
+// from a whole string, chunks of a reader or fragments from a socket.
+// It only keeps track of the structure, the values go to a JsonHandler.
+struct Pda {
+    nest: Vec<Inside>, // Containers we are currently inside, innermost last
+    items: Vec<usize>, // Number of values in each of them
+    state: (Inside, S),
+
+    temp_key: Option<String>,
+    temp_val: Option<String>,
+
+    esc_ch: Escape, // Escape Character decoder
+    quote: char,    // The quote which closes the current string
+    ident: bool,    // Reading a JSON5 unquoted key
+    comment: Option<Comment>,
+
+    ch_pos: usize,
+    line_idx: usize,
+    tok_pos: Pos,    // Where the current key or value token began
+    tok_byte: usize, // Byte offset of the first character of that token
+
+    lazy: bool,            // Don't copy strings until their first escape, see Text
+    byte_pos: usize,       // Byte offset of the current character
+    esc_at: Option<usize>, // Byte offset of the first escape of the current string
+
+    opts: ParseOptions,
+    err_at: Option<(Pos, usize)>, // Where the error is when it is not the current character
+}
+
+impl Pda {
+    fn new(opts: &ParseOptions) -> Pda {
+        Pda {
+            nest: Vec::new(),
+            items: Vec::new(),
+            state: (Inside::Bgn, S::Ready),
+            temp_key: None,
+            temp_val: None,
+            esc_ch: Escape::default(),
+            quote: '"',
+            ident: false,
+            comment: None,
+            ch_pos: 0,
+            line_idx: 1,
+            tok_pos: Pos::default(),
+            tok_byte: 0,
+            lazy: false,
+            byte_pos: 0,
+            esc_at: None,
+            opts: opts.clone(),
+            err_at: None,
+        }
+    }
+
+    fn pos(&self) -> Pos {
+        Pos {
+            line_idx: self.line_idx,
+            ch_pos: self.ch_pos,
+        }
+    }
 
-    let mut temp_key: Option<String> = None;
-    let mut temp_val: Option<String> = None;
+    // Turn a Fail into a ParseErr at the current character, unless the
+    // failing step said it is somewhere else
+    fn locate(&mut self, fail: Fail) -> ParseErr {
+        let (at, byte_pos) = self.err_at.take().unwrap_or((self.pos(), self.byte_pos));
+        ParseErr::e(fail.kind, at, byte_pos, fail.msg)
+    }
 
-    let mut esc_ch: bool = false; // Entering Escape Character mode
+    // An error on the character which is about to come
+    fn next_err(&self, kind: ParseErrorKind, err_msg: &str) -> ParseErr {
+        let at = Pos {
+            line_idx: self.line_idx,
+            ch_pos: self.ch_pos + 1,
+        };
+        ParseErr::e(kind, at, self.byte_pos, err_msg.into())
+    }
 
-    let mut ch_pos: usize = 0;
-    let mut line_idx: usize = 1;
-    for ch in json_str.chars() {
-        // Character and line counter
+    // Character and line counter
+    fn count(&mut self, ch: char) {
         match ch {
             '\n' => {
-                line_idx += 1;
-                ch_pos = 1;
+                self.line_idx += 1;
+                self.ch_pos = 0;
             }
             _ => {
-                ch_pos += 1;
+                self.ch_pos += 1;
             }
         }
+    }
 
-        // Debug zone
-        if line_idx == 11 && ch != ' ' {
-            println!("Start DBG Mode!");
+    fn check_size(&self, ch: char) -> Result<(), Fail> {
+        match self.opts.max_total_bytes {
+            Some(max) if self.byte_pos + ch.len_utf8() > max => Err(Fail::new(
+                ParseErrorKind::InputTooLarge,
+                format!("The input is larger than max_total_bytes ({max})"),
+            )),
+            _ => Ok(()),
         }
+    }
 
-        match state.0 {
-            Inside::Bgn => {
-                match ch {
-                    '{' => {
-                        mem.push(Preb::BgnObj);
-                        state.0 = Inside::Obj;
-                    }
-                    '[' => {
-                        mem.push(Preb::BgnLst);
-                        state.0 = Inside::List;
+    fn step<H: Sink + ?Sized>(&mut self, ch: char, h: &mut H) -> Result<(), ParseErr> {
+        self.count(ch);
+        let res = self
+            .check_size(ch)
+            .and_then(|()| self.transit(ch, h))
+            .map_err(|fail| self.locate(fail));
+        self.byte_pos += ch.len_utf8();
+        res
+    }
+
+    fn transit<H: Sink + ?Sized>(&mut self, ch: char, h: &mut H) -> Result<(), Fail> {
+        if let Some(comment) = self.comment {
+            return self.comment_char(comment, ch);
+        }
+        // Inside of a token, characters are collected until the token ends
+        match self.state.1 {
+            S::BgnKey if self.ident => {
+                if is_ident_part(ch) {
+                    self.string_len(self.byte_pos + ch.len_utf8() - self.tok_byte)?;
+                    if let Some(tk) = self.temp_key.as_mut().filter(|_| !self.lazy) {
+                        tk.push(ch);
                     }
-                    ' ' | '\n' | '\r' | '\t' => { /* Do nothing ...*/ }
-                    _ => {
-                        return Err(ParseErr::e(
-                            line_idx,
-                            ch_pos,
-                            "Expected \'{{\' or \'[\'.".into(),
-                        ));
+                    return Ok(());
+                }
+                // Like the delimiter of a primitive, handled below after the key
+                self.end_ident(h)?;
+            }
+            S::BgnKey => return self.key_char(ch, h),
+            S::BgnStrV => return self.str_char(ch, h),
+            S::BgnPrimV => {
+                let slash = ch == '/' && (self.opts.comments || self.opts.json5);
+                if !(self.is_space(ch) || slash || matches!(ch, ',' | '}' | ']')) {
+                    if let Some(tv) = self.temp_val.as_mut() {
+                        tv.push(ch);
                     }
+                    return Ok(());
                 }
+                // The delimiter ends the primitive, then it is handled below as usual
+                self.end_prim(h)?;
             }
-            Inside::Obj => {
-                match state.1 {
-                    S::Ready => {
-                        match ch {
-                            ' ' | '\n' | '\r' | '\t' => {} // Do nothing if space bar is entered
-                            '}' => {
-                                // End of object, try to pack up the previous entry
-                                if let Err(err_msg) = pack_object(&mut mem) {
-                                    return Err(ParseErr::e(line_idx, ch_pos, err_msg.into()));
-                                }
-                                state.0 = inside_what(&mem);
-                                state.1 = S::EndPrimV;
-                            }
-                            '\"' => {
-                                state.1 = S::BgnKey;
-                                temp_key = Some(String::new());
-                            }
-                            _ => {
-                                return Err(ParseErr::e(
-                                    line_idx,
-                                    ch_pos,
-                                    "Expected a String value as key.".into(),
-                                ));
-                            }
-                        }
+            _ => {}
+        }
+
+        // Whitespace and comments are allowed between any two tokens
+        if self.is_space(ch) {
+            return Ok(());
+        }
+        if ch == '/' && (self.opts.comments || self.opts.json5) {
+            self.comment = Some(Comment::Slash);
+            return Ok(());
+        }
+        let trailing_commas = self.opts.trailing_commas || self.opts.json5;
+
+        match self.state.0 {
+            Inside::Bgn => self.bgn_val(ch, h, "Expected a JSON value."),
+            Inside::Obj => match self.state.1 {
+                S::Ready => match ch {
+                    '}' => self.end_ctnr(h),
+                    _ => self.bgn_key(ch),
+                },
+                S::ExpectKey => match ch {
+                    '}' if trailing_commas => self.end_ctnr(h),
+                    _ => self.bgn_key(ch),
+                },
+                S::EndKey => match ch {
+                    ':' => {
+                        self.state.1 = S::ExpectVal;
+                        Ok(())
                     }
-                    S::ExpectKey => match ch {
-                        ' ' | '\n' | '\r' | '\t' => {}
-                        '\"' => {
-                            state.1 = S::BgnKey;
-                            temp_key = Some(String::new());
-                        }
-                        _ => {
-                            return Err(ParseErr::e(
-                                line_idx,
-                                ch_pos,
-                                "Expected a String value as key.".into(),
-                            ));
-                        }
-                    },
-                    S::BgnKey => {
-                        if esc_ch {
-                            match get_esc_char(ch) {
-                                Ok(esc_char) => {
-                                    if let Some(tk) = &mut temp_key {
-                                        tk.push_str(&esc_char);
-                                    } else {
-                                        return Err(ParseErr::e(
-                                            line_idx,
-                                            ch_pos,
-                                            "String key not yet initialize.".into(),
-                                        ));
-                                    }
-                                }
-                                Err(err_msg) => {
-                                    return Err(ParseErr::e(line_idx, ch_pos, err_msg.into()));
-                                }
-                            }
-                            esc_ch = false;
-                        } else {
-                            match ch {
-                                '\\' => {
-                                    esc_ch = true;
-                                }
-                                '\"' => {
-                                    state.1 = S::EndKey;
-                                    if let Some(tk) = &mut temp_key {
-                                        mem.push(Preb::Key(tk.clone()));
-                                    } else {
-                                        return Err(ParseErr::e(line_idx, ch_pos, "String key not yet initialize, but its was ending of key...?".into()));
-                                    }
-                                    temp_key = None;
-                                }
-                                _ => {
-                                    if let Some(tk) = &mut temp_key {
-                                        tk.push(ch);
-                                    } else {
-                                        return Err(ParseErr::e(line_idx, ch_pos, "String key not yet initialize, but its there was a character coming????".into()));
-                                    }
-                                }
-                            }
-                        }
+                    _ => Err(Fail::new(
+                        ParseErrorKind::ExpectedColon,
+                        "Expected ':' followed by value of given key",
+                    )),
+                },
+                S::ExpectVal => self.bgn_val(ch, h, "Expected a Primitive Value!"),
+                S::EndPrimV | S::EndStrV => match ch {
+                    ',' => {
+                        self.state.1 = S::ExpectKey;
+                        Ok(())
                     }
-                    S::EndKey => {
-                        match ch {
-                            ':' => {
-                                state.1 = S::ExpectVal;
-                            }
-                            ' ' | '\t' | '\r' | '\n' => {}
-                            _ => {
-                                // Crash here because of unexpected character
-                                return Err(ParseErr::e(
-                                    line_idx,
-                                    ch_pos,
-                                    "Expected ':' followed by value of given key".into(),
-                                ));
-                            }
-                        }
+                    '}' => self.end_ctnr(h),
+                    ']' => Err(Fail::unexpected(
+                        "Unexpected ']'! You are inside an Object, not a List!",
+                    )),
+                    _ => Err(Fail::unexpected(
+                        "Expected '}' to finish the object or ',' for next entry",
+                    )),
+                },
+                S::BgnKey | S::BgnStrV | S::BgnPrimV => {
+                    Err(Fail::unexpected("Unexpected state inside an Object"))
+                }
+            },
+            Inside::List => match self.state.1 {
+                S::Ready => match ch {
+                    ']' => self.end_ctnr(h),
+                    _ => self.bgn_val(
+                        ch,
+                        h,
+                        "Expected value to be number, string, true, false or null",
+                    ),
+                },
+                S::ExpectVal => match ch {
+                    ']' if trailing_commas => self.end_ctnr(h),
+                    _ => self.bgn_val(
+                        ch,
+                        h,
+                        "Expected value to be number, string, true, false or null",
+                    ),
+                },
+                S::EndPrimV | S::EndStrV => match ch {
+                    ',' => {
+                        self.state.1 = S::ExpectVal;
+                        Ok(())
                     }
-                    S::ExpectVal => match ch {
-                        '{' => {
-                            state.0 = Inside::Obj;
-                            state.1 = S::Ready;
-                            mem.push(Preb::BgnObj);
-                        }
-                        '[' => {
-                            state.0 = Inside::List;
-                            state.1 = S::Ready;
-                            mem.push(Preb::BgnLst);
-                        }
-                        '\"' => {
-                            state.1 = S::BgnStrV;
-                            temp_val = Some(String::new());
-                        }
-                        '0'..='9' | '-' | 't' | 'f' | 'n' => {
-                            state.1 = S::BgnPrimV;
-                            temp_val = Some(String::from(ch));
-                        }
-                        ' ' | '\t' => {}
-                        _ => {
-                            return Err(ParseErr::e(
-                                line_idx,
-                                ch_pos,
-                                "Expected a Primitive Value!".into(),
-                            ));
-                        }
-                    },
-                    S::BgnPrimV => {
-                        match ch {
-                            ',' => {
-                                // Now we're in Obj, we can pack the entry
-                                if let Some(tv) = &temp_val {
-                                    match primitive_parse(&tv) {
-                                        Ok(pv) => {
-                                            mem.push(Preb::Val(pv));
-
-                                            // Pack entry imedietly
-                                            if let Err(err_msg) = pack_entry(&mut mem) {
-                                                return Err(ParseErr::e(
-                                                    line_idx,
-                                                    ch_pos,
-                                                    err_msg.into(),
-                                                ));
-                                            }
-                                        }
-                                        Err(err_msg) => {
-                                            return Err(ParseErr::e(
-                                                line_idx,
-                                                ch_pos,
-                                                err_msg.into(),
-                                            ));
-                                        }
-                                    }
-                                } else {
-                                    return Err(ParseErr::e(
-                                        line_idx,
-                                        ch_pos,
-                                        "Value is not yet initialize".into(),
-                                    ));
-                                }
-                                // If everything pass, safe to reset the temp_val
-                                temp_val = None;
-                                state.1 = S::ExpectKey;
-                            }
-                            '}' => {
-                                // This imply that it is the end of object
-                                // What we do is: parse value, packup entry, packup object,
-                                //      change state of state.0 to result of inside_what(&mem)
-                                // Example { ... "k12":true}
-                                // However, there should be a verification that we're currently in Obj
-                                // ^ No need since state.0 already know where we are
-
-                                if let Some(tv) = &temp_val {
-                                    match primitive_parse(&tv) {
-                                        Ok(pv) => {
-                                            mem.push(Preb::Val(pv));
-                                            if let Err(err_msg) = pack_entry(&mut mem) {
-                                                return Err(ParseErr::e(
-                                                    line_idx,
-                                                    ch_pos,
-                                                    err_msg.into(),
-                                                ));
-                                            }
-                                            if let Err(err_msg) = pack_object(&mut mem) {
-                                                return Err(ParseErr::e(
-                                                    line_idx,
-                                                    ch_pos,
-                                                    err_msg.into(),
-                                                ));
-                                            }
-                                        }
-                                        Err(err_msg) => {
-                                            return Err(ParseErr::e(
-                                                line_idx,
-                                                ch_pos,
-                                                err_msg.into(),
-                                            ));
-                                        }
-                                    }
-                                } else {
-                                    return Err(ParseErr::e(
-                                        line_idx,
-                                        ch_pos,
-                                        "Value is not yet initialize".into(),
-                                    ));
-                                }
-                                temp_val = None;
-                                // Update where we are
-                                state.0 = inside_what(&mem);
-                                state.1 = S::EndPrimV;
-                            }
-                            ']' => {
-                                // This also imply that it is the end of list
-                                // Example: .... -12, 34]
-                                // Same as '}' but we check that we're in Lst
-                                // Since we're in Inside:Obj -> just crash!
-                                return Err(ParseErr::e(
-                                    line_idx,
-                                    ch_pos,
-                                    "Unexpected ']'! You are inside an Object, not a List!"
-                                        .into(),
-                                ));
-                            }
-                            ' ' | '\n' | '\r' | '\t' => {
-                                // I think we safe to pack entry here
-                                if let Some(tv) = &temp_val {
-                                    match primitive_parse(&tv) {
-                                        Ok(pv) => {
-                                            mem.push(Preb::Val(pv));
-                                            if let Err(err_msg) = pack_entry(&mut mem) {
-                                                return Err(ParseErr::e(
-                                                    line_idx,
-                                                    ch_pos,
-                                                    err_msg.into(),
-                                                ));
-                                            }
-                                        }
-                                        Err(err_msg) => {
-                                            return Err(ParseErr::e(
-                                                line_idx,
-                                                ch_pos,
-                                                err_msg.into(),
-                                            ));
-                                        }
-                                    }
-                                } else {
-                                    return Err(ParseErr::e(
-                                        line_idx,
-                                        ch_pos,
-                                        "Value is not given".into(),
-                                    ));
-                                }
-                                temp_val = None;
-                                state.1 = S::EndPrimV;
-                            }
-                            _ => {
-                                if let Some(tv) = &mut temp_val {
-                                    tv.push(ch);
-                                } else {
-                                    return Err(ParseErr::e(
-                                        line_idx,
-                                        ch_pos,
-                                        "Primitive value is not yet initialized".into(),
-                                    ));
-                                }
-                            }
-                        }
-                    }
-                    S::EndPrimV => {
-                        // This state will occur when after parsing the primitive value
-                        // In other words, only when found the ' ', '\t' or '\n'
-                        // Check that top of stack is entry already... if not then pack it
-                        if let Some(preb) = &mem.last() {
-                            match preb {
-                                Preb::Ent(_, _) => {}
-                                Preb::Val(_) => {
-                                    if let Err(err_msg) = pack_entry(&mut mem) {
-                                        return Err(ParseErr::e(
-                                            line_idx,
-                                            ch_pos,
-                                            err_msg.into(),
-                                        ));
-                                    }
-                                }
-                                _ => {
-                                    return Err(ParseErr::e(
-                                        line_idx,
-                                        ch_pos,
-                                        "Expected Value".into(),
-                                    ));
-                                }
-                            }
-                        }
+                    ']' => self.end_ctnr(h),
+                    '}' => Err(Fail::unexpected(
+                        "Unexpected '}'! You are inside a List, not an Object!",
+                    )),
+                    _ => Err(Fail::unexpected(
+                        "Expected ']' to finish the list or ',' for next value",
+                    )),
+                },
+                S::ExpectKey => Err(Fail::unexpected("state:ExpectKey is not allowed!")),
+                S::BgnKey => Err(Fail::unexpected("state:BgnKey is not allowed!")),
+                S::EndKey => Err(Fail::unexpected("state:EndKey is not allowed!")),
+                S::BgnStrV | S::BgnPrimV => Err(Fail::unexpected("Unexpected state inside a List")),
+            },
+            Inside::End => Err(Fail::new(
+                ParseErrorKind::TrailingData,
+                "Any character after the end of root container is not allowed.",
+            )),
+        }
+    }
 
-                        match ch {
-                            ',' => {
-                                // Maybe safe to jump to start
-                                // if let Res::Fail(err_msg) = pack_entry(&mut mem) {
-                                //     err_panic(line_idx, ch_pos, err_msg);
-                                // }
-                                state.1 = S::ExpectKey;
-                            }
-                            '}' => {
-                                // The problem might occur when entry is already packed
-                                // This imply that it is the end of object
-                                // What we do is: just pack the object (Packing entry is done when found ' ', '\n', '\t' on  previous state)
-                                // Example { ... "k12": true }
+    // Begin a new value of any type
+    fn bgn_val<H: Sink + ?Sized>(
+        &mut self,
+        ch: char,
+        h: &mut H,
+        err_msg: &'static str,
+    ) -> Result<(), Fail> {
+        match ch {
+            '{' => {
+                self.open(Inside::Obj)?;
+                h.bgn_obj()?;
+                self.state = (Inside::Obj, S::Ready);
+            }
+            '[' => {
+                self.open(Inside::List)?;
+                h.bgn_lst()?;
+                self.state = (Inside::List, S::Ready);
+            }
+            '\"' | '\'' if ch == '\"' || self.opts.json5 => {
+                self.state.1 = S::BgnStrV;
+                self.quote = ch;
+                self.temp_val = Some(String::new());
+                self.tok_pos = self.pos();
+                self.tok_byte = self.byte_pos;
+                self.esc_at = None;
+            }
+            // '+', '.', 'I' and 'N' can't begin a JSON value, they are taken
+            // anyway so the number check can say what is wrong with them
+            '0'..='9' | '-' | 't' | 'f' | 'n' | '+' | '.' | 'I' | 'N' => {
+                self.state.1 = S::BgnPrimV;
+                self.temp_val = Some(String::from(ch));
+                self.tok_pos = self.pos();
+                self.tok_byte = self.byte_pos;
+            }
+            _ => return Err(Fail::unexpected(err_msg)),
+        }
+        Ok(())
+    }
 
-                                // Check the top of mem stack before pack object
+    fn open(&mut self, inside: Inside) -> Result<(), Fail> {
+        if let Some(max) = self.opts.max_depth {
+            if self.nest.len() >= max {
+                return Err(Fail::new(
+                    ParseErrorKind::DepthExceeded,
+                    format!("The containers are nested deeper than max_depth ({max})"),
+                ));
+            }
+        }
+        self.nest.push(inside);
+        self.items.push(0);
+        Ok(())
+    }
 
-                                if let Err(err_msg) = pack_object(&mut mem) {
-                                    return Err(ParseErr::e(line_idx, ch_pos, err_msg.into()));
-                                }
-                                // Update where we are
-                                state.0 = inside_what(&mem);
-                                state.1 = S::EndPrimV; // Objects are count as Primitive Value
-                            }
-                            ']' => {
-                                return Err(ParseErr::e(
-                                    line_idx,
-                                    ch_pos,
-                                    "Unexpected ']'! You are inside an Object, not a List!"
-                                        .into(),
-                                ));
-                            }
-                            ' ' | '\t' | '\r' | '\n' => {} // Ignore case
-                            _ => {
-                                return Err(ParseErr::e(
-                                    line_idx,
-                                    ch_pos,
-                                    "Expected '}' to finish the object".into(),
-                                ));
-                            }
-                        }
-                    }
-                    S::BgnStrV => {
-                        if esc_ch {
-                            if let Some(tv) = &mut temp_val {
-                                match get_esc_char(ch) {
-                                    Ok(esc_char) => {
-                                        tv.push_str(&esc_char);
-                                    }
-                                    Err(err_msg) => {
-                                        return Err(ParseErr::e(
-                                            line_idx,
-                                            ch_pos,
-                                            err_msg.into(),
-                                        ));
-                                    }
-                                }
-                            } else {
-                                return Err(ParseErr::e(
-                                    line_idx,
-                                    ch_pos,
-                                    "String value is not yet initialized".into(),
-                                ));
-                            }
-                            esc_ch = false;
-                        } else {
-                            match ch {
-                                '\\' => {
-                                    esc_ch = true;
-                                }
-                                '\"' => {
-                                    // push string value to the mem
-                                    // safe to pack entry
-                                    if let Some(tv) = &temp_val {
-                                        mem.push(Preb::Val(JSON::Str(tv.clone())));
-                                    } else {
-                                        return Err(ParseErr::e(line_idx, ch_pos,"Can't saved to mem since string value is not yet initialized".into()));
-                                    }
-
-                                    if let Err(err_msg) = pack_entry(&mut mem) {
-                                        return Err(ParseErr::e(
-                                            line_idx,
-                                            ch_pos,
-                                            err_msg.into(),
-                                        ));
-                                    } else {
-                                        temp_val = None;
-                                    }
-
-                                    state.1 = S::EndStrV;
-                                }
-                                _ => {
-                                    // keep pushing the ch to temp_value
-                                    if let Some(tv) = &mut temp_val {
-                                        tv.push(ch);
-                                    } else {
-                                        return Err(ParseErr::e(
-                                            line_idx,
-                                            ch_pos,
-                                            "String value is not yet initialize".into(),
-                                        ));
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    S::EndStrV => {
-                        match ch {
-                            '}' => {
-                                // Example case: { ... ,"key1": "value" }
-                                if let Err(err_msg) = pack_object(&mut mem) {
-                                    return Err(ParseErr::e(line_idx, ch_pos, err_msg.into()));
-                                }
-                                state.0 = inside_what(&mem);
-                                state.1 = S::EndPrimV;
-                            }
-                            ',' => {
-                                // Example case: { ... ,"key1": "value", ... }
-                                state.1 = S::ExpectKey;
-                            }
-                            ' ' | '\t' | '\r' | '\n' => {} // ignore case
-                            _ => {
-                                return Err(ParseErr::e(
-                                    line_idx,
-                                    ch_pos,
-                                    "Unexpected any character after end the String value"
-                                        .into(),
-                                ));
-                            }
-                        }
-                    }
-                }
+    fn bgn_key(&mut self, ch: char) -> Result<(), Fail> {
+        match ch {
+            '\"' => {}
+            '\'' if self.opts.json5 => {}
+            _ if self.opts.json5 && is_ident_start(ch) => self.string_len(ch.len_utf8())?,
+            _ => {
+                let err_msg = "Expected a String value as key.";
+                return Err(Fail::new(ParseErrorKind::ExpectedKey, err_msg));
             }
-            Inside::List => {
-                match state.1 {
-                    S::Ready => match ch {
-                        '{' => {
-                            state.0 = Inside::Obj;
-                            state.1 = S::Ready;
-                            mem.push(Preb::BgnObj);
-                        }
-                        '[' => {
-                            state.0 = Inside::List;
-                            state.1 = S::Ready;
-                            mem.push(Preb::BgnLst);
-                        }
-                        ']' => {
-                            if let Err(err_msg) = pack_list(&mut mem) {
-                                return Err(ParseErr::e(line_idx, ch_pos, err_msg.into()));
-                            }
-                            state.0 = inside_what(&mem);
-                            state.1 = S::EndPrimV;
-                        }
-                        '\"' => {
-                            state.1 = S::BgnStrV;
-                            temp_val = Some(String::new());
-                        }
-                        '0'..='9' | '-' | 't' | 'f' | 'n' => {
-                            state.1 = S::BgnPrimV;
-                            temp_val = Some(String::from(ch));
-                        }
-                        ' ' | '\t' | '\r' | '\n' => {}
-                        _ => {
-                            return Err(ParseErr::e(
-                                line_idx,
-                                ch_pos,
-                                "Expected value to be number, string, true, false or null"
-                                    .into(),
-                            ));
-                        }
-                    },
-                    S::ExpectKey => {
-                        return Err(ParseErr::e(
-                            line_idx,
-                            ch_pos,
-                            "state:ExpectKey is not allowed!".into(),
-                        ));
-                    }
-                    S::BgnKey => {
-                        return Err(ParseErr::e(
-                            line_idx,
-                            ch_pos,
-                            "state:BgnKey is not allowed!".into(),
-                        ));
-                    }
-                    S::EndKey => {
-                        return Err(ParseErr::e(
-                            line_idx,
-                            ch_pos,
-                            "state:EndKey is not allowed!".into(),
-                        ));
-                    }
-                    S::ExpectVal => match ch {
-                        '{' => {
-                            state.0 = Inside::Obj;
-                            state.1 = S::Ready;
-                            mem.push(Preb::BgnObj);
-                        }
-                        '[' => {
-                            state.0 = Inside::List;
-                            state.1 = S::Ready;
-                            mem.push(Preb::BgnLst);
-                        }
-                        '\"' => {
-                            state.1 = S::BgnStrV;
-                            temp_val = Some(String::new());
-                        }
-                        '0'..='9' | '-' | 't' | 'f' | 'n' => {
-                            state.1 = S::BgnPrimV;
-                            temp_val = Some(String::from(ch));
-                        }
-                        ' ' | '\t' | '\r' | '\n' => {}
-                        _ => {
-                            return Err(ParseErr::e(
-                                line_idx,
-                                ch_pos,
-                                "Expected value to be number, string, true, false or null"
-                                    .into(),
-                            ));
-                        }
-                    },
-                    S::BgnPrimV => {
-                        match ch {
-                            ',' => {
-                                // Now we're in List, we just parse and put in mem
-                                if let Some(tv) = &temp_val {
-                                    match primitive_parse(&tv) {
-                                        Ok(pv) => {
-                                            mem.push(Preb::Val(pv));
-                                        }
-                                        Err(err_msg) => {
-                                            return Err(ParseErr::e(
-                                                line_idx,
-                                                ch_pos,
-                                                err_msg.into(),
-                                            ));
-                                        }
-                                    }
-                                } else {
-                                    return Err(ParseErr::e(
-                                        line_idx,
-                                        ch_pos,
-                                        "Value is not given".into(),
-                                    ));
-                                }
-                                temp_val = None;
-                                state.1 = S::ExpectVal;
-                            }
-                            '}' => {
-                                return Err(ParseErr::e(
-                                    line_idx,
-                                    ch_pos,
-                                    "Unexpected '}', currently inside a list not an Object!"
-                                        .into(),
-                                ));
-                            }
-                            ']' => {
-                                // Example case [... 13, 12]
-                                if let Some(tv) = &temp_val {
-                                    match primitive_parse(tv) {
-                                        Ok(pv) => {
-                                            mem.push(Preb::Val(pv));
-                                            if let Err(err_msg) = pack_list(&mut mem) {
-                                                return Err(ParseErr::e(
-                                                    line_idx,
-                                                    ch_pos,
-                                                    err_msg.into(),
-                                                ));
-                                            }
-                                        }
-                                        Err(err_msg) => {
-                                            return Err(ParseErr::e(
-                                                line_idx,
-                                                ch_pos,
-                                                err_msg.into(),
-                                            ));
-                                        }
-                                    }
-                                } else {
-                                    return Err(ParseErr::e(
-                                        line_idx,
-                                        ch_pos,
-                                        "Value is not yet initialized".into(),
-                                    ));
-                                }
-                                temp_val = None;
-                                // Update where we are
-                                state.0 = inside_what(&mem);
-                            }
-                            ' ' | '\n' | '\r' | '\t' => { // TODO: Suspicious for bug!!
-                                if let Some(tv) = &temp_val {
-                                    match primitive_parse(&tv) {
-                                        Ok(pv) => {
-                                            mem.push(Preb::Val(pv));
-                                            state.1 = S::EndPrimV;
-                                            // if let Err(err_msg) = pack_entry(&mut mem) {
-                                            //     return Err(ParseErr::e(
-                                            //         line_idx,
-                                            //         ch_pos,
-                                            //         err_msg.to_string(),
-                                            //     ));
-                                            // }
-                                        }
-                                        Err(err_msg) => {
-                                            return Err(ParseErr::e(
-                                                line_idx,
-                                                ch_pos,
-                                                err_msg.into(),
-                                            ));
-                                        }
-                                    }
-                                } else {
-                                    return Err(ParseErr::e(
-                                        line_idx,
-                                        ch_pos,
-                                        "Value is not yet initialized".into(),
-                                    ));
-                                }
-                                temp_val = None;
-                                state.1 = S::EndPrimV;
-                            }
-                            _ => {
-                                if let Some(tv) = &mut temp_val {
-                                    tv.push(ch);
-                                } else {
-                                    return Err(ParseErr::e(
-                                        line_idx,
-                                        ch_pos,
-                                        "Primitive value is not yet initialized".into(),
-                                    ));
-                                }
-                            }
-                        }
-                    }
-                    S::EndPrimV => {
-                        match ch {
-                            ',' => {
-                                // Maybe safe to jump to start
-                                state.1 = S::ExpectVal;
-                            }
-                            ']' => {
-                                // This imply that it is the end of list
-                                // What we do is: just pack the object
-                                // Example [ ... "k12", true ]
-                                if let Err(err_msg) = pack_list(&mut mem) {
-                                    return Err(ParseErr::e(line_idx, ch_pos, err_msg.into()));
-                                }
-                                // Update where we are
-                                state.0 = inside_what(&mem);
-                                state.1 = S::EndPrimV; // Objects are count as Primitive Value
-                            }
-                            '}' => {
-                                return Err(ParseErr::e(
-                                    line_idx,
-                                    ch_pos,
-                                    "Unexpected ']'! You are inside a List, not an Object!"
-                                        .into(),
-                                ));
-                            }
-                            ' ' | '\t' | '\r' | '\n' => {}
-                            _ => {
-                                return Err(ParseErr::e(
-                                    line_idx,
-                                    ch_pos,
-                                    "Expected ']' to finish the list or ',' for next value"
-                                        .into(),
-                                ));
-                            }
-                        }
-                    }
-                    S::BgnStrV => {
-                        if esc_ch {
-                            if let Some(tv) = &mut temp_val {
-                                match get_esc_char(ch) {
-                                    Ok(esc_char) => {
-                                        tv.push_str(&esc_char);
-                                    }
-                                    Err(err_msg) => {
-                                        return Err(ParseErr::e(
-                                            line_idx,
-                                            ch_pos,
-                                            err_msg.into(),
-                                        ));
-                                    }
-                                }
-                            } else {
-                                return Err(ParseErr::e(
-                                    line_idx,
-                                    ch_pos,
-                                    "String value is not yet initialized".into(),
-                                ));
-                            }
-                            esc_ch = false;
-                        } else {
-                            match ch {
-                                '\\' => {
-                                    esc_ch = true;
-                                }
-                                '\"' => {
-                                    // push string value to the mem
-                                    // safe to pack entry
-                                    if let Some(tv) = &temp_val {
-                                        mem.push(Preb::Val(JSON::Str(tv.clone())));
-                                    } else {
-                                        return Err(ParseErr::e(line_idx, ch_pos,"Can't saved to mem since string value is not yet initialized".into()));
-                                    }
-                                    state.1 = S::EndStrV;
-                                }
-                                _ => {
-                                    // keep pushing the ch to temp_value
-                                    if let Some(tv) = &mut temp_val {
-                                        tv.push(ch);
-                                    } else {
-                                        return Err(ParseErr::e(
-                                            line_idx,
-                                            ch_pos,
-                                            "String value is not yet initialize".into(),
-                                        ));
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    S::EndStrV => {
-                        match ch {
-                            ']' => {
-                                // Example case: [ ... ,"value1", "value2" ]
-                                if let Err(err_msg) = pack_list(&mut mem) {
-                                    return Err(ParseErr::e(line_idx, ch_pos, err_msg.into()));
-                                }
-                                state.0 = inside_what(&mem);
-                                state.1 = S::EndPrimV;
-                            }
-                            ',' => {
-                                // Example case: [ ... ,"key1", "value", ... ]
-                                state.1 = S::ExpectVal;
-                            }
-                            ' ' | '\t' | '\r' | '\n' => {} // ignore case
-                            _ => {
-                                return Err(ParseErr::e(
-                                    line_idx,
-                                    ch_pos,
-                                    "Unexpect any char after end of the String value".into(),
-                                ));
-                            }
-                        }
-                    } 
-                }
+        }
+        self.state.1 = S::BgnKey;
+        self.quote = ch;
+        self.ident = !matches!(ch, '\"' | '\'');
+        self.temp_key = Some(match self.ident {
+            true if !self.lazy => String::from(ch),
+            _ => String::new(),
+        });
+        self.tok_pos = self.pos();
+        self.tok_byte = self.byte_pos;
+        self.esc_at = None;
+        Ok(())
+    }
+
+    // The character after an unquoted key
+    fn end_ident<H: Sink + ?Sized>(&mut self, h: &mut H) -> Result<(), Fail> {
+        self.ident = false;
+        let tail = self.temp_key.take();
+        let key = if self.lazy {
+            Text {
+                raw: self.tok_byte..self.byte_pos,
+                tail: None,
             }
-            Inside::End => match ch {
-                ' ' | '\t' | '\r' | '\n' => {}
-                _ => {
-                    return Err(ParseErr::e(
-                        line_idx,
-                        ch_pos,
-                        "Any character after the end of root container is not allowed.".into(),
-                    ));
-                }
-            },
+        } else {
+            Text { raw: 0..0, tail }
+        };
+        self.put_key(key, h)
+    }
+
+    fn comment_char(&mut self, comment: Comment, ch: char) -> Result<(), Fail> {
+        if comment == Comment::Slash && !matches!(ch, '/' | '*') {
+            let err_msg = "Expected '/' or '*' after '/' to begin a comment";
+            return Err(Fail::unexpected(err_msg));
         }
+        self.comment = comment.after(ch);
+        Ok(())
     }
-    // Extract value from mem<Preb> to final_obj
-    if state.0 == (Inside::End) {
-        if mem.len() == 1 {
-            if let Some(fobj) = mem.pop() {
-                match fobj {
-                    Preb::Val(final_object) => match final_object {
-                        JSON::Lst(final_object) => Ok(JSON::Lst(final_object)),
-                        JSON::Obj(final_object) => Ok(JSON::Obj(final_object)),
-                        _ => Err(ParseErr::e(
-                            line_idx,
-                            ch_pos,
-                            "Unexpected root JSON data type".into(),
-                        )),
-                    },
-                    _ => Err(ParseErr::e(
-                        line_idx,
-                        ch_pos,
-                        "Unexpected final tokens in parser memory".into(),
-                    )),
-                }
-            } else {
-                Err(ParseErr::e(
-                    line_idx,
-                    ch_pos,
-                    "No data in parser memory".into(),
-                ))
+
+    fn is_space(&self, ch: char) -> bool {
+        match ch {
+            ' ' | '\n' | '\r' | '\t' => true,
+            // The other whitespace of JavaScript
+            '\u{000B}' | '\u{000C}' | '\u{00A0}' | '\u{FEFF}' | '\u{2028}' | '\u{2029}' => {
+                self.opts.json5
             }
+            _ => self.opts.json5 && is_unicode_space(ch),
+        }
+    }
+
+    // A key or string of len bytes so far, quoted or not
+    fn string_len(&self, len: usize) -> Result<(), Fail> {
+        match self.opts.max_string_len {
+            Some(max) if len > max => Err(Fail::new(
+                ParseErrorKind::StringTooLong,
+                format!("The string is longer than max_string_len ({max} bytes)"),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // Collect one character of a string token, give the whole Text back on its closing '"'
+    fn text_char(&mut self, ch: char) -> Result<Option<Text>, Fail> {
+        if ch != self.quote || self.esc_ch.on {
+            self.string_len(self.byte_pos + ch.len_utf8() - self.tok_byte - 1)?;
+        }
+        let temp = if self.state.1 == S::BgnKey {
+            &mut self.temp_key
         } else {
-            Err(ParseErr::e(
-                line_idx,
-                ch_pos,
-                "There is no or more than one JSON structure in a single file".into(),
-            ))
+            &mut self.temp_val
+        };
+        let Some(buf) = temp.as_mut() else {
+            return Ok(None);
+        };
+        if self.lazy && self.esc_at.is_none() {
+            match ch {
+                '\\' => self.esc_at = Some(self.byte_pos),
+                _ if ch == self.quote => {}
+                _ => return Ok(None), // Still a plain range of the input, nothing to copy
+            }
         }
-    } else {
-        Err(ParseErr::e(
-            line_idx,
-            ch_pos,
-            "Incomplete JSON structure".into(),
-        ))
+        let escaped = self.esc_ch.feed(ch, buf, self.opts.json5);
+        if escaped.map_err(|err_msg| Fail::new(ParseErrorKind::InvalidEscape, err_msg))? {
+            return Ok(None);
+        }
+        if ch != self.quote {
+            buf.push(ch);
+            return Ok(None);
+        }
+
+        let tail = temp.take();
+        Ok(Some(if !self.lazy {
+            Text { raw: 0..0, tail }
+        } else if let Some(esc_at) = self.esc_at {
+            Text {
+                raw: self.tok_byte + 1..esc_at,
+                tail,
+            }
+        } else {
+            Text {
+                raw: self.tok_byte + 1..self.byte_pos,
+                tail: None,
+            }
+        }))
+    }
+
+    fn key_char<H: Sink + ?Sized>(&mut self, ch: char, h: &mut H) -> Result<(), Fail> {
+        if let Some(key) = self.text_char(ch)? {
+            self.put_key(key, h)?;
+        }
+        Ok(())
+    }
+
+    fn put_key<H: Sink + ?Sized>(&mut self, key: Text, h: &mut H) -> Result<(), Fail> {
+        if let Err(fail) = h.key_text(key) {
+            // Errors about the key point at its beginning
+            self.err_at = Some((self.tok_pos, self.tok_byte));
+            return Err(fail);
+        }
+        self.state.1 = S::EndKey;
+        Ok(())
+    }
+
+    fn str_char<H: Sink + ?Sized>(&mut self, ch: char, h: &mut H) -> Result<(), Fail> {
+        if let Some(val) = self.text_char(ch)? {
+            h.str_text(val)?;
+            self.val_done()?;
+            self.state.1 = S::EndStrV;
+        }
+        Ok(())
     }
-}
 
-fn pretty_print(obj: &JSON, lvl: usize, indent_str: &str) -> Result<(), String>{
-    match obj {
-        JSON::Lst(lst) => {
-            if lvl == 0 {
-                println!("[");
+    fn end_prim<H: Sink + ?Sized>(&mut self, h: &mut H) -> Result<(), Fail> {
+        let tv = self.temp_val.take().unwrap_or_default();
+        let val = primitive_parse(&tv, &self.opts).map_err(|(at, fail)| {
+            // Numbers are ASCII, the faulty character is on the line of the token
+            let pos = Pos {
+                line_idx: self.tok_pos.line_idx,
+                ch_pos: self.tok_pos.ch_pos + at,
+            };
+            self.err_at = Some((pos, self.tok_byte + at));
+            fail
+        })?;
+        h.prim(val)?;
+        self.val_done()
+    }
+
+    // Close the innermost container, the caller already checked that it is the right one
+    fn end_ctnr<H: Sink + ?Sized>(&mut self, h: &mut H) -> Result<(), Fail> {
+        self.items.pop();
+        match self.nest.pop() {
+            Some(Inside::Obj) => h.end_obj()?,
+            Some(Inside::List) => h.end_lst()?,
+            _ => {
+                let err_msg = "There is no container to be closed";
+                return Err(Fail::unexpected(err_msg));
             }
-            let len = lst.len();
-            for i in 0..len {
-                let e_item = lst.get(i).unwrap();
-                match e_item {
-                    JSON::Int(i) => { print!("{}{i}", indent_str.repeat(lvl+1)); },
-                    JSON::Flt(f) => { print!("{}{f}", indent_str.repeat(lvl+1)); },
-                    JSON::Str(s) => { print!("{}\"{s}\"", indent_str.repeat(lvl+1)); },
-                    JSON::Lst(_) => {
-                        println!("{}[", indent_str.repeat(lvl+1));
-                        pretty_print(e_item, lvl+1, indent_str)?
-                    },
-                    JSON::Obj(_) => {
-                        println!("{}{{", indent_str.repeat(lvl+1)); 
-                        pretty_print(e_item, lvl+1, indent_str)?
-                    },
-                    JSON::Bol(b) => { print!("{}{:?}",indent_str.repeat(lvl+1), b); },
-                    JSON::Nul => { print!("{}null", indent_str.repeat(lvl+1)); },
-                }
-                if i < len - 1 {
-                    println!(",");
-                } else {
-                    println!("");
-                }
+        }
+        self.state.0 = match self.nest.last() {
+            Some(Inside::Obj) => Inside::Obj,
+            Some(Inside::List) => Inside::List,
+            _ => Inside::End,
+        };
+        self.val_done()
+    }
+
+    // A value was just completed
+    fn val_done(&mut self) -> Result<(), Fail> {
+        if self.state.0 == Inside::Bgn {
+            self.state.0 = Inside::End; // Primitive root value
+        }
+        self.state.1 = S::EndPrimV;
+        if let Some(n) = self.items.last_mut() {
+            *n += 1;
+            match self.opts.max_container_len {
+                Some(max) if *n > max => Err(Fail::new(
+                    ParseErrorKind::ContainerTooLong,
+                    format!("The container has more than max_container_len ({max}) values"),
+                )),
+                _ => Ok(()),
             }
-            print!("{}]", indent_str.repeat(lvl));
+        } else {
             Ok(())
-        },
-        JSON::Obj(obj) => {
-            if lvl == 0 {
-                println!("{{");
+        }
+    }
+
+    fn end<H: Sink + ?Sized>(&mut self, h: &mut H) -> Result<(), ParseErr> {
+        // A primitive root value has no closing token, it ends with the input
+        if self.state.1 == S::BgnPrimV {
+            if let Err(fail) = self.end_prim(h) {
+                return Err(self.locate(fail));
             }
-            let mut elem_count = obj.len();
-            for (k, v) in obj {
-                elem_count -= 1;
-                print!("{}\"{k}\": ", indent_str.repeat(lvl+1));
-                match v {
-                    JSON::Int(i) => { print!("{i}"); },
-                    JSON::Flt(f) => { print!("{f}"); },
-                    JSON::Str(s) => { print!("\"{s}\""); },
-                    JSON::Lst(_) => {
-                        println!("[");
-                        pretty_print(v, lvl+1, indent_str)?
-                    },
-                    JSON::Obj(_) => { 
-                        println!("{{");
-                        pretty_print(v, lvl+1, indent_str)? 
-                    },
-                    JSON::Bol(b) => { print!("{:?}", b); },
-                    JSON::Nul => { print!("null"); },
-                }
-                if elem_count > 0 {
-                    println!(",");
-                } else {
-                    println!("");
+        }
+        match self.comment {
+            Some(Comment::Block | Comment::BlockStar) => {
+                let err_msg = "The input ends inside a /* */ comment";
+                return Err(self.locate(Fail::new(ParseErrorKind::UnexpectedEof, err_msg)));
+            }
+            Some(Comment::Slash) => {
+                let err_msg = "Expected '/' or '*' after '/' to begin a comment";
+                return Err(self.locate(Fail::new(ParseErrorKind::UnexpectedEof, err_msg)));
+            }
+            _ => {}
+        }
+        if matches!(self.state.1, S::BgnKey | S::BgnStrV) && !self.ident {
+            let err_msg = "The input ends inside a string";
+            return Err(self.locate(Fail::new(ParseErrorKind::UnterminatedString, err_msg)));
+        }
+        if self.state.0 != Inside::End {
+            let err_msg = "Incomplete JSON structure";
+            return Err(self.locate(Fail::new(ParseErrorKind::UnexpectedEof, err_msg)));
+        }
+        Ok(())
+    }
+}
+
+// What the Parser is waiting for after a feed()
+#[derive(Debug, PartialEq)]
+pub enum Progress {
+    NeedMore, // The root value is not complete yet
+    Complete, // The root value is complete, finish() can be called
+}
+
+// Incremental parser building the JSON tree, the document can be fed in
+// fragments cut anywhere. A primitive root like `42` only completes on a
+// following whitespace or on finish().
+pub struct Parser {
+    pda: Pda,
+    tree: TreeBuilder,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Parser::new()
+    }
+}
+
+impl Parser {
+    pub fn new() -> Parser {
+        Parser::with_options(&ParseOptions::default())
+    }
+
+    pub fn with_options(opts: &ParseOptions) -> Parser {
+        Parser {
+            pda: Pda::new(opts),
+            tree: TreeBuilder::new(opts.duplicate_keys),
+        }
+    }
+
+    // Feed the next fragment of the document
+    pub fn feed(&mut self, chunk: &str) -> Result<Progress, ParseErr> {
+        for ch in chunk.chars() {
+            self.pda.step(ch, &mut self.tree)?;
+        }
+        if self.pda.state.0 == Inside::End {
+            Ok(Progress::Complete)
+        } else {
+            Ok(Progress::NeedMore)
+        }
+    }
+
+    // No more input will come, take the root value out
+    pub fn finish(mut self) -> Result<JSON, ParseErr> {
+        self.pda.end(&mut self.tree)?;
+        take_root(&mut self.tree.mem).map_err(|fail| self.pda.locate(fail))
+    }
+}
+
+// A strict RFC 8259 parse with ParseOptions::default(), without limits. Use
+// ParseOptions with max_depth and the other limits for untrusted input.
+pub fn parse(json_str: &str) -> Result<JSON, ParseErr> {
+    ParseOptions::default().parse(json_str)
+}
+
+// See ParseOptions::parse_events()
+pub fn parse_events<H: JsonHandler + ?Sized>(
+    json_str: &str,
+    handler: &mut H,
+) -> Result<(), ParseErr> {
+    ParseOptions::default().parse_events(json_str, handler)
+}
+
+// Position of a character in the document, both start from 1
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Pos {
+    pub line_idx: usize,
+    pub ch_pos: usize,
+}
+
+// Events of the pull parser. Keys and values are located at their first
+// character, the others at their bracket.
+#[derive(Debug)]
+pub enum Event {
+    BeginObject(Pos),
+    Key(String, Pos),
+    BeginList(Pos),
+    Value(JSON, Pos),
+    EndObject(Pos),
+    EndList(Pos),
+}
+
+impl Event {
+    pub fn pos(&self) -> Pos {
+        match self {
+            Event::BeginObject(p)
+            | Event::Key(_, p)
+            | Event::BeginList(p)
+            | Event::Value(_, p)
+            | Event::EndObject(p)
+            | Event::EndList(p) => *p,
+        }
+    }
+
+    fn set_pos(&mut self, pos: Pos) {
+        match self {
+            Event::BeginObject(p)
+            | Event::Key(_, p)
+            | Event::BeginList(p)
+            | Event::Value(_, p)
+            | Event::EndObject(p)
+            | Event::EndList(p) => *p = pos,
+        }
+    }
+}
+
+// Handler which just queues the events for Tokens, positions are set afterward
+#[derive(Default)]
+struct EventQueue {
+    events: VecDeque<Event>,
+}
+
+impl JsonHandler for EventQueue {
+    fn start_object(&mut self) -> Result<(), String> {
+        self.events.push_back(Event::BeginObject(Pos::default()));
+        Ok(())
+    }
+    fn key(&mut self, key: String) -> Result<(), String> {
+        self.events.push_back(Event::Key(key, Pos::default()));
+        Ok(())
+    }
+    fn end_object(&mut self) -> Result<(), String> {
+        self.events.push_back(Event::EndObject(Pos::default()));
+        Ok(())
+    }
+    fn start_list(&mut self) -> Result<(), String> {
+        self.events.push_back(Event::BeginList(Pos::default()));
+        Ok(())
+    }
+    fn end_list(&mut self) -> Result<(), String> {
+        self.events.push_back(Event::EndList(Pos::default()));
+        Ok(())
+    }
+    fn scalar(&mut self, val: JSON) -> Result<(), String> {
+        self.events.push_back(Event::Value(val, Pos::default()));
+        Ok(())
+    }
+}
+
+// Pull parser, the caller drives the PDA by iterating over the events.
+// Stopping the iteration early stops the parsing as well.
+pub struct Tokens<'a> {
+    chars: std::str::Chars<'a>,
+    pda: Pda,
+    queue: EventQueue,
+    err: Option<ParseErr>,
+    done: bool,
+}
+
+impl<'a> Tokens<'a> {
+    pub fn new(json_str: &'a str) -> Tokens<'a> {
+        Tokens::with_options(json_str, &ParseOptions::default())
+    }
+
+    pub fn with_options(json_str: &'a str, opts: &ParseOptions) -> Tokens<'a> {
+        Tokens {
+            chars: json_str.chars(),
+            pda: Pda::new(opts),
+            queue: EventQueue::default(),
+            err: None,
+            done: false,
+        }
+    }
+
+    // Locate the events which were queued by the last step of the PDA
+    fn locate(&mut self, from: usize) {
+        let (here, tok_pos) = (self.pda.pos(), self.pda.tok_pos);
+        for ev in self.queue.events.iter_mut().skip(from) {
+            match ev {
+                Event::Key(..) | Event::Value(..) => ev.set_pos(tok_pos),
+                _ => ev.set_pos(here),
+            }
+        }
+    }
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = Result<Event, ParseErr>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(ev) = self.queue.events.pop_front() {
+                return Some(Ok(ev));
+            }
+            if let Some(err) = self.err.take() {
+                return Some(Err(err));
+            }
+            if self.done {
+                return None;
+            }
+
+            let from = self.queue.events.len();
+            let res = match self.chars.next() {
+                Some(ch) => self.pda.step(ch, &mut self.queue),
+                None => {
+                    self.done = true;
+                    self.pda.end(&mut self.queue)
                 }
+            };
+            self.locate(from);
+            if let Err(err) = res {
+                self.err = Some(err);
+                self.done = true;
             }
-            print!("{}}}", indent_str.repeat(lvl));
-            Ok(())
-        },
-        _ => {
-            Err("Expected a JSON list or object".into())
-        },
+        }
     }
 }
 
+// See ParseOptions::parse_reader(), without limits like parse()
+pub fn parse_reader<R: Read>(reader: R) -> Result<JSON, ParseErr> {
+    ParseOptions::default().parse_reader(reader)
+}
+
 /*
 [06/11/23]: We may need one more state which is called "EndCtnr" or "end container"
 The containers are an Object or a List. This state has to do something different
@@ -1151,11 +1666,9 @@ mod tests {
                                 println!("{each_key}");
                             }
 
-                            if let Some(some_obj) = obj.get("results") {
-                                if let JSON::Lst(vec) = some_obj {
-                                    for each_element in vec {
-                                        println!("{:?}", each_element);
-                                    }
+                            if let Some(JSON::Lst(vec)) = obj.get("results") {
+                                for each_element in vec {
+                                    println!("{:?}", each_element);
                                 }
                             }
                             println!("Found JSON Object as root");
@@ -1172,12 +1685,726 @@ mod tests {
     }
 
     #[test]
-    fn test_pretty_print(){
+    fn test_pretty_print() {
         if let Ok(str_content) = fs::read_to_string("json_test/test.json") {
-            if let Ok(json_obj) = parse(&str_content){
-                let r = pretty_print(&json_obj, 0, "  ");
-                assert_eq!(r, Ok(()));
+            if let Ok(json_obj) = parse(&str_content) {
+                let mut out = Vec::new();
+                to_writer_pretty(&json_obj, &mut out, PrettyOptions::default()).unwrap();
+                assert!(parse(std::str::from_utf8(&out).unwrap()).is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn test_serialize() {
+        let src = r#"{"b": [1, 2.5, -0.0, 1e300, 18446744073709551616], "a": {"q\"\\": "tab\tnl\n\u0001é😀"},
+            "c": [], "d": {}, "e": [true, null]}"#;
+        let json = parse(src).unwrap();
+        let pretty = |json: &JSON, opts: PrettyOptions| {
+            let mut out = Vec::new();
+            to_writer_pretty(json, &mut out, opts).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let sorted = PrettyOptions {
+            sort_keys: true,
+            ..PrettyOptions::default()
+        };
+        assert_eq!(
+            pretty(&json, sorted.clone()),
+            r#"{
+  "a": {
+    "q\"\\": "tab\tnl\n\u0001é😀"
+  },
+  "b": [
+    1,
+    2.5,
+    -0.0,
+    1e300,
+    18446744073709551616
+  ],
+  "c": [],
+  "d": {},
+  "e": [
+    true,
+    null
+  ]
+}"#
+        );
+
+        // Whatever the layout, parse() gives the same tree back
+        let compact = json.to_string();
+        assert!(!compact.contains([' ', '\n']));
+        let layouts = [
+            PrettyOptions {
+                indent: "\t".into(),
+                newline: "\r\n".into(),
+                space_after_colon: false,
+                ascii_only: true,
+                ..sorted.clone()
+            },
+            PrettyOptions::default(),
+        ];
+        for opts in layouts {
+            let again = parse(&pretty(&json, opts)).unwrap();
+            assert_eq!(
+                pretty(&again, sorted.clone()),
+                pretty(&json, sorted.clone())
+            );
+        }
+        let again = parse(&compact).unwrap();
+        assert_eq!(
+            pretty(&again, sorted.clone()),
+            pretty(&json, sorted.clone())
+        );
+        let JSON::Obj(obj) = again else {
+            panic!("Expected an object");
+        };
+        assert!(
+            matches!(&obj["b"], JSON::Lst(l) if matches!(l[..2], [JSON::Int(1), JSON::Flt(_)]))
+        );
+
+        let ascii = PrettyOptions {
+            ascii_only: true,
+            ..PrettyOptions::default()
+        };
+        assert_eq!(
+            pretty(&JSON::Str("é😀\u{7f}".into()), ascii),
+            "\"\\u00e9\\ud83d\\ude00\u{7f}\""
+        );
+        assert_eq!(format!("{}", JSON::Str("\"".into())), r#""\"""#);
+        assert_eq!(format!("{:#}", parse("[1]").unwrap()), "[\n  1\n]");
+
+        // Floats stay floats, Infinity and NaN as asked
+        let flts = JSON::Lst(vec![JSON::Flt(1.0), JSON::Flt(1e300), JSON::Flt(f64::NAN)]);
+        assert_eq!(flts.to_string(), "[1.0,1e300,null]");
+        let policy = |non_finite| {
+            let mut out = Vec::new();
+            let opts = PrettyOptions {
+                non_finite,
+                ..PrettyOptions::default()
+            };
+            let lst = JSON::Lst(vec![JSON::Flt(f64::NEG_INFINITY), JSON::Flt(f64::NAN)]);
+            to_writer_pretty(&lst, &mut out, opts).map(|()| String::from_utf8(out).unwrap())
+        };
+        assert!(policy(NonFinite::Error).is_err());
+        assert_eq!(policy(NonFinite::Null).unwrap(), "[\n  null,\n  null\n]");
+        assert_eq!(
+            policy(NonFinite::Str).unwrap(),
+            "[\n  \"-Infinity\",\n  \"NaN\"\n]"
+        );
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let json = parse(r#"{"kéy": ["\b\f\n\r\t\/\\\"", "\u0041\ud83d\ude00\u4E2D"]}"#).unwrap();
+        if let JSON::Obj(obj) = json {
+            match obj.get("kéy") {
+                Some(JSON::Lst(lst)) => {
+                    match &lst[0] {
+                        JSON::Str(s) => assert_eq!(s, "\u{0008}\u{000C}\n\r\t/\\\""),
+                        _ => panic!("Expected a string"),
+                    }
+                    match &lst[1] {
+                        JSON::Str(s) => assert_eq!(s, "A😀中"),
+                        _ => panic!("Expected a string"),
+                    }
+                }
+                _ => panic!("Expected a list under decoded key"),
             }
+        } else {
+            panic!("Expected an object");
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_lone_surrogate() {
+        assert!(parse(r#"["\ud83d"]"#).is_err());
+        assert!(parse(r#"["\ud83dx"]"#).is_err());
+        assert!(parse(r#"["\ude00"]"#).is_err());
+        assert!(parse(r#"{"\ud83dA": 1}"#).is_err());
+        assert!(parse(r#"["\u12G4"]"#).is_err());
+        assert!(parse(r#"["\x"]"#).is_err());
+    }
+
+    #[test]
+    fn test_scalar_root() {
+        assert!(matches!(parse("42"), Ok(JSON::Int(42))));
+        assert!(matches!(parse(" -1.5\n"), Ok(JSON::Flt(f)) if f == -1.5));
+        assert!(matches!(parse("\ttrue "), Ok(JSON::Bol(true))));
+        assert!(matches!(parse("null"), Ok(JSON::Nul)));
+        assert!(matches!(parse(r#" "he\"llo" "#), Ok(JSON::Str(s)) if s == "he\"llo"));
+        assert!(parse("").is_err());
+        assert!(parse("\"open").is_err());
+        assert!(parse("1 2").is_err());
+        assert!(parse("\"a\" x").is_err());
+    }
+
+    // Reader which only gives a few bytes at a time to split UTF-8 sequences
+    struct Trickle<'a>(&'a [u8], usize);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.1.min(buf.len()).min(self.0.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_parse_reader() {
+        let src = "{\"名前\": [\"😀\", {\"k\": [1, 2.5]}], \"b\": null}";
+        for step in [1, 2, 3, 5] {
+            match parse_reader(Trickle(src.as_bytes(), step)) {
+                Ok(JSON::Obj(obj)) => match obj.get("名前") {
+                    Some(JSON::Lst(lst)) => {
+                        assert!(matches!(&lst[0], JSON::Str(s) if s == "😀"));
+                        assert!(matches!(&lst[1], JSON::Obj(_)));
+                    }
+                    _ => panic!("Expected a list under the key"),
+                },
+                other => panic!("Expected an object, got {:?}", other),
+            }
+        }
+
+        let broken = "[1,\n 2,\n \"ü\" x]";
+        let from_str = parse(broken).unwrap_err();
+        let from_reader = parse_reader(Trickle(broken.as_bytes(), 1)).unwrap_err();
+        assert_eq!(from_str.to_string(), from_reader.to_string());
+
+        assert!(parse_reader(Trickle(b"[\"\xff\"]", 4)).is_err());
+        assert!(parse_reader(Trickle(b"\"\xc3", 4)).is_err());
+    }
+
+    #[test]
+    fn test_parser_feed() {
+        let mut parser = Parser::new();
+        assert_eq!(parser.feed("{\"a\": [1, ").unwrap(), Progress::NeedMore);
+        assert_eq!(parser.feed("2], \"b\\u00").unwrap(), Progress::NeedMore);
+        assert_eq!(parser.feed("e9\": tr").unwrap(), Progress::NeedMore);
+        assert_eq!(parser.feed("ue}").unwrap(), Progress::Complete);
+        assert_eq!(parser.feed(" \n").unwrap(), Progress::Complete);
+        match parser.finish() {
+            Ok(JSON::Obj(obj)) => {
+                assert!(matches!(obj.get("bé"), Some(JSON::Bol(true))));
+                assert!(matches!(obj.get("a"), Some(JSON::Lst(l)) if l.len() == 2));
+            }
+            other => panic!("Expected an object, got {:?}", other),
+        }
+
+        let mut parser = Parser::new();
+        assert_eq!(parser.feed("12").unwrap(), Progress::NeedMore);
+        assert_eq!(parser.feed("3").unwrap(), Progress::NeedMore);
+        assert!(matches!(parser.finish(), Ok(JSON::Int(123))));
+
+        let mut parser = Parser::new();
+        parser.feed("[1, 2").unwrap();
+        assert!(parser.finish().is_err());
+
+        let mut parser = Parser::new();
+        parser.feed("[]").unwrap();
+        assert!(parser.feed("[]").is_err());
+    }
+
+    // Collect only the "id" values of a document, keeping track of the depth
+    #[derive(Default)]
+    struct IdCollector {
+        depth: usize,
+        max_depth: usize,
+        want: bool,
+        ids: Vec<i64>,
+    }
+
+    impl JsonHandler for IdCollector {
+        fn start_object(&mut self) -> Result<(), String> {
+            self.depth += 1;
+            self.max_depth = self.max_depth.max(self.depth);
+            Ok(())
+        }
+        fn end_object(&mut self) -> Result<(), String> {
+            self.depth -= 1;
+            Ok(())
+        }
+        fn key(&mut self, key: String) -> Result<(), String> {
+            self.want = key == "id";
+            Ok(())
+        }
+        fn scalar(&mut self, val: JSON) -> Result<(), String> {
+            if let (true, JSON::Int(i)) = (self.want, val) {
+                self.ids.push(i);
+            }
+            self.want = false;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_parse_events() {
+        let src = r#"{"results": [{"id": 1, "tags": ["a"]}, {"name": "x", "id": 2}], "id": 3}"#;
+        let mut collector = IdCollector::default();
+        parse_events(src, &mut collector).unwrap();
+        assert_eq!(collector.ids, vec![1, 2, 3]);
+        assert_eq!(collector.max_depth, 2);
+
+        let mut collector = IdCollector::default();
+        assert!(parse_events("[{\"id\": 1}", &mut collector).is_err());
+
+        // A handler error stops the parsing
+        struct NoLists;
+        impl JsonHandler for NoLists {
+            fn start_list(&mut self) -> Result<(), String> {
+                Err("Lists are not welcome".into())
+            }
+        }
+        let err = parse_events("{\"a\": [1]}", &mut NoLists).unwrap_err();
+        assert_eq!(err.to_string(), "Line[1], Char[7]: Lists are not welcome");
+    }
+
+    #[test]
+    fn test_tokens() {
+        let src = "{\"skip\": {\"a\": [1, {\"b\": 2}]},\n \"keep\": [\"x\", 2.5]}";
+        let mut names = Vec::new();
+        let mut tokens = Tokens::new(src);
+        while let Some(ev) = tokens.next() {
+            match ev.unwrap() {
+                Event::Key(k, pos) if k == "skip" => {
+                    assert_eq!(
+                        pos,
+                        Pos {
+                            line_idx: 1,
+                            ch_pos: 2
+                        }
+                    );
+                    // Skip the whole value of "skip" without looking at it
+                    let mut depth = 0;
+                    for ev in tokens.by_ref() {
+                        match ev.unwrap() {
+                            Event::BeginObject(_) | Event::BeginList(_) => depth += 1,
+                            Event::EndObject(_) | Event::EndList(_) => depth -= 1,
+                            _ => {}
+                        }
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                }
+                Event::Key(k, _) => names.push(k),
+                Event::Value(JSON::Flt(f), pos) => {
+                    assert_eq!(f, 2.5);
+                    assert_eq!(
+                        pos,
+                        Pos {
+                            line_idx: 2,
+                            ch_pos: 16
+                        }
+                    );
+                }
+                Event::EndList(pos) => assert_eq!(
+                    pos,
+                    Pos {
+                        line_idx: 2,
+                        ch_pos: 19
+                    }
+                ),
+                _ => {}
+            }
+        }
+        assert_eq!(names, vec!["keep".to_string()]);
+
+        // Events before the error are still given, then the error, then nothing
+        let mut tokens = Tokens::new("[1, }");
+        assert!(matches!(tokens.next(), Some(Ok(Event::BeginList(_)))));
+        assert!(matches!(
+            tokens.next(),
+            Some(Ok(Event::Value(JSON::Int(1), _)))
+        ));
+        assert!(matches!(tokens.next(), Some(Err(_))));
+        assert!(tokens.next().is_none());
+    }
+
+    #[cfg(feature = "preserve_order")]
+    #[test]
+    fn test_preserve_order() {
+        let json = parse(r#"{"z": 1, "a": {"y": 2, "b": 3}, "m": 4, "a": 5}"#).unwrap();
+        let JSON::Obj(obj) = &json else {
+            panic!("Expected an object");
+        };
+        assert_eq!(obj.keys().collect::<Vec<_>>(), ["z", "a", "m"]);
+        match obj.get("a") {
+            Some(JSON::Obj(inner)) => assert_eq!(inner.keys().collect::<Vec<_>>(), ["y", "b"]),
+            _ => panic!("Expected the first \"a\" to be kept"),
+        }
+    }
+
+    #[test]
+    fn test_lossless_numbers() {
+        let json = parse("[12, 0.5, 18446744073709551615, 0.1000000000000000055511, 1e400, -0.0]");
+        let Ok(JSON::Lst(lst)) = json else {
+            panic!("Expected a list");
+        };
+        assert!(matches!(lst[0], JSON::Int(12)));
+        assert!(matches!(lst[1], JSON::Flt(f) if f == 0.5));
+        assert!(matches!(&lst[2], JSON::Num(n) if n.as_u64() == Some(u64::MAX)));
+        assert!(matches!(&lst[3], JSON::Num(n) if n.as_str() == "0.1000000000000000055511"));
+        assert!(matches!(&lst[4], JSON::Num(n) if n.as_f64().is_none()));
+        assert!(matches!(lst[5], JSON::Flt(f) if f == 0.0 && f.is_sign_negative()));
+
+        // The same value gives the same variant, however it is written
+        let cases = [
+            (
+                "[1.0, 1.50, 1E2, 1.0e0, 0.10, -0.0, 1e-7]",
+                "[1.0,1.5,100.0,1.0,0.1,-0.0,1e-7]",
+            ),
+            (
+                "[100, -0, 123456789012345678901]",
+                "[100,0,123456789012345678901]",
+            ),
+            (
+                "[0.1000000000000000055511, 1e400]",
+                "[0.1000000000000000055511,1e400]",
+            ),
+        ];
+        for (src, out) in cases {
+            assert_eq!(parse(src).unwrap().to_string(), out);
+        }
+        let JSON::Lst(lst) = parse("[1.50, 1.5, 1E2, 100.0, -0]").unwrap() else {
+            panic!("Expected a list");
+        };
+        assert!(
+            matches!(lst[..], [JSON::Flt(a), JSON::Flt(b), JSON::Flt(c), JSON::Flt(d), JSON::Int(0)]
+            if a == 1.5 && b == a && c == 100.0 && d == c)
+        );
+    }
+
+    #[test]
+    fn test_strict_numbers() {
+        // ch_pos points at the faulty character of the number
+        let bad = [
+            ("[1, nan]", 5, "Infinity and NaN are not valid JSON numbers"),
+            ("[NaN]", 2, "Infinity and NaN are not valid JSON numbers"),
+            (
+                "[-Infinity]",
+                2,
+                "Infinity and NaN are not valid JSON numbers",
+            ),
+            ("[+1]", 2, "A number can't start with '+'"),
+            ("[1.]", 3, "Expected a digit after '.'"),
+            ("[.5]", 2, "Expected a digit before '.'"),
+            ("[01]", 2, "Leading zeros are not allowed"),
+            ("{\"a\": 12e}", 9, "Expected a digit in the exponent"),
+            ("-", 1, "Expected a digit after '-'"),
+            ("[nul]", 2, "Invalid literal, expected true, false or null"),
+        ];
+        for (src, ch_pos, msg) in bad {
+            let err = parse(src).unwrap_err();
+            assert_eq!((err.ch_pos, err.msg.as_str()), (ch_pos, msg), "{src}");
+        }
+
+        let lenient = ParseOptions {
+            lenient_numbers: true,
+            ..ParseOptions::default()
+        };
+        let Ok(JSON::Lst(lst)) = lenient.parse("[+1, 1., .5, NaN, -Infinity]") else {
+            panic!("Expected a list");
+        };
+        assert!(matches!(lst[0], JSON::Int(1)));
+        assert!(matches!(lst[1], JSON::Flt(f) if f == 1.0));
+        assert!(matches!(lst[2], JSON::Flt(f) if f == 0.5));
+        assert!(matches!(lst[3], JSON::Flt(f) if f.is_nan()));
+        assert!(matches!(lst[4], JSON::Flt(f) if f.is_infinite()));
+        assert!(lenient.parse("[007]").is_err());
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let cases = [
+            ("[1 2]", ParseErrorKind::UnexpectedChar, 1, 4, 3),
+            ("{\"a\" 1}", ParseErrorKind::ExpectedColon, 1, 6, 5),
+            ("{\"a\": 1, 2}", ParseErrorKind::ExpectedKey, 1, 10, 9),
+            ("[\"abc", ParseErrorKind::UnterminatedString, 1, 5, 5),
+            ("[\"\\x\"]", ParseErrorKind::InvalidEscape, 1, 4, 3),
+            ("[1,\n 1.e5]", ParseErrorKind::InvalidNumber, 2, 3, 6),
+            ("[\"é\", nope]", ParseErrorKind::InvalidLiteral, 1, 7, 7),
+            ("{} {}", ParseErrorKind::TrailingData, 1, 4, 3),
+            ("{\"a\": [1", ParseErrorKind::UnexpectedEof, 1, 8, 8),
+        ];
+        for (src, kind, line, column, byte_offset) in cases {
+            let err = parse(src).unwrap_err();
+            assert_eq!(
+                (err.kind(), err.line(), err.column(), err.byte_offset()),
+                (kind, line, column, byte_offset),
+                "{src}"
+            );
+        }
+
+        let err = parse_reader(&b"[\"\xff\"]"[..]).unwrap_err();
+        assert_eq!(err.kind(), ParseErrorKind::InvalidUtf8);
+
+        struct Stop;
+        impl JsonHandler for Stop {
+            fn key(&mut self, _key: String) -> Result<(), String> {
+                Err("No keys please".into())
+            }
+        }
+        let err = parse_events("{\"a\": 1}", &mut Stop).unwrap_err();
+        assert_eq!(
+            (err.kind(), err.msg()),
+            (ParseErrorKind::Handler, "No keys please")
+        );
+
+        let err: Box<dyn std::error::Error> = Box::new(parse("[").unwrap_err());
+        assert_eq!(
+            err.to_string(),
+            "Line[1], Char[1]: Incomplete JSON structure"
+        );
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        // A max_depth stops hostile nesting long before the stack is in danger
+        let limited = ParseOptions {
+            max_depth: Some(128),
+            ..ParseOptions::default()
+        };
+        let deep = "[".repeat(100_000);
+        let err = limited.parse(&deep).unwrap_err();
+        assert_eq!(
+            (err.kind(), err.column()),
+            (ParseErrorKind::DepthExceeded, 129)
+        );
+        assert!(limited
+            .parse(&format!("{}{}", "[".repeat(128), "]".repeat(128)))
+            .is_ok());
+        // Without limits by default, the parser itself doesn't recurse
+        assert_eq!(
+            parse(&deep).unwrap_err().kind(),
+            ParseErrorKind::UnexpectedEof
+        );
+
+        let opts = ParseOptions {
+            max_depth: Some(2),
+            max_string_len: Some(3),
+            max_container_len: Some(2),
+            max_total_bytes: Some(20),
+            ..ParseOptions::default()
+        };
+        let cases = [
+            ("[[1]]", None),
+            ("[[[1]]]", Some((ParseErrorKind::DepthExceeded, 3))),
+            ("[\"abc\", \"é\"]", None),
+            ("[\"abcd\"]", Some((ParseErrorKind::StringTooLong, 6))),
+            ("{\"abéc\": 1}", Some((ParseErrorKind::StringTooLong, 5))),
+            ("{\"a\": 1, \"b\": 2}", None),
+            ("[1, 2, 3]", Some((ParseErrorKind::ContainerTooLong, 9))),
+            (
+                "[[1, 2], {\"a\": 33333}]",
+                Some((ParseErrorKind::InputTooLarge, 21)),
+            ),
+        ];
+        for (src, expected) in cases {
+            let found = opts.parse(src).err().map(|e| (e.kind(), e.column()));
+            assert_eq!(found, expected, "{src}");
+        }
+
+        // parse() and parse_reader() take deep documents as they are
+        let deep = format!("{}{}", "[".repeat(1000), "]".repeat(1000));
+        assert_eq!(
+            limited.parse(&deep).unwrap_err().kind(),
+            ParseErrorKind::DepthExceeded
+        );
+        assert!(limited.parse_reader(deep.as_bytes()).is_err());
+        assert!(parse_reader(deep.as_bytes()).is_ok());
+        let json = parse(&deep).unwrap();
+        assert_eq!(json.to_string(), deep);
+        let mut out = Vec::new();
+        to_writer_pretty(&json, &mut out, PrettyOptions::default()).unwrap();
+        let pretty = std::str::from_utf8(&out).unwrap();
+        assert_eq!(parse(pretty).unwrap().to_string(), deep);
+        // Display doesn't fail on a tree made by hand either
+        let mut lst = JSON::Lst(Vec::new());
+        for _ in 0..600 {
+            lst = JSON::Lst(vec![lst]);
+        }
+        assert_eq!(lst.to_string().len(), 2 * 601);
+        assert!(format!("{lst:#}").ends_with("]\n]"));
+    }
+
+    #[test]
+    fn test_duplicate_keys() {
+        let src = "{\"a\": 1, \"b\": {\"a\": 2}, \"a\": 3, \"a\": [4]}";
+        let with = |dup| ParseOptions {
+            duplicate_keys: dup,
+            ..ParseOptions::default()
+        };
+
+        let err = with(DuplicateKeys::Error).parse(src).unwrap_err();
+        assert_eq!(
+            (err.kind(), err.column(), err.msg()),
+            (
+                ParseErrorKind::DuplicateKey,
+                25,
+                "Duplicate key \"a\" in the object"
+            )
+        );
+        let err = with(DuplicateKeys::Error).parse_borrowed(src).unwrap_err();
+        assert_eq!(
+            (err.kind(), err.column()),
+            (ParseErrorKind::DuplicateKey, 25)
+        );
+        assert!(with(DuplicateKeys::Error)
+            .parse("[{\"a\": 1}, {\"a\": 2}]")
+            .is_ok());
+
+        let first = |json: &JSON| match json {
+            JSON::Obj(obj) => match &obj["a"] {
+                JSON::Int(i) => *i,
+                _ => -1,
+            },
+            _ => panic!("Expected an object"),
+        };
+        assert_eq!(first(&parse(src).unwrap()), 1);
+        let json = with(DuplicateKeys::FirstWins).parse(src).unwrap();
+        assert_eq!(first(&json), 1);
+        let json = with(DuplicateKeys::LastWins).parse(src).unwrap();
+        assert!(matches!(&json, JSON::Obj(o) if matches!(&o["a"], JSON::Lst(l) if l.len() == 1)));
+
+        let Ok(JSON::Obj(obj)) = with(DuplicateKeys::Collect).parse(src) else {
+            panic!("Expected an object");
+        };
+        let JSON::Lst(vals) = &obj["a"] else {
+            panic!("Expected the values of \"a\" in a list");
+        };
+        assert!(matches!(
+            vals[..],
+            [JSON::Int(1), JSON::Int(3), JSON::Lst(_)]
+        ));
+        assert_eq!(obj["b"].to_string(), "[{\"a\":[2]}]");
+        // A repeated key and a list value don't look the same
+        let collect = |src| with(DuplicateKeys::Collect).parse(src).unwrap().to_string();
+        assert_eq!(collect("{\"a\": [1], \"a\": [2]}"), "{\"a\":[[1],[2]]}");
+        assert_eq!(collect("{\"a\": [1, 2]}"), "{\"a\":[[1,2]]}");
+        assert_eq!(collect("[{}]"), "[{}]");
+    }
+
+    #[test]
+    fn test_relaxed_syntax() {
+        let jsonc = "{\n  // The name\n  \"a\": [1, 2 /* two */], /* end */\n}";
+        let trailing = "{\"a\": [1, 2,],}";
+        assert!(parse(jsonc).is_err());
+        assert!(parse(trailing).is_err());
+
+        // Each one needs its own flag
+        let comments = ParseOptions {
+            comments: true,
+            ..ParseOptions::default()
+        };
+        let trailing_commas = ParseOptions {
+            trailing_commas: true,
+            ..ParseOptions::default()
+        };
+        assert!(comments.parse("[1, 2,]").is_err());
+        assert!(trailing_commas.parse("[1 /* */]").is_err());
+        let JSON::Obj(obj) = trailing_commas.parse(trailing).unwrap() else {
+            panic!("Expected an object");
+        };
+        assert!(matches!(&obj["a"], JSON::Lst(l) if l.len() == 2));
+        let both = ParseOptions {
+            comments: true,
+            trailing_commas: true,
+            ..ParseOptions::default()
+        };
+        let JSON::Obj(obj) = both.parse(jsonc).unwrap() else {
+            panic!("Expected an object");
+        };
+        assert!(matches!(&obj["a"], JSON::Lst(l) if matches!(l[..], [JSON::Int(1), JSON::Int(2)])));
+        assert!(matches!(comments.parse("1// one"), Ok(JSON::Int(1))));
+
+        let err = comments.parse("[1, / 2]").unwrap_err();
+        assert_eq!(
+            (err.kind(), err.column()),
+            (ParseErrorKind::UnexpectedChar, 6)
+        );
+        let err = comments.parse("[1 /* 2 ]").unwrap_err();
+        assert_eq!(err.kind(), ParseErrorKind::UnexpectedEof);
+
+        let json5 = ParseOptions {
+            json5: true,
+            ..ParseOptions::default()
+        };
+        let src = r#"// JSON5 sample
+{
+  unquoted: 'and you can quote me on that',
+  singleQuotes: 'I can use "double quotes" here',
+  lineBreaks: "Look, Mom! \
+No \\n's!",
+  hexadecimal: 0xdecaf,
+  leadingDecimalPoint: .8675309, andTrailing: 8675309.,
+  positiveSign: +1,
+  trailingComma: 'in objects', andIn: ['arrays',],
+  "backwardsCompatible": "with JSON",
+  $_ünicode1: '\x41\'\v',
+  inf: [Infinity, -Infinity, NaN],
+}"#;
+        let JSON::Obj(obj) = json5.parse(src).unwrap() else {
+            panic!("Expected an object");
+        };
+        let text = |key: &str| match &obj[key] {
+            JSON::Str(s) => s.clone(),
+            _ => panic!("Expected a string at {key}"),
+        };
+        assert_eq!(text("unquoted"), "and you can quote me on that");
+        assert_eq!(text("singleQuotes"), "I can use \"double quotes\" here");
+        assert_eq!(text("lineBreaks"), "Look, Mom! No \\n's!");
+        assert_eq!(text("$_ünicode1"), "A'\u{B}");
+        assert!(matches!(obj["hexadecimal"], JSON::Int(0xdecaf)));
+        assert!(matches!(obj["leadingDecimalPoint"], JSON::Flt(f) if f == 0.8675309));
+        assert!(matches!(obj["andTrailing"], JSON::Flt(f) if f == 8675309.0));
+        assert!(matches!(obj["positiveSign"], JSON::Int(1)));
+        assert!(matches!(&obj["inf"], JSON::Lst(l) if matches!(l[..],
+            [JSON::Flt(a), JSON::Flt(b), JSON::Flt(c)] if a == f64::INFINITY && b == -a && c.is_nan())));
+
+        // Still strict by default, and the JSON5 errors point at the token
+        assert!(parse("{a: 1}").is_err());
+        assert!(parse("['a']").is_err());
+        let err = json5.parse("[0x]").unwrap_err();
+        assert_eq!(
+            (err.kind(), err.column()),
+            (ParseErrorKind::InvalidNumber, 3)
+        );
+        let err = json5.parse("[+.]").unwrap_err();
+        assert_eq!(err.kind(), ParseErrorKind::InvalidNumber);
+        assert_eq!(
+            json5.parse("['\\1']").unwrap_err().kind(),
+            ParseErrorKind::InvalidEscape
+        );
+
+        // Unquoted keys are borrowed like the quoted ones
+        let JsonRef::Obj(obj) = json5.parse_borrowed("{key: 'v', k\u{2003}: 1}").unwrap() else {
+            panic!("Expected an object");
+        };
+        assert!(obj.iter().all(|(k, _)| matches!(k, Cow::Borrowed(_))));
+        assert!(obj.contains_key("key") && obj.contains_key("k"));
+
+        // max_string_len counts unquoted keys like quoted ones
+        let short = ParseOptions {
+            max_string_len: Some(3),
+            ..json5
+        };
+        for src in ["{abc: 1}", "{'abc': 1}"] {
+            assert!(short.parse(src).is_ok(), "{src}");
+        }
+        for (src, column) in [
+            ("{abcdefgh: 1}", 5),
+            ("{'abcdefgh': 1}", 6),
+            ("{abé: 1}", 4),
+        ] {
+            let err = short.parse(src).unwrap_err();
+            assert_eq!(
+                (err.kind(), err.column()),
+                (ParseErrorKind::StringTooLong, column),
+                "{src}"
+            );
+            assert!(short.parse_borrowed(src).is_err(), "{src}");
+        }
+    }
+}
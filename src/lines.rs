@@ -0,0 +1,250 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use super::{ser, ParseErr, ParseErrorKind, ParseOptions, Parser, Pos, JSON};
+
+// Read a JSON Lines (NDJSON) file, one document per line. See Lines.
+pub fn parse_lines<R: Read>(reader: R) -> Lines<R> {
+    ParseOptions::default().parse_lines(reader)
+}
+
+impl ParseOptions {
+    pub fn parse_lines<R: Read>(&self, reader: R) -> Lines<R> {
+        Lines {
+            reader: BufReader::new(reader),
+            opts: self.clone(),
+            line_idx: 1,
+            byte_pos: 0,
+            buf: Vec::new(),
+            done: false,
+        }
+    }
+}
+
+// Iterator over the documents of a JSON Lines file. A broken line gives its
+// error and the next one goes on, only an I/O error stops the iteration.
+// Blank lines are skipped. The errors are located in the whole file, not in
+// their line alone.
+pub struct Lines<R> {
+    reader: BufReader<R>,
+    opts: ParseOptions,
+    line_idx: usize, // Line of the file the next read starts at
+    byte_pos: usize, // And its byte offset
+    buf: Vec<u8>,
+    done: bool,
+}
+
+impl<R: Read> Iterator for Lines<R> {
+    type Item = Result<JSON, ParseErr>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            self.buf.clear();
+            let (n, cut) = match self.read_line() {
+                Ok((0, _)) => return None,
+                Ok(read) => read,
+                Err(e) => {
+                    self.done = true;
+                    let at = Pos {
+                        line_idx: self.line_idx,
+                        ch_pos: 1,
+                    };
+                    let err_msg = format!("I/O error: {e}");
+                    return Some(Err(ParseErr::e(
+                        ParseErrorKind::Io,
+                        at,
+                        self.byte_pos,
+                        err_msg,
+                    )));
+                }
+            };
+            let (line_idx, byte_pos) = (self.line_idx, self.byte_pos);
+            self.line_idx += 1;
+            self.byte_pos += n;
+
+            let mut line = &self.buf[..];
+            line = line.strip_suffix(b"\n").unwrap_or(line);
+            line = line.strip_suffix(b"\r").unwrap_or(line);
+            if !cut && line.iter().all(|b| matches!(b, b' ' | b'\t')) {
+                continue;
+            }
+            let res = parse_line(&self.opts, line, cut).map_err(|mut err| {
+                err.line_idx += line_idx - 1;
+                err.byte_pos += byte_pos;
+                err
+            });
+            return Some(res);
+        }
+        None
+    }
+}
+
+impl<R: Read> Lines<R> {
+    // Read the next line into buf, but no more than max_total_bytes of it
+    // (and its "\r\n"): the rest is skipped and cut tells so. Gives the
+    // bytes of the whole line.
+    fn read_line(&mut self) -> io::Result<(usize, bool)> {
+        let Some(max) = self.opts.max_total_bytes else {
+            return Ok((self.reader.read_until(b'\n', &mut self.buf)?, false));
+        };
+        let limit = max.saturating_add(2);
+        let n = (&mut self.reader)
+            .take(limit as u64)
+            .read_until(b'\n', &mut self.buf)?;
+        if n < limit || self.buf.ends_with(b"\n") {
+            return Ok((n, false));
+        }
+        let mut skipped = 0;
+        loop {
+            let available = self.reader.fill_buf()?;
+            if available.is_empty() {
+                return Ok((n + skipped, true));
+            }
+            match available.iter().position(|&b| b == b'\n') {
+                Some(i) => {
+                    self.reader.consume(i + 1);
+                    return Ok((n + skipped + i + 1, true));
+                }
+                None => {
+                    let len = available.len();
+                    self.reader.consume(len);
+                    skipped += len;
+                }
+            }
+        }
+    }
+}
+
+// A cut line is larger than max_total_bytes, the parser finds it out at the
+// byte over the limit unless another error comes first
+fn parse_line(opts: &ParseOptions, line: &[u8], cut: bool) -> Result<JSON, ParseErr> {
+    let mut parser = Parser::with_options(opts);
+    match std::str::from_utf8(line) {
+        Ok(s) => {
+            parser.feed(s)?;
+        }
+        Err(e) => {
+            // Feed what is valid, so the error is located right after it
+            if let Ok(s) = std::str::from_utf8(&line[..e.valid_up_to()]) {
+                parser.feed(s)?;
+            }
+            // Unless the cut split the last character
+            if !cut || e.error_len().is_some() {
+                return Err(parser
+                    .pda
+                    .next_err(ParseErrorKind::InvalidUtf8, "Invalid UTF-8 sequence"));
+            }
+        }
+    }
+    if let (true, Some(max)) = (cut, opts.max_total_bytes) {
+        let err_msg = format!("The input is larger than max_total_bytes ({max})");
+        return Err(parser.pda.next_err(ParseErrorKind::InputTooLarge, &err_msg));
+    }
+    parser.finish()
+}
+
+// Write documents as JSON Lines, each one compact on its own line
+pub struct LinesWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> LinesWriter<W> {
+    pub fn new(writer: W) -> LinesWriter<W> {
+        LinesWriter { writer }
+    }
+
+    pub fn write(&mut self, json: &JSON) -> io::Result<()> {
+        ser::to_io(json, &mut self.writer, &ser::COMPACT)?;
+        self.writer.write_all(b"\n")
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    // Give the writer back, it is not flushed
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_lines() {
+        let src = "{\"id\": 1, \"tags\": [\"a\\n\", \"b\"]}\r\n\n[1.0, null]\n{\"id\": }\n  \n\"é\" \"x\"\n3";
+        let docs: Vec<_> = parse_lines(src.as_bytes()).collect();
+        assert_eq!(docs.len(), 5);
+        assert!(matches!(&docs[0], Ok(JSON::Obj(o)) if o.len() == 2));
+        assert!(matches!(&docs[1], Ok(JSON::Lst(l)) if matches!(l[..], [JSON::Flt(_), JSON::Nul])));
+        assert!(matches!(docs[4], Ok(JSON::Int(3))));
+
+        // The errors are located in the file
+        let err = docs[2].as_ref().unwrap_err();
+        assert_eq!(
+            (err.kind(), err.line(), err.column()),
+            (ParseErrorKind::UnexpectedChar, 4, 8)
+        );
+        assert_eq!(&src[err.byte_offset()..err.byte_offset() + 1], "}");
+        let err = docs[3].as_ref().unwrap_err();
+        assert_eq!(
+            (err.kind(), err.line(), err.column()),
+            (ParseErrorKind::TrailingData, 6, 5)
+        );
+        assert_eq!(&src[err.byte_offset()..err.byte_offset() + 1], "\"");
+
+        let bad = parse_lines(&b"1\n\"\xff\"\n2"[..]).collect::<Vec<_>>();
+        let err = bad[1].as_ref().unwrap_err();
+        assert_eq!(
+            (err.kind(), err.line(), err.column()),
+            (ParseErrorKind::InvalidUtf8, 2, 2)
+        );
+        assert!(matches!(bad[2], Ok(JSON::Int(2))));
+
+        // What the writer gives can be read back
+        let mut writer = LinesWriter::new(Vec::new());
+        for doc in docs.iter().flatten() {
+            writer.write(doc).unwrap();
+        }
+        let out = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(out.lines().count(), 3);
+        assert!(out.ends_with("[1.0,null]\n3\n"));
+        let again: Vec<_> = parse_lines(out.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(matches!(&again[0], JSON::Obj(o) if matches!(&o["tags"],
+            JSON::Lst(l) if matches!(&l[0], JSON::Str(s) if s == "a\n"))));
+
+        // A long line is cut at max_total_bytes, not read whole into memory
+        let opts = ParseOptions {
+            max_total_bytes: Some(8),
+            ..ParseOptions::default()
+        };
+        let long = io::repeat(b' ').take(1 << 24);
+        let src =
+            b"[1, 2]\r\n\"123456\"\r\n[\"\xc3\xa9\xc3\xa9\xc3\xa9\"]\n\"123456\xf0\x9f\x98\x80\"\n";
+        let src = src.chain(long);
+        let cut: Vec<_> = opts
+            .parse_lines(src.chain(&b"1\n[1,\xff]\n2"[..]))
+            .collect();
+        assert_eq!(cut.len(), 7);
+        assert!(matches!(&cut[0], Ok(JSON::Lst(l)) if l.len() == 2));
+        assert!(matches!(&cut[1], Ok(JSON::Str(s)) if s == "123456"));
+        // The cut can split a character, it is still too large
+        for (doc, line, column) in [(&cut[2], 3, 6), (&cut[3], 4, 8), (&cut[4], 5, 9)] {
+            let err = doc.as_ref().unwrap_err();
+            assert_eq!(
+                (err.kind(), err.line(), err.column()),
+                (ParseErrorKind::InputTooLarge, line, column)
+            );
+        }
+        assert_eq!(cut[4].as_ref().unwrap_err().byte_offset(), 50);
+        let err = cut[5].as_ref().unwrap_err();
+        assert_eq!(
+            (err.kind(), err.line(), err.column()),
+            (ParseErrorKind::InvalidUtf8, 6, 4)
+        );
+        assert!(matches!(cut[6], Ok(JSON::Int(2))));
+    }
+}
@@ -0,0 +1,232 @@
+use core::fmt;
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::ops::Index;
+
+// HashMap look-alike which remembers the order the keys were inserted in.
+// With the `preserve_order` feature, JSON objects are stored in it so they
+// come out in the same order as the source.
+#[derive(Clone)]
+pub struct ObjMap<K, V> {
+    entries: Vec<(K, V)>,
+    index: HashMap<u64, Vec<usize>>, // Hash of the key -> positions in entries
+    hasher: RandomState,
+}
+
+impl<K, V> Default for ObjMap<K, V> {
+    fn default() -> Self {
+        ObjMap {
+            entries: Vec::new(),
+            index: HashMap::new(),
+            hasher: RandomState::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> ObjMap<K, V> {
+    pub fn new() -> ObjMap<K, V> {
+        ObjMap::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn find<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.index
+            .get(&self.hasher.hash_one(key))?
+            .iter()
+            .copied()
+            .find(|&i| self.entries[i].0.borrow() == key)
+    }
+
+    // A new key goes to the end, an existing key keeps its place and only
+    // gets the new value, the old one is given back
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        if let Some(i) = self.find(&key) {
+            return Some(std::mem::replace(&mut self.entries[i].1, val));
+        }
+        let hash = self.hasher.hash_one(&key);
+        self.index.entry(hash).or_default().push(self.entries.len());
+        self.entries.push((key, val));
+        None
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).map(|i| &self.entries[i].1)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).map(|i| &mut self.entries[i].1)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).is_some()
+    }
+
+    // Remove the key, the other keys keep their order
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let i = self.find(key)?;
+        let (_, val) = self.entries.remove(i);
+        // Every position after the removed entry moved one step to the front
+        self.index.retain(|_, pos| {
+            pos.retain(|&p| p != i);
+            for p in pos.iter_mut() {
+                if *p > i {
+                    *p -= 1;
+                }
+            }
+            !pos.is_empty()
+        });
+        Some(val)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.entries.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.iter().map(|(_, v)| v)
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.entries.iter_mut().map(|(_, v)| v)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries.iter().map(|(k, v)| (k, v))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&K, &mut V)> {
+        self.entries.iter_mut().map(|(k, v)| (&*k, v))
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for ObjMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.entries.iter().map(|(k, v)| (k, v)))
+            .finish()
+    }
+}
+
+// Same as HashMap, two maps are equal when they have the same entries in any order
+impl<K: Hash + Eq, V: PartialEq> PartialEq for ObjMap<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl<K, Q, V> Index<&Q> for ObjMap<K, V>
+where
+    K: Hash + Eq + Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+impl<K: Hash + Eq, V> FromIterator<(K, V)> for ObjMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = ObjMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: Hash + Eq, V> Extend<(K, V)> for ObjMap<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<K, V> IntoIterator for ObjMap<K, V> {
+    type Item = (K, V);
+    type IntoIter = std::vec::IntoIter<(K, V)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a ObjMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = std::iter::Map<std::slice::Iter<'a, (K, V)>, fn(&'a (K, V)) -> (&'a K, &'a V)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter().map(|(k, v)| (k, v))
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a mut ObjMap<K, V> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter =
+        std::iter::Map<std::slice::IterMut<'a, (K, V)>, fn(&'a mut (K, V)) -> (&'a K, &'a mut V)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter_mut().map(|(k, v)| (&*k, v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_obj_map_order() {
+        let mut map: ObjMap<String, i32> = ["z", "a", "m"]
+            .iter()
+            .enumerate()
+            .map(|(i, k)| (k.to_string(), i as i32))
+            .collect();
+        assert_eq!(map.insert("a".into(), 10), Some(1));
+        map.insert("b".into(), 3);
+        assert_eq!(map.keys().collect::<Vec<_>>(), ["z", "a", "m", "b"]);
+        assert_eq!(map["a"], 10);
+
+        assert_eq!(map.remove("z"), Some(0));
+        assert_eq!(map.remove("z"), None);
+        assert_eq!(map.get("m"), Some(&2));
+        *map.get_mut("b").unwrap() += 1;
+        let pairs: Vec<(&String, &i32)> = map.iter().collect();
+        assert_eq!(pairs.len(), 3);
+        assert_eq!((pairs[2].0.as_str(), *pairs[2].1), ("b", 4));
+
+        let mut other = ObjMap::new();
+        other.insert("b".to_string(), 4);
+        other.insert("m".to_string(), 2);
+        other.insert("a".to_string(), 10);
+        assert_eq!(map, other);
+    }
+}
@@ -0,0 +1,246 @@
+use std::str::Chars;
+
+use super::{
+    take_root, Escape, Fail, Inside, ParseErr, ParseErrorKind, ParseOptions, Pda, TreeBuilder,
+    JSON, S,
+};
+
+// The record separator of RFC 7464, it comes before each text of a json-seq
+const RS: char = '\u{1E}';
+
+// See ParseOptions::parse_multi()
+pub fn parse_multi(json_str: &str) -> Documents<'_> {
+    ParseOptions::default().parse_multi(json_str)
+}
+
+// See ParseOptions::parse_json_seq()
+pub fn parse_json_seq(json_str: &str) -> Documents<'_> {
+    ParseOptions::default().parse_json_seq(json_str)
+}
+
+impl ParseOptions {
+    // Concatenated JSON: documents back to back like `{..}{..}[..]`, with or
+    // without whitespace between them. Numbers, true, false and null need a
+    // whitespace after them. The iteration stops at the first error.
+    pub fn parse_multi<'a>(&self, json_str: &'a str) -> Documents<'a> {
+        Documents::new(self, json_str, false)
+    }
+
+    // JSON text sequence (RFC 7464, application/json-seq): each document
+    // comes after an RS character. A text cut by the next RS is an error,
+    // like a number without a whitespace after it. A broken text gives its
+    // error and the parsing goes on from the next RS.
+    pub fn parse_json_seq<'a>(&self, json_str: &'a str) -> Documents<'a> {
+        Documents::new(self, json_str, true)
+    }
+}
+
+// Iterator over the documents of a multi-document input. The errors are
+// located in the whole input.
+pub struct Documents<'a> {
+    chars: Chars<'a>,
+    pda: Pda,
+    tree: TreeBuilder,
+    seq: bool,
+    skip: bool,              // Skipping a broken text up to the next RS
+    stash: Option<ParseErr>, // Error on the character which ended the value just given
+    done: bool,
+}
+
+impl<'a> Documents<'a> {
+    fn new(opts: &ParseOptions, json_str: &'a str, seq: bool) -> Documents<'a> {
+        Documents {
+            chars: json_str.chars(),
+            pda: Pda::new(opts),
+            tree: TreeBuilder::new(opts.duplicate_keys),
+            seq,
+            skip: false,
+            stash: None,
+            done: false,
+        }
+    }
+
+    // Nothing of the next document has been read yet
+    fn idle(&self) -> bool {
+        self.pda.state == (Inside::Bgn, S::Ready) && self.pda.comment.is_none()
+    }
+
+    fn restart(&mut self) {
+        self.pda.restart();
+        self.tree = TreeBuilder::new(self.pda.opts.duplicate_keys);
+        self.skip = false;
+    }
+
+    fn take(&mut self) -> Result<JSON, ParseErr> {
+        take_root(&mut self.tree.mem).map_err(|fail| self.pda.locate(fail))
+    }
+
+    fn truncated(&mut self, err_msg: &str) -> ParseErr {
+        self.pda
+            .locate(Fail::new(ParseErrorKind::UnexpectedEof, err_msg))
+    }
+
+    // A character which is not given to the PDA, only counted
+    fn pass(&mut self, ch: char) -> Result<(), ParseErr> {
+        self.pda.count(ch);
+        let res = self
+            .pda
+            .check_size(ch)
+            .map_err(|fail| self.pda.locate(fail));
+        self.pda.byte_pos += ch.len_utf8();
+        res
+    }
+
+    // The next RS of a json-seq ends the text before it
+    fn separator(&mut self, ch: char) -> Option<Result<JSON, ParseErr>> {
+        self.pda.count(ch);
+        let res = if let Err(fail) = self.pda.check_size(ch) {
+            self.done = true;
+            Some(Err(self.pda.locate(fail)))
+        } else if self.skip || self.idle() {
+            None
+        } else if self.pda.state.0 == Inside::End {
+            Some(self.take())
+        } else {
+            Some(Err(self.truncated("The JSON text is cut by the next RS")))
+        };
+        self.restart();
+        self.pda.byte_pos += ch.len_utf8();
+        res
+    }
+
+    fn finish(&mut self) -> Option<Result<JSON, ParseErr>> {
+        if self.skip || self.idle() {
+            return None;
+        }
+        if self.seq {
+            return Some(match self.pda.state.0 {
+                Inside::End => self.take(),
+                _ => Err(self.truncated("The JSON text is cut by the end of input")),
+            });
+        }
+        if self.pda.state.0 == Inside::End {
+            return None; // Given already
+        }
+        Some(self.pda.end(&mut self.tree).and_then(|()| self.take()))
+    }
+}
+
+impl Iterator for Documents<'_> {
+    type Item = Result<JSON, ParseErr>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(err) = self.stash.take() {
+            return Some(Err(err));
+        }
+        while !self.done {
+            let Some(ch) = self.chars.next() else {
+                self.done = true;
+                return self.finish();
+            };
+            if self.seq && ch == RS {
+                match self.separator(ch) {
+                    Some(res) => return Some(res),
+                    None => continue,
+                }
+            }
+            if self.skip {
+                if let Err(err) = self.pass(ch) {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+                continue;
+            }
+            if !self.seq && self.pda.state.0 == Inside::End {
+                self.restart();
+            }
+
+            let res = self.pda.step(ch, &mut self.tree);
+            let ended = self.pda.state.0 == Inside::End;
+            match res {
+                Err(err) if self.seq => {
+                    self.skip = true;
+                    return Some(Err(err));
+                }
+                Err(err) => {
+                    self.done = true;
+                    if !ended {
+                        return Some(Err(err));
+                    }
+                    // Like `1}`, the '}' ends the number and is the error
+                    self.stash = Some(err);
+                    return Some(self.take());
+                }
+                Ok(()) if ended && !self.seq => return Some(self.take()),
+                Ok(()) => {}
+            }
+        }
+        None
+    }
+}
+
+impl Pda {
+    // Start over from Inside::Bgn for the next document, the position goes on
+    fn restart(&mut self) {
+        self.nest.clear();
+        self.items.clear();
+        self.state = (Inside::Bgn, S::Ready);
+        self.temp_key = None;
+        self.temp_val = None;
+        self.esc_ch = Escape::default();
+        self.ident = false;
+        self.comment = None;
+        self.err_at = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multi_documents() {
+        let docs: Vec<_> = parse_multi("{\"a\":1}{\"b\":2}[3]\n\"x\"4 5")
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(docs.len(), 6);
+        assert!(matches!(&docs[2], JSON::Lst(l) if matches!(l[..], [JSON::Int(3)])));
+        assert!(matches!(&docs[3], JSON::Str(s) if s == "x"));
+        assert!(matches!(docs[4..], [JSON::Int(4), JSON::Int(5)]));
+        assert_eq!(parse_multi(" \n ").count(), 0);
+
+        // Located in the whole input, and nothing after the first error
+        let mut docs = parse_multi("[]\n{\"a\" 1} 2");
+        assert!(matches!(docs.next(), Some(Ok(JSON::Lst(_)))));
+        let err = docs.next().unwrap().unwrap_err();
+        assert_eq!((err.line(), err.column()), (2, 6));
+        assert!(docs.next().is_none());
+        let mut docs = parse_multi("1}");
+        assert!(matches!(docs.next(), Some(Ok(JSON::Int(1)))));
+        assert!(matches!(docs.next(), Some(Err(_))));
+        assert!(docs.next().is_none());
+        let err = parse_multi("{} [").last().unwrap().unwrap_err();
+        assert_eq!(err.kind(), ParseErrorKind::UnexpectedEof);
+
+        let seq = "\u{1E}{\"a\":1}\n\u{1E}[1,\n\u{1E}2\n\u{1E}1 2\n\u{1E}\u{1E}true\n\u{1E}3";
+        let docs: Vec<_> = parse_json_seq(seq).collect();
+        assert_eq!(docs.len(), 6);
+        assert!(matches!(&docs[0], Ok(JSON::Obj(o)) if matches!(o["a"], JSON::Int(1))));
+        let err = docs[1].as_ref().unwrap_err();
+        assert_eq!(
+            (err.kind(), err.line(), err.column()),
+            (ParseErrorKind::UnexpectedEof, 3, 1)
+        );
+        assert!(matches!(docs[2], Ok(JSON::Int(2))));
+        assert_eq!(
+            docs[3].as_ref().unwrap_err().kind(),
+            ParseErrorKind::TrailingData
+        );
+        assert!(matches!(docs[4], Ok(JSON::Bol(true))));
+        // A number without a whitespace after it may be cut
+        assert_eq!(
+            docs[5].as_ref().unwrap_err().kind(),
+            ParseErrorKind::UnexpectedEof
+        );
+    }
+}
@@ -0,0 +1,327 @@
+use core::fmt;
+use std::str::FromStr;
+
+// A number kept exactly as written in the source. The parser only gives it
+// for the numbers which JSON::Int or JSON::Flt can't hold without losing
+// something: integers out of the i64 range, too many digits for an f64...
+#[derive(Debug, Clone, PartialEq)]
+pub struct Number {
+    lexeme: String,
+}
+
+// A number split as 0.DIGITS x 10^exp, without leading and trailing zeros
+#[derive(PartialEq)]
+struct Decimal {
+    neg: bool,
+    digits: String,
+    exp: i64,
+}
+
+fn decimal(num: &str) -> Option<Decimal> {
+    let (neg, num) = match num.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, num),
+    };
+    let (mantissa, exp) = match num.find(['e', 'E']) {
+        Some(i) => (&num[..i], num[i + 1..].parse::<i64>().ok()?),
+        None => (num, 0),
+    };
+    let (int, frac) = match mantissa.find('.') {
+        Some(i) => (&mantissa[..i], &mantissa[i + 1..]),
+        None => (mantissa, ""),
+    };
+    let all_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if int.is_empty() || !all_digits(int) || !all_digits(frac) {
+        return None;
+    }
+
+    let digits = format!("{int}{frac}");
+    let lead = digits.len() - digits.trim_start_matches('0').len();
+    let digits = digits.trim_matches('0').to_string();
+    let exp = if digits.is_empty() {
+        0
+    } else {
+        exp.checked_add(int.len() as i64 - lead as i64)?
+    };
+    Some(Decimal { neg, digits, exp })
+}
+
+// True when the f64 is the very same decimal value as the lexeme
+pub(crate) fn is_exact_f64(lexeme: &str, flt: f64) -> bool {
+    match (decimal(lexeme), decimal(&format!("{flt:e}"))) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+// The shortest text which parses back to the very same f64, always with a
+// '.' or an exponent so it stays a float: 1.0, 0.1, 1e300, -1.5e-7.
+// Plain or with an exponent like JavaScript: plain from 1e-6 up to 1e21.
+pub(crate) fn format_flt(flt: f64) -> String {
+    format_shortest(flt, true)
+}
+
+// Number.prototype.toString() of ECMAScript, which RFC 8785 (JCS) asks for:
+// 1, 0, 1e+21, -1.5e-7
+pub(crate) fn format_es(flt: f64) -> String {
+    format_shortest(flt, false)
+}
+
+fn format_shortest(flt: f64, keep_flt: bool) -> String {
+    // {:e} tells how many digits are enough. Written again with that many,
+    // they are the closest ones and the even one on a tie, as ECMAScript
+    // asks. decimal() splits them from the exponent.
+    let dec = decimal(&format!("{flt:e}"))
+        .filter(|_| flt.is_finite())
+        .and_then(|short| decimal(&format!("{flt:.*e}", short.digits.len().saturating_sub(1))));
+    let Some(Decimal { neg, digits, exp }) = dec else {
+        return format!("{flt}"); // Infinity and NaN, up to the caller
+    };
+    let sign = if neg { "-" } else { "" };
+    if digits.is_empty() {
+        // ECMAScript writes -0 as 0
+        return if keep_flt {
+            format!("{sign}0.0")
+        } else {
+            "0".into()
+        };
+    }
+    let len = digits.len() as i64;
+    match exp {
+        // The decimal point goes after `exp` digits
+        n if len <= n && n <= 21 => {
+            let point = if keep_flt { ".0" } else { "" };
+            format!("{sign}{digits}{}{point}", "0".repeat((n - len) as usize))
+        }
+        n if 0 < n && n <= 21 => {
+            let (int, frac) = digits.split_at(n as usize);
+            format!("{sign}{int}.{frac}")
+        }
+        n if -6 < n && n <= 0 => format!("{sign}0.{}{digits}", "0".repeat(-n as usize)),
+        n => {
+            let (first, rest) = digits.split_at(1);
+            let dot = if rest.is_empty() { "" } else { "." };
+            let plus = if n > 1 && !keep_flt { "+" } else { "" };
+            format!("{sign}{first}{dot}{rest}e{plus}{}", n - 1)
+        }
+    }
+}
+
+// Check the RFC 8259 number grammar: -? (0 | [1-9][0-9]*) (.[0-9]+)? ([eE][+-]?[0-9]+)?
+// On error, give the byte offset of the faulty character in the lexeme
+pub(crate) fn check_number(num: &str) -> Result<(), (usize, &'static str)> {
+    let b = num.as_bytes();
+    let digits_from = |mut i: usize| {
+        while i < b.len() && b[i].is_ascii_digit() {
+            i += 1;
+        }
+        i
+    };
+
+    let mut i = 0;
+    if b.first() == Some(&b'+') {
+        return Err((0, "A number can't start with '+'"));
+    }
+    if b.first() == Some(&b'-') {
+        i += 1;
+    }
+    match b.get(i) {
+        Some(b'0') => {
+            i += 1;
+            if b.get(i).is_some_and(u8::is_ascii_digit) {
+                return Err((i - 1, "Leading zeros are not allowed"));
+            }
+        }
+        Some(b'1'..=b'9') => i = digits_from(i),
+        Some(b'.') => return Err((i, "Expected a digit before '.'")),
+        None if i > 0 => return Err((i - 1, "Expected a digit after '-'")),
+        _ => return Err((i, "Expected a digit")),
+    }
+
+    if b.get(i) == Some(&b'.') {
+        let end = digits_from(i + 1);
+        if end == i + 1 {
+            return Err((i, "Expected a digit after '.'"));
+        }
+        i = end;
+    }
+
+    if matches!(b.get(i), Some(b'e' | b'E')) {
+        let mut j = i + 1;
+        if matches!(b.get(j), Some(b'+' | b'-')) {
+            j += 1;
+        }
+        let end = digits_from(j);
+        if end == j {
+            return Err((i, "Expected a digit in the exponent"));
+        }
+        i = end;
+    }
+
+    if i < b.len() {
+        return Err((i, "Unexpected character in number"));
+    }
+    Ok(())
+}
+
+impl Number {
+    pub fn as_str(&self) -> &str {
+        &self.lexeme
+    }
+
+    // The integer value, None if the number has a fraction or is out of range
+    pub fn as_i128(&self) -> Option<i128> {
+        let dec = decimal(&self.lexeme)?;
+        if dec.digits.is_empty() {
+            return Some(0);
+        }
+        let zeros = usize::try_from(dec.exp)
+            .ok()?
+            .checked_sub(dec.digits.len())?;
+        if zeros > 40 {
+            return None;
+        }
+        let sign = if dec.neg { "-" } else { "" };
+        format!("{sign}{}{}", dec.digits, "0".repeat(zeros))
+            .parse::<i128>()
+            .ok()
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        self.as_i128().and_then(|i| i64::try_from(i).ok())
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        self.as_i128().and_then(|i| u64::try_from(i).ok())
+    }
+
+    // The closest f64, None if the number is too large for an f64
+    pub fn as_f64(&self) -> Option<f64> {
+        self.lexeme.parse::<f64>().ok().filter(|f| f.is_finite())
+    }
+}
+
+impl FromStr for Number {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Number, &'static str> {
+        match check_number(s) {
+            Ok(()) => Ok(Number {
+                lexeme: s.to_string(),
+            }),
+            Err((_, err_msg)) => Err(err_msg),
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.lexeme)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_number_accessors() {
+        let big: Number = "18446744073709551615".parse().unwrap();
+        assert_eq!(big.as_u64(), Some(u64::MAX));
+        assert_eq!(big.as_i64(), None);
+        assert_eq!(big.as_i128(), Some(u64::MAX as i128));
+
+        let n: Number = "-1.5e3".parse().unwrap();
+        assert_eq!(n.as_i64(), Some(-1500));
+        assert_eq!(n.as_u64(), None);
+        assert_eq!(n.as_f64(), Some(-1500.0));
+
+        let n: Number = "0.1000000000000000055511".parse().unwrap();
+        assert_eq!(n.as_i64(), None);
+        assert_eq!(n.as_f64(), Some(0.1));
+        assert_eq!(n.to_string(), "0.1000000000000000055511");
+
+        let n: Number = "1e400".parse().unwrap();
+        assert_eq!(n.as_f64(), None);
+        assert_eq!(n.as_i128(), None);
+
+        assert!("1.2.3".parse::<Number>().is_err());
+        assert!("01".parse::<Number>().is_err());
+        assert!("abc".parse::<Number>().is_err());
+    }
+
+    #[test]
+    fn test_is_exact_f64() {
+        assert!(is_exact_f64("0.1", 0.1));
+        assert!(is_exact_f64("1.0", 1.0));
+        assert!(is_exact_f64("0.10", 0.1));
+        assert!(is_exact_f64("-2.50e2", -250.0));
+        assert!(is_exact_f64("-0.0", -0.0));
+        assert!(!is_exact_f64("0.1000000000000000055511", 0.1));
+        assert!(!is_exact_f64("1e400", f64::INFINITY));
+    }
+
+    #[test]
+    fn test_format_flt() {
+        let cases = [
+            (1.0, "1.0"),
+            (-0.0, "-0.0"),
+            (0.1, "0.1"),
+            (2.5, "2.5"),
+            (100.0, "100.0"),
+            (123456.789, "123456.789"),
+            (1e20, "100000000000000000000.0"),
+            (1e21, "1e21"),
+            (1e300, "1e300"),
+            (1.5e-7, "1.5e-7"),
+            (0.000001, "0.000001"),
+            (-2.5e-300, "-2.5e-300"),
+            (f64::MAX, "1.7976931348623157e308"),
+            (5e-324, "5e-324"),
+        ];
+        for (flt, text) in cases {
+            assert_eq!(format_flt(flt), text);
+            assert_eq!(check_number(text), Ok(()));
+        }
+        // Always the same f64 back, over random bit patterns
+        let mut bits = 0x9E37_79B9_7F4A_7C15u64;
+        for _ in 0..5000 {
+            bits = bits
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let flt = f64::from_bits(bits);
+            if flt.is_finite() {
+                let text = format_flt(flt);
+                assert_eq!(
+                    text.parse::<f64>().unwrap().to_bits(),
+                    flt.to_bits(),
+                    "{text}"
+                );
+                assert_eq!(check_number(&text), Ok(()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_number() {
+        for ok in ["0", "-0", "10", "1.5", "-0.0e+0", "2E-3", "123e10"] {
+            assert_eq!(check_number(ok), Ok(()), "{ok}");
+        }
+        let errs = [
+            ("+1", 0, "A number can't start with '+'"),
+            ("01", 0, "Leading zeros are not allowed"),
+            ("-00.1", 1, "Leading zeros are not allowed"),
+            ("1.", 1, "Expected a digit after '.'"),
+            (".5", 0, "Expected a digit before '.'"),
+            ("-", 0, "Expected a digit after '-'"),
+            ("-a", 1, "Expected a digit"),
+            ("1e", 1, "Expected a digit in the exponent"),
+            ("1e+", 1, "Expected a digit in the exponent"),
+            ("1.5x", 3, "Unexpected character in number"),
+            ("0x1F", 1, "Unexpected character in number"),
+        ];
+        for (num, at, msg) in errs {
+            assert_eq!(check_number(num), Err((at, msg)), "{num}");
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use super::JSON;
+
+// JSON Pointer (RFC 6901): "/results/0/name" goes through the key "results",
+// the index 0 of that list, then the key "name". "" is the whole document.
+// In the keys, "~1" stands for '/' and "~0" for '~'.
+impl JSON {
+    pub fn pointer(&self, ptr: &str) -> Option<&JSON> {
+        tokens(ptr)?
+            .iter()
+            .try_fold(self, |node, token| node.child(token))
+    }
+
+    pub fn pointer_mut(&mut self, ptr: &str) -> Option<&mut JSON> {
+        tokens(ptr)?
+            .iter()
+            .try_fold(self, |node, token| node.child_mut(token))
+    }
+
+    // Put the value at ptr like the "add" of JSON Patch (RFC 6902): in an
+    // object the key is set, in a list the value is inserted before the index
+    // and "-" appends it. The parent must exist. Gives back the value which
+    // was replaced, if any.
+    pub fn pointer_insert(&mut self, ptr: &str, val: JSON) -> Result<Option<JSON>, &'static str> {
+        let mut tokens = tokens(ptr).ok_or("Invalid JSON Pointer")?;
+        let Some(last) = tokens.pop() else {
+            return Ok(Some(std::mem::replace(self, val)));
+        };
+        let parent = tokens
+            .iter()
+            .try_fold(self, |node, token| node.child_mut(token))
+            .ok_or("The parent of the pointer doesn't exist")?;
+        match parent {
+            JSON::Obj(obj) => Ok(obj.insert(last, val)),
+            JSON::Lst(lst) if last == "-" => {
+                lst.push(val);
+                Ok(None)
+            }
+            JSON::Lst(lst) => {
+                let i = index(&last)
+                    .filter(|i| *i <= lst.len())
+                    .ok_or("The list index of the pointer is out of range")?;
+                lst.insert(i, val);
+                Ok(None)
+            }
+            _ => Err("The parent of the pointer is not a list or an object"),
+        }
+    }
+
+    // Take the value at ptr out of its parent. The whole document ("") can't
+    // be removed.
+    pub fn pointer_remove(&mut self, ptr: &str) -> Option<JSON> {
+        let mut tokens = tokens(ptr)?;
+        let last = tokens.pop()?;
+        match tokens
+            .iter()
+            .try_fold(self, |node, token| node.child_mut(token))?
+        {
+            JSON::Obj(obj) => obj.remove(last.as_str()),
+            JSON::Lst(lst) => {
+                let i = index(&last).filter(|i| *i < lst.len())?;
+                Some(lst.remove(i))
+            }
+            _ => None,
+        }
+    }
+
+    fn child(&self, token: &str) -> Option<&JSON> {
+        match self {
+            JSON::Obj(obj) => obj.get(token),
+            JSON::Lst(lst) => lst.get(index(token)?),
+            _ => None,
+        }
+    }
+
+    fn child_mut(&mut self, token: &str) -> Option<&mut JSON> {
+        match self {
+            JSON::Obj(obj) => obj.get_mut(token),
+            JSON::Lst(lst) => lst.get_mut(index(token)?),
+            _ => None,
+        }
+    }
+}
+
+// The reference tokens of the pointer, unescaped. None if it is invalid.
+fn tokens(ptr: &str) -> Option<Vec<String>> {
+    if ptr.is_empty() {
+        return Some(Vec::new());
+    }
+    ptr.strip_prefix('/')?.split('/').map(unescape).collect()
+}
+
+fn unescape(token: &str) -> Option<String> {
+    let mut out = String::with_capacity(token.len());
+    let mut chars = token.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '~' => match chars.next() {
+                Some('0') => out.push('~'),
+                Some('1') => out.push('/'),
+                _ => return None, // Any other '~' is an error
+            },
+            _ => out.push(ch),
+        }
+    }
+    Some(out)
+}
+
+// List indices are 0 or digits without leading zeros, no sign
+fn index(token: &str) -> Option<usize> {
+    let digits = !token.is_empty() && token.bytes().all(|b| b.is_ascii_digit());
+    if !digits || token.len() > 1 && token.starts_with('0') {
+        return None;
+    }
+    token.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse;
+    use crate::JSON;
+
+    #[test]
+    fn test_pointer() {
+        // The example of RFC 6901
+        let mut doc = parse(
+            r#"{"foo": ["bar", "baz"], "": 0, "a/b": 1, "c%d": 2, "e^f": 3, "g|h": 4,
+                "i\\j": 5, "k\"l": 6, " ": 7, "m~n": 8}"#,
+        )
+        .unwrap();
+        assert!(matches!(doc.pointer(""), Some(JSON::Obj(o)) if o.len() == 10));
+        assert!(matches!(doc.pointer("/foo"), Some(JSON::Lst(l)) if l.len() == 2));
+        assert!(matches!(doc.pointer("/foo/0"), Some(JSON::Str(s)) if s == "bar"));
+        let ints = [
+            ("/", 0),
+            ("/a~1b", 1),
+            ("/c%d", 2),
+            ("/e^f", 3),
+            ("/g|h", 4),
+            ("/i\\j", 5),
+            ("/k\"l", 6),
+            ("/ ", 7),
+            ("/m~0n", 8),
+        ];
+        for (ptr, int) in ints {
+            assert!(
+                matches!(doc.pointer(ptr), Some(JSON::Int(i)) if *i == int),
+                "{ptr}"
+            );
+        }
+        for ptr in [
+            "foo", "/foo/2", "/foo/01", "/foo/-", "/foo/+1", "/a~2b", "/foo/0/x", "/nope",
+        ] {
+            assert!(doc.pointer(ptr).is_none(), "{ptr}");
+        }
+
+        if let Some(JSON::Str(s)) = doc.pointer_mut("/foo/1") {
+            s.push('!');
+        }
+        assert!(matches!(doc.pointer("/foo/1"), Some(JSON::Str(s)) if s == "baz!"));
+
+        // Like the "add" of JSON Patch
+        assert!(matches!(
+            doc.pointer_insert("/foo/0", JSON::Int(9)),
+            Ok(None)
+        ));
+        assert!(matches!(doc.pointer_insert("/foo/-", JSON::Nul), Ok(None)));
+        assert!(matches!(
+            doc.pointer_insert("/a~1b", JSON::Bol(true)),
+            Ok(Some(JSON::Int(1)))
+        ));
+        assert!(doc.pointer_insert("/foo/9", JSON::Nul).is_err());
+        assert!(doc.pointer_insert("/x/y", JSON::Nul).is_err());
+        assert!(doc.pointer_insert("/ /y", JSON::Nul).is_err());
+        assert!(matches!(doc.pointer("/foo"), Some(JSON::Lst(l))
+            if matches!(l[..], [JSON::Int(9), JSON::Str(_), JSON::Str(_), JSON::Nul])));
+
+        assert!(matches!(doc.pointer_remove("/foo/1"), Some(JSON::Str(s)) if s == "bar"));
+        assert!(matches!(doc.pointer_remove("/m~0n"), Some(JSON::Int(8))));
+        assert!(doc.pointer_remove("/m~0n").is_none());
+        assert!(doc.pointer_remove("").is_none());
+        assert!(matches!(doc.pointer("/foo"), Some(JSON::Lst(l)) if l.len() == 3));
+
+        let mut scalar = JSON::Int(1);
+        assert!(matches!(
+            scalar.pointer_insert("", JSON::Nul),
+            Ok(Some(JSON::Int(1)))
+        ));
+        assert!(matches!(scalar, JSON::Nul));
+    }
+}
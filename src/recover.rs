@@ -0,0 +1,273 @@
+use super::{
+    take_root, Comment, Escape, Fail, Inside, ParseErr, ParseErrorKind, ParseOptions, Pda, Preb,
+    TreeBuilder, JSON, S,
+};
+
+// What parse_recovering() could make out of a broken document
+#[derive(Debug)]
+pub struct Recovered {
+    pub value: Option<JSON>,   // Best-effort tree, the broken parts are left out
+    pub errors: Vec<ParseErr>, // Every error found, in the order of the document
+}
+
+// Parse the whole document even when it has errors, see Recovered
+pub fn parse_recovering(json_str: &str) -> Recovered {
+    ParseOptions::default().parse_recovering(json_str)
+}
+
+impl ParseOptions {
+    // After an error, the broken key or value is dropped and the parsing goes
+    // on from the next ',', '}' or ']' of the same container
+    pub fn parse_recovering(&self, json_str: &str) -> Recovered {
+        let mut pda = Pda::new(self);
+        let mut tree = TreeBuilder::new(self.duplicate_keys);
+        let mut errors = Vec::new();
+        let mut skip: Option<Skip> = None;
+        let mut too_large = false;
+
+        for ch in json_str.chars() {
+            pda.count(ch);
+            if let Err(fail) = pda.check_size(ch) {
+                errors.push(pda.locate(fail));
+                too_large = true;
+                break;
+            }
+            match skip.as_mut() {
+                None => {
+                    if let Err(fail) = pda.transit(ch, &mut tree) {
+                        errors.push(pda.locate(fail));
+                        skip = pda.forget(ch, &mut tree, &mut errors);
+                    }
+                }
+                Some(sk) => {
+                    if sk.sync(ch) && pda.resync(ch, &mut tree, &mut errors) {
+                        skip = None;
+                    }
+                }
+            }
+            pda.byte_pos += ch.len_utf8();
+        }
+
+        if skip.is_none() && !too_large {
+            if let Err(err) = pda.end(&mut tree) {
+                errors.push(err);
+            }
+        }
+        // end() stops at a broken last primitive, the structure is still incomplete then
+        let at_eof = errors.last().is_some_and(|err| {
+            matches!(
+                err.kind(),
+                ParseErrorKind::UnexpectedEof | ParseErrorKind::UnterminatedString
+            )
+        });
+        if !at_eof && !too_large && pda.state.0 != Inside::End {
+            let fail = Fail::new(ParseErrorKind::UnexpectedEof, "Incomplete JSON structure");
+            errors.push(pda.locate(fail));
+        }
+        // Close whatever is still open to give back the tree so far
+        pda.drop_token(&mut tree);
+        while !pda.nest.is_empty() {
+            if let Err(fail) = pda.end_ctnr(&mut tree) {
+                errors.push(pda.locate(fail));
+                break;
+            }
+        }
+
+        Recovered {
+            value: take_root(&mut tree.mem).ok(),
+            errors,
+        }
+    }
+}
+
+// Where we are while skipping the broken part of the document. The strings
+// and comments are followed like the Pda does, so that a ',', '}' or ']'
+// inside them doesn't end the skip.
+struct Skip {
+    depth: usize,        // Brackets opened while skipping
+    quote: Option<char>, // The quote of the string we are in
+    esc: bool,
+    comment: Option<Comment>,
+    comments: bool,      // The // and /* */ comments are allowed
+    single_quotes: bool, // The 'strings' of JSON5 are allowed
+}
+
+impl Skip {
+    fn new(opts: &ParseOptions) -> Skip {
+        Skip {
+            depth: 0,
+            quote: None,
+            esc: false,
+            comment: None,
+            comments: opts.comments || opts.json5,
+            single_quotes: opts.json5,
+        }
+    }
+
+    // True when ch is a ',', '}' or ']' of the container the error is in
+    fn sync(&mut self, ch: char) -> bool {
+        if let Some(quote) = self.quote {
+            match ch {
+                _ if self.esc => self.esc = false,
+                '\\' => self.esc = true,
+                _ if ch == quote => self.quote = None,
+                _ => {}
+            }
+            return false;
+        }
+        match self.comment {
+            // A lone '/' was no comment, ch is taken as it is
+            Some(Comment::Slash) if !matches!(ch, '/' | '*') => self.comment = None,
+            Some(comment) => {
+                self.comment = comment.after(ch);
+                return false;
+            }
+            None => {}
+        }
+        match ch {
+            '"' => self.quote = Some(ch),
+            '\'' if self.single_quotes => self.quote = Some(ch),
+            '/' if self.comments => self.comment = Some(Comment::Slash),
+            '{' | '[' => self.depth += 1,
+            '}' | ']' if self.depth > 0 => self.depth -= 1,
+            ',' => return self.depth == 0,
+            '}' | ']' => return true,
+            _ => {}
+        }
+        false
+    }
+}
+
+impl Pda {
+    // Forget the token which was broken by an error on ch. Give the Skip to
+    // go on with, or None when ch already ends the broken part.
+    fn forget(
+        &mut self,
+        ch: char,
+        tree: &mut TreeBuilder,
+        errors: &mut Vec<ParseErr>,
+    ) -> Option<Skip> {
+        let in_str = matches!(self.state.1, S::BgnKey | S::BgnStrV) && !self.ident;
+        let quote = self.quote;
+        self.drop_token(tree);
+        let mut skip = Skip::new(&self.opts);
+        if in_str {
+            // An error on the closing quote ends the string anyway
+            skip.quote = Some(quote).filter(|&quote| ch != quote);
+        } else if skip.sync(ch) && self.resync(ch, tree, errors) {
+            return None;
+        }
+        Some(skip)
+    }
+
+    // Drop the half-read token, and the key waiting for a value that won't come
+    fn drop_token(&mut self, tree: &mut TreeBuilder) {
+        self.temp_key = None;
+        self.temp_val = None;
+        self.esc_ch = Escape::default();
+        self.ident = false;
+        self.comment = None;
+        self.err_at = None;
+        if let Some(Preb::Key(_)) = tree.mem.last() {
+            tree.mem.pop();
+        }
+    }
+
+    // Go on from the ',', '}' or ']' which ends the skipped part, false when
+    // there is no container to go on with
+    fn resync(&mut self, ch: char, tree: &mut TreeBuilder, errors: &mut Vec<ParseErr>) -> bool {
+        match (ch, &self.state.0) {
+            (',', Inside::Obj) => self.state.1 = S::ExpectKey,
+            (',', Inside::List) => self.state.1 = S::ExpectVal,
+            // A mismatched bracket still closes the innermost container
+            ('}' | ']', Inside::Obj | Inside::List) => {
+                if let Err(fail) = self.end_ctnr(tree) {
+                    errors.push(self.locate(fail));
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_recovering() {
+        let src = r#"{
+  "a": tru,
+  "b": [1 2, "x\qy", 3,],
+  c: 4,
+  "d": {"e" 5},
+  "f": null
+}"#;
+        let rec = parse_recovering(src);
+        let found: Vec<_> = rec.errors.iter().map(|e| (e.kind(), e.line())).collect();
+        assert_eq!(
+            found,
+            [
+                (ParseErrorKind::InvalidLiteral, 2),
+                (ParseErrorKind::UnexpectedChar, 3),
+                (ParseErrorKind::InvalidEscape, 3),
+                (ParseErrorKind::UnexpectedChar, 3),
+                (ParseErrorKind::ExpectedKey, 4),
+                (ParseErrorKind::ExpectedColon, 5),
+            ]
+        );
+        let Some(JSON::Obj(obj)) = rec.value else {
+            panic!("Expected an object");
+        };
+        assert_eq!(obj.len(), 3);
+        assert!(!obj.contains_key("a"));
+        assert!(matches!(&obj["b"], JSON::Lst(l) if matches!(l[..], [JSON::Int(1), JSON::Int(3)])));
+        assert!(matches!(&obj["d"], JSON::Obj(o) if o.is_empty()));
+        assert!(matches!(obj["f"], JSON::Nul));
+
+        // The containers left open are closed at the end
+        let rec = parse_recovering("[1, {\"a\": [2, tru");
+        assert_eq!(rec.errors.len(), 2);
+        assert_eq!(rec.errors[1].kind(), ParseErrorKind::UnexpectedEof);
+        let Some(JSON::Lst(lst)) = rec.value else {
+            panic!("Expected a list");
+        };
+        assert!(matches!(&lst[1], JSON::Obj(o) if matches!(&o["a"], JSON::Lst(l) if l.len() == 1)));
+
+        let rec = parse_recovering("[1] 2");
+        assert!(matches!(rec.value, Some(JSON::Lst(_))));
+        assert_eq!(rec.errors[0].kind(), ParseErrorKind::TrailingData);
+        assert!(parse_recovering("[\"ok\"]").errors.is_empty());
+    }
+
+    #[test]
+    fn test_recover_json5() {
+        let json5 = ParseOptions {
+            json5: true,
+            ..ParseOptions::default()
+        };
+        // The ',' '}' and ']' in the skipped strings and comments don't end the skip
+        let src = "{a: tru 'x,}' // y, ]\n 2, b: nul /* ], } */ 3, c: [1 2 'z]', 3], d: 4}";
+        let rec = json5.parse_recovering(src);
+        assert_eq!(rec.errors.len(), 3);
+        let Some(JSON::Obj(obj)) = rec.value else {
+            panic!("Expected an object");
+        };
+        assert_eq!(obj.len(), 2);
+        assert!(matches!(&obj["c"], JSON::Lst(l) if matches!(l[..], [JSON::Int(1), JSON::Int(3)])));
+        assert!(matches!(obj["d"], JSON::Int(4)));
+
+        // A lone '/' while skipping isn't a comment
+        let comments = ParseOptions {
+            comments: true,
+            ..ParseOptions::default()
+        };
+        let rec = comments.parse_recovering("[tru 1/2, 3]");
+        assert!(matches!(rec.value, Some(JSON::Lst(l)) if matches!(l[..], [JSON::Int(3)])));
+        // And with plain JSON a ' is just a character
+        let rec = parse_recovering("[tru 'x, 3, '4']");
+        assert_eq!(rec.errors.len(), 2);
+        assert!(matches!(rec.value, Some(JSON::Lst(l)) if matches!(l[..], [JSON::Int(3)])));
+    }
+}
@@ -0,0 +1,194 @@
+use core::fmt::Write;
+
+use super::{ParseErr, ParseErrorKind};
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const CYAN: &str = "\x1b[1;36m";
+const RESET: &str = "\x1b[0m";
+
+impl ParseErr {
+    // Show the error with the line of the source where it is and a caret
+    // under the faulty character, plus a hint when we can guess the cause.
+    // json_str must be the input the error came from.
+    //
+    //   error: Expected '}' to finish the object or ',' for next entry
+    //    --> line 1, column 10
+    //     |
+    //   1 | {"a":"b" "c":1}
+    //     |          ^
+    //     = hint: did you forget a comma?
+    pub fn render(&self, json_str: &str) -> String {
+        self.render_with(json_str, false)
+    }
+
+    // Same as render() with ANSI colors for terminals
+    pub fn render_colored(&self, json_str: &str) -> String {
+        self.render_with(json_str, true)
+    }
+
+    fn render_with(&self, json_str: &str, color: bool) -> String {
+        let paint = |code: &'static str| if color { code } else { "" };
+        let (red, blue, cyan, reset) = (paint(RED), paint(BLUE), paint(CYAN), paint(RESET));
+
+        let line = json_str
+            .split('\n')
+            .nth(self.line_idx.saturating_sub(1))
+            .unwrap_or("")
+            .trim_end_matches('\r');
+        // Keep the tabs so the caret lines up with the character above it
+        let pad: String = line
+            .chars()
+            .take(self.ch_pos.saturating_sub(1))
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect();
+        let num = self.line_idx.to_string();
+        let gutter = " ".repeat(num.len());
+
+        let mut out = String::new();
+        // Writing to a String can't fail
+        let _ = writeln!(out, "{red}error{reset}: {}", self.msg);
+        let _ = writeln!(
+            out,
+            "{gutter}{blue}-->{reset} line {}, column {}",
+            self.line_idx, self.ch_pos
+        );
+        let _ = writeln!(out, "{gutter} {blue}|{reset}");
+        let _ = writeln!(out, "{blue}{num} |{reset} {line}");
+        let _ = write!(out, "{gutter} {blue}|{reset} {pad}{red}^{reset}");
+        if let Some(hint) = self.hint(json_str) {
+            let _ = write!(out, "\n{gutter} {blue}={reset} {cyan}hint{reset}: {hint}");
+        }
+        out
+    }
+
+    // Guess the cause from the kind of error and the characters around it
+    fn hint(&self, json_str: &str) -> Option<&'static str> {
+        let at = json_str.get(self.byte_pos..).unwrap_or("");
+        let ch = at.chars().next();
+        let before = json_str.get(..self.byte_pos).unwrap_or("").trim_end();
+        let prev = before.chars().next_back();
+
+        match self.kind {
+            ParseErrorKind::UnexpectedChar | ParseErrorKind::ExpectedKey => match (prev, ch) {
+                (Some(','), Some('}' | ']')) => Some("trailing comma not allowed"),
+                (_, Some('\'')) => Some("strings and keys need double quotes"),
+                (_, Some(c))
+                    if self.kind == ParseErrorKind::ExpectedKey
+                        && (c.is_alphabetic() || c == '_') =>
+                {
+                    Some("keys must be strings in double quotes")
+                }
+                (Some(p), Some(c)) if ends_value(p) && begins_value(c) => {
+                    Some("did you forget a comma?")
+                }
+                (_, Some('}' | ']')) => Some("the brackets don't match"),
+                _ => None,
+            },
+            ParseErrorKind::ExpectedColon => {
+                Some("did you forget the ':' between the key and its value?")
+            }
+            ParseErrorKind::UnterminatedString => Some("a string is missing its closing '\"'"),
+            ParseErrorKind::InvalidEscape => {
+                Some("the escapes are \\\" \\\\ \\/ \\b \\f \\n \\r \\t and \\uXXXX")
+            }
+            ParseErrorKind::InvalidLiteral => Some("true, false and null are lowercase"),
+            ParseErrorKind::TrailingData => {
+                Some("only one value is allowed at the root, put them in a list?")
+            }
+            ParseErrorKind::UnexpectedEof if before.is_empty() => {
+                Some("the input is empty, there is no value to parse")
+            }
+            ParseErrorKind::UnexpectedEof if in_container(before) => {
+                Some("a closing '}' or ']' is missing")
+            }
+            _ => None,
+        }
+    }
+}
+
+// Whether a '{' or '[' is still open at the end of src, the strings skipped
+fn in_container(src: &str) -> bool {
+    let (mut depth, mut in_str, mut esc) = (0usize, false, false);
+    for ch in src.chars() {
+        match (in_str, ch) {
+            (true, _) if esc => esc = false,
+            (true, '\\') => esc = true,
+            (true, '"') => in_str = false,
+            (false, '"') => in_str = true,
+            (false, '{' | '[') => depth += 1,
+            (false, '}' | ']') => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    depth > 0
+}
+
+fn ends_value(ch: char) -> bool {
+    // The last characters of strings, numbers, containers, true/false and null
+    ch == '"' || ch == '}' || ch == ']' || ch == 'e' || ch == 'l' || ch.is_ascii_digit()
+}
+
+fn begins_value(ch: char) -> bool {
+    matches!(ch, '"' | '{' | '[' | '-' | 't' | 'f' | 'n') || ch.is_ascii_digit()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse;
+
+    #[test]
+    fn test_render() {
+        let src = "{\n\t\"a\": \"b\" \"c\": 1\n}";
+        let err = parse(src).unwrap_err();
+        assert_eq!(
+            err.render(src),
+            "error: Expected '}' to finish the object or ',' for next entry\n \
+             --> line 2, column 11\n  \
+             |\n\
+             2 | \t\"a\": \"b\" \"c\": 1\n  \
+             | \t         ^\n  \
+             = hint: did you forget a comma?"
+        );
+        assert!(err.render_colored(src).contains("\x1b[1;31m^\x1b[0m"));
+
+        let hint = |src: &str| {
+            let rendered = parse(src).unwrap_err().render(src);
+            rendered.split("hint: ").nth(1).map(str::to_string)
+        };
+        assert_eq!(
+            hint("[1, 2,]").as_deref(),
+            Some("trailing comma not allowed")
+        );
+        assert_eq!(
+            hint("{\"a\": 1,\n}").as_deref(),
+            Some("trailing comma not allowed")
+        );
+        assert_eq!(hint("[1 2]").as_deref(), Some("did you forget a comma?"));
+        assert_eq!(
+            hint("{\"a\" 1}").as_deref(),
+            Some("did you forget the ':' between the key and its value?")
+        );
+        assert_eq!(
+            hint("{a: 1}").as_deref(),
+            Some("keys must be strings in double quotes")
+        );
+        assert_eq!(
+            hint("['a']").as_deref(),
+            Some("strings and keys need double quotes")
+        );
+        assert_eq!(hint("[1, 2}").as_deref(), Some("the brackets don't match"));
+        assert_eq!(hint("[+1]"), None);
+        assert_eq!(
+            hint("{\"a\": [1, \"]}\"").as_deref(),
+            Some("a closing '}' or ']' is missing")
+        );
+        for src in ["", " \n\t"] {
+            assert_eq!(
+                hint(src).as_deref(),
+                Some("the input is empty, there is no value to parse"),
+                "{src:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use core::fmt::{self, Write};
+use std::{io, iter, slice, vec};
+
+use super::number::format_flt;
+use super::JSON;
+
+// Trees can be of any depth, without a ParseOptions::max_depth or made by
+// hand. The code walking them by recursion stops there
+pub(crate) const MAX_DEPTH: usize = 512;
+
+// How to_writer_pretty() lays the JSON out. With empty indent and newline
+// and no space after the colons, the output is the compact one.
+#[derive(Debug, Clone)]
+pub struct PrettyOptions {
+    pub indent: String,  // Written once per level of nesting
+    pub newline: String, // Like "\n" or "\r\n"
+    pub space_after_colon: bool,
+    pub sort_keys: bool, // Objects in the order of their keys instead of the map order
+    pub ascii_only: bool, // Write the characters out of ASCII as \uXXXX escapes
+    pub non_finite: NonFinite,
+}
+
+// What to write for the Infinity and NaN floats, JSON numbers can't hold them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NonFinite {
+    Error, // Stop with an error, nothing can be written which reads back the same
+    #[default]
+    Null, // Like JSON.stringify() of JavaScript
+    Str,   // The strings "Infinity", "-Infinity" and "NaN"
+}
+
+impl Default for PrettyOptions {
+    fn default() -> PrettyOptions {
+        PrettyOptions {
+            indent: String::from("  "),
+            newline: String::from("\n"),
+            space_after_colon: true,
+            sort_keys: false,
+            ascii_only: false,
+            non_finite: NonFinite::default(),
+        }
+    }
+}
+
+pub(crate) const COMPACT: PrettyOptions = PrettyOptions {
+    indent: String::new(),
+    newline: String::new(),
+    space_after_colon: false,
+    sort_keys: false,
+    ascii_only: false,
+    non_finite: NonFinite::Null,
+};
+
+// Why the writing stopped
+pub(crate) enum Stop {
+    Fmt, // The underlying writer failed
+    Msg(&'static str),
+}
+
+impl From<fmt::Error> for Stop {
+    fn from(_: fmt::Error) -> Stop {
+        Stop::Fmt
+    }
+}
+
+// Compact JSON, `{:#}` gives the pretty one with the default PrettyOptions.
+// parse() gives the same tree back.
+impl fmt::Display for JSON {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let opts = if f.alternate() {
+            &PrettyOptions::default()
+        } else {
+            &COMPACT
+        };
+        write_json(f, self, opts).map_err(|_| fmt::Error)
+    }
+}
+
+// Write the JSON with the layout of opts to any io::Write. The writes are
+// small, a BufWriter around files and sockets is a good idea.
+pub fn to_writer_pretty<W: io::Write + ?Sized>(
+    json: &JSON,
+    writer: &mut W,
+    opts: PrettyOptions,
+) -> io::Result<()> {
+    to_io(json, writer, &opts)
+}
+
+pub(crate) fn to_io<W: io::Write + ?Sized>(
+    json: &JSON,
+    writer: &mut W,
+    opts: &PrettyOptions,
+) -> io::Result<()> {
+    let mut out = IoWriter {
+        inner: writer,
+        err: None,
+    };
+    match write_json(&mut out, json, opts) {
+        Ok(()) => Ok(()),
+        Err(Stop::Msg(err_msg)) => Err(io::Error::new(io::ErrorKind::InvalidData, err_msg)),
+        Err(Stop::Fmt) => Err(out
+            .err
+            .unwrap_or_else(|| io::Error::other("Formatter error"))),
+    }
+}
+
+// Keep the io::Error, fmt::Write can only say that something failed
+struct IoWriter<'w, W: ?Sized> {
+    inner: &'w mut W,
+    err: Option<io::Error>,
+}
+
+impl<W: io::Write + ?Sized> fmt::Write for IoWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.err = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+pub(crate) fn write_json<W: Write + ?Sized>(
+    out: &mut W,
+    json: &JSON,
+    opts: &PrettyOptions,
+) -> Result<(), Stop> {
+    Ser { out, opts }.value(json)
+}
+
+struct Ser<'a, W: ?Sized> {
+    out: &'a mut W,
+    opts: &'a PrettyOptions,
+}
+
+// The lists and objects being written. An explicit stack instead of
+// recursion, Display can't fail on a tree however deep.
+enum Open<'j> {
+    Lst(iter::Enumerate<slice::Iter<'j, JSON>>),
+    Obj(iter::Enumerate<vec::IntoIter<(&'j String, &'j JSON)>>),
+}
+
+impl<W: Write + ?Sized> Ser<'_, W> {
+    fn value(&mut self, json: &JSON) -> Result<(), Stop> {
+        let mut stack = Vec::new();
+        let mut next = Some(json);
+        loop {
+            match next.take() {
+                Some(JSON::Int(i)) => write!(self.out, "{i}")?,
+                Some(JSON::Flt(f)) => self.flt(*f)?,
+                Some(JSON::Num(n)) => write!(self.out, "{n}")?,
+                Some(JSON::Str(s)) => self.string(s)?,
+                Some(JSON::Lst(lst)) if lst.is_empty() => self.out.write_str("[]")?,
+                Some(JSON::Lst(lst)) => {
+                    self.out.write_char('[')?;
+                    stack.push(Open::Lst(lst.iter().enumerate()));
+                }
+                Some(JSON::Obj(obj)) if obj.is_empty() => self.out.write_str("{}")?,
+                Some(JSON::Obj(obj)) => {
+                    let mut entries: Vec<_> = obj.iter().collect();
+                    if self.opts.sort_keys {
+                        entries.sort_by(|a, b| a.0.cmp(b.0));
+                    }
+                    self.out.write_char('{')?;
+                    stack.push(Open::Obj(entries.into_iter().enumerate()));
+                }
+                Some(JSON::Bol(b)) => write!(self.out, "{b}")?,
+                Some(JSON::Nul) => self.out.write_str("null")?,
+                None => {}
+            }
+            let lvl = stack.len();
+            match stack.last_mut() {
+                None => return Ok(()),
+                Some(Open::Lst(items)) => match items.next() {
+                    Some((i, item)) => {
+                        self.next_item(i, lvl)?;
+                        next = Some(item);
+                    }
+                    None => {
+                        stack.pop();
+                        self.newline(lvl - 1)?;
+                        self.out.write_char(']')?;
+                    }
+                },
+                Some(Open::Obj(entries)) => match entries.next() {
+                    Some((i, (k, v))) => {
+                        self.next_item(i, lvl)?;
+                        self.string(k)?;
+                        self.out.write_char(':')?;
+                        if self.opts.space_after_colon {
+                            self.out.write_char(' ')?;
+                        }
+                        next = Some(v);
+                    }
+                    None => {
+                        stack.pop();
+                        self.newline(lvl - 1)?;
+                        self.out.write_char('}')?;
+                    }
+                },
+            }
+        }
+    }
+
+    fn next_item(&mut self, i: usize, lvl: usize) -> Result<(), Stop> {
+        if i > 0 {
+            self.out.write_char(',')?;
+        }
+        self.newline(lvl)
+    }
+
+    fn newline(&mut self, lvl: usize) -> Result<(), Stop> {
+        self.out.write_str(&self.opts.newline)?;
+        for _ in 0..lvl {
+            self.out.write_str(&self.opts.indent)?;
+        }
+        Ok(())
+    }
+
+    fn flt(&mut self, f: f64) -> Result<(), Stop> {
+        if f.is_finite() {
+            self.out.write_str(&format_flt(f))?;
+            return Ok(());
+        }
+        match self.opts.non_finite {
+            NonFinite::Error => {
+                return Err(Stop::Msg(
+                    "Infinity and NaN can't be written as JSON numbers",
+                ));
+            }
+            NonFinite::Null => self.out.write_str("null")?,
+            NonFinite::Str if f.is_nan() => self.out.write_str("\"NaN\"")?,
+            NonFinite::Str if f > 0.0 => self.out.write_str("\"Infinity\"")?,
+            NonFinite::Str => self.out.write_str("\"-Infinity\"")?,
+        }
+        Ok(())
+    }
+
+    fn string(&mut self, s: &str) -> Result<(), Stop> {
+        Ok(write_escaped(self.out, s, self.opts.ascii_only)?)
+    }
+}
+
+// The string in double quotes, with the characters JSON doesn't allow
+// escaped. The plain runs between the escapes are written at once.
+pub(crate) fn write_escaped<W: Write + ?Sized>(
+    out: &mut W,
+    s: &str,
+    ascii_only: bool,
+) -> fmt::Result {
+    out.write_char('"')?;
+    let mut plain = 0;
+    for (i, ch) in s.char_indices() {
+        let esc = match ch {
+            '"' => "\\\"",
+            '\\' => "\\\\",
+            '\n' => "\\n",
+            '\r' => "\\r",
+            '\t' => "\\t",
+            '\u{0008}' => "\\b",
+            '\u{000C}' => "\\f",
+            '\u{0000}'..='\u{001F}' => "",
+            _ if !ch.is_ascii() && ascii_only => "",
+            _ => continue,
+        };
+        out.write_str(&s[plain..i])?;
+        plain = i + ch.len_utf8();
+        if !esc.is_empty() {
+            out.write_str(esc)?;
+            continue;
+        }
+        let mut units = [0u16; 2];
+        for unit in ch.encode_utf16(&mut units) {
+            write!(out, "\\u{unit:04x}")?;
+        }
+    }
+    out.write_str(&s[plain..])?;
+    out.write_char('"')
+}
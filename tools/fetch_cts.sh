@@ -0,0 +1,25 @@
+#!/bin/sh
+# Vendor the official JSONPath Compliance Test Suite as json_test/cts.json,
+# with its license and the commit it was taken from, for test_jsonpath_cts.
+# Give the commit to pin, a full hash of the upstream main branch:
+#
+#   tools/fetch_cts.sh <commit>
+#
+# Then commit the three files under json_test/.
+
+set -eu
+
+REPO=https://github.com/jsonpath-standard/jsonpath-compliance-test-suite
+REV=${1:?usage: tools/fetch_cts.sh <commit>}
+DEST=$(dirname "$0")/../json_test
+
+TMP=$(mktemp -d)
+trap 'rm -rf "$TMP"' EXIT
+
+git -C "$TMP" init -q
+git -C "$TMP" fetch -q --depth 1 "$REPO" "$REV"
+git -C "$TMP" checkout -q FETCH_HEAD
+
+cp "$TMP/cts.json" "$DEST/cts.json"
+cp "$TMP/LICENSE" "$DEST/cts.LICENSE"
+printf '%s\n%s\n' "$REPO" "$(git -C "$TMP" rev-parse HEAD)" > "$DEST/cts.SOURCE"
@@ -0,0 +1,75 @@
+#!/usr/bin/env python3
+# Write src/categories.rs, the Unicode general categories for the \p{..} of
+# I-Regexp, from the unicodedata module of Python. The Unicode version is the
+# one of the Python which runs it (3.11 has Unicode 14.0).
+#
+#   python3 tools/gen_categories.py > src/categories.rs
+
+import sys
+import unicodedata
+
+HEADER = """\
+// The Unicode general category of every character, for the \\p{..} of
+// I-Regexp. Generated from the Unicode %s data of Python's unicodedata:
+// the runs of characters with the same category, sorted, Cn left out.
+// Don't edit it, run again: python3 tools/gen_categories.py > src/categories.rs
+
+use core::cmp::Ordering;
+
+// The two letters of the category of ch, like ('L', 'u'). The characters in
+// no run are unassigned, Cn.
+pub(crate) fn category(ch: char) -> (char, char) {
+    let cp = ch as u32;
+    let found = RUNS.binary_search_by(|&(from, to, _)| {
+        if to < cp {
+            Ordering::Less
+        } else if from > cp {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    });
+    match found {
+        Ok(i) => {
+            let [major, minor] = RUNS[i].2;
+            (major as char, minor as char)
+        }
+        Err(_) => ('C', 'n'),
+    }
+}
+
+#[rustfmt::skip]
+const RUNS: &[(u32, u32, [u8; 2])] = &[
+"""
+
+WIDTH = 100
+
+
+def runs():
+    # Surrogates can't be a char, and Cn is what the binary search misses
+    start, cat = 0, None
+    for cp in range(0x110000 + 1):
+        c = unicodedata.category(chr(cp)) if cp < 0x110000 else None
+        if c != cat:
+            if cat not in (None, "Cn", "Cs"):
+                yield start, cp - 1, cat
+            start, cat = cp, c
+
+
+def main():
+    out = sys.stdout
+    out.write(HEADER % unicodedata.unidata_version.rsplit(".", 1)[0])
+    line = []
+    for a, b, c in runs():
+        entry = '(0x%X, 0x%X, *b"%s")' % (a, b, c)
+        if line and len("    " + ", ".join(line + [entry]) + ",") > WIDTH:
+            out.write("    " + ", ".join(line) + ",\n")
+            line = []
+        line.append(entry)
+    if line:
+        out.write("    " + ", ".join(line) + ",\n")
+    out.write("];\n")
+
+
+if __name__ == "__main__":
+    main()